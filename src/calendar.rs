@@ -0,0 +1,548 @@
+use super::*;
+
+pub(crate) const RSVP_INSTRUCTIONS: &str = "To respond to this invitation, include a line \
+containing only `RSVP: ACCEPTED`, `RSVP: DECLINED` or `RSVP: TENTATIVE` in your reply.";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Rsvp {
+  Accepted,
+  Declined,
+  Tentative,
+}
+
+impl Rsvp {
+  pub(crate) fn extract(response: &str) -> (String, Option<Self>) {
+    let mut rsvp = None;
+
+    let lines = response
+      .lines()
+      .filter(|line| {
+        let Some(value) = line.trim().strip_prefix("RSVP:") else {
+          return true;
+        };
+
+        let parsed = match value.trim().to_ascii_uppercase().as_str() {
+          "ACCEPTED" | "ACCEPT" => Self::Accepted,
+          "DECLINED" | "DECLINE" => Self::Declined,
+          "TENTATIVE" => Self::Tentative,
+          _ => return true,
+        };
+
+        rsvp = Some(parsed);
+
+        false
+      })
+      .collect::<Vec<&str>>();
+
+    (lines.join("\n").trim().to_string(), rsvp)
+  }
+
+  fn partstat(self) -> &'static str {
+    match self {
+      Self::Accepted => "ACCEPTED",
+      Self::Declined => "DECLINED",
+      Self::Tentative => "TENTATIVE",
+    }
+  }
+}
+
+struct Property {
+  name: String,
+  params: Vec<(String, String)>,
+  value: String,
+}
+
+impl Property {
+  fn parse(line: &str) -> Option<Self> {
+    let mut quoted = false;
+    let mut colon = None;
+
+    for (i, c) in line.char_indices() {
+      match c {
+        '"' => quoted = !quoted,
+        ':' if !quoted => {
+          colon = Some(i);
+          break;
+        }
+        _ => {}
+      }
+    }
+
+    let colon = colon?;
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+
+    let mut parts = Vec::new();
+    let mut start = 0;
+    quoted = false;
+
+    for (i, c) in head.char_indices() {
+      match c {
+        '"' => quoted = !quoted,
+        ';' if !quoted => {
+          parts.push(&head[start..i]);
+          start = i + 1;
+        }
+        _ => {}
+      }
+    }
+
+    parts.push(&head[start..]);
+
+    let name = parts[0].to_ascii_uppercase();
+
+    let params = parts[1..]
+      .iter()
+      .filter_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        Some((
+          key.to_ascii_uppercase(),
+          value.trim_matches('"').to_string(),
+        ))
+      })
+      .collect();
+
+    Some(Self {
+      name,
+      params,
+      value: value.to_string(),
+    })
+  }
+
+  fn param(&self, name: &str) -> Option<&str> {
+    self
+      .params
+      .iter()
+      .find(|(key, _)| key == name)
+      .map(|(_, value)| value.as_str())
+  }
+
+  fn text(&self) -> String {
+    let mut text = String::new();
+    let mut chars = self.value.chars();
+
+    while let Some(c) = chars.next() {
+      if c != '\\' {
+        text.push(c);
+        continue;
+      }
+
+      match chars.next() {
+        Some('n' | 'N') => text.push('\n'),
+        Some(c) => text.push(c),
+        None => {}
+      }
+    }
+
+    text
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Person {
+  pub(crate) name: Option<String>,
+  pub(crate) address: String,
+}
+
+impl Person {
+  fn from_property(property: &Property) -> Self {
+    let value = property.value.trim();
+
+    Self {
+      name: property.param("CN").map(str::to_string),
+      address: value
+        .strip_prefix("mailto:")
+        .or_else(|| value.strip_prefix("MAILTO:"))
+        .unwrap_or(value)
+        .to_string(),
+    }
+  }
+}
+
+impl Display for Person {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    match &self.name {
+      Some(name) => write!(f, "{name} <{}>", self.address),
+      None => write!(f, "{}", self.address),
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Time {
+  value: String,
+  tzid: Option<String>,
+}
+
+impl Time {
+  fn from_property(property: &Property) -> Self {
+    Self {
+      value: property.value.trim().to_string(),
+      tzid: property.param("TZID").map(str::to_string),
+    }
+  }
+
+  fn line(&self, name: &str) -> String {
+    match (&self.tzid, self.value.len()) {
+      (Some(tzid), _) => format!("{name};TZID={tzid}:{}", self.value),
+      (None, 8) => format!("{name};VALUE=DATE:{}", self.value),
+      (None, _) => format!("{name}:{}", self.value),
+    }
+  }
+}
+
+impl Display for Time {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    if let Ok(date) = jiff::civil::Date::strptime("%Y%m%d", &self.value) {
+      return write!(f, "{date} (all day)");
+    }
+
+    let (value, utc) = match self.value.strip_suffix('Z') {
+      Some(value) => (value, true),
+      None => (self.value.as_str(), false),
+    };
+
+    let Ok(datetime) = jiff::civil::DateTime::strptime("%Y%m%dT%H%M%S", value) else {
+      return write!(f, "{}", self.value);
+    };
+
+    let local = datetime.strftime("%Y-%m-%d %H:%M");
+
+    if utc {
+      return write!(f, "{local} UTC");
+    }
+
+    match &self.tzid {
+      Some(tzid) => match jiff::tz::TimeZone::get(tzid)
+        .ok()
+        .and_then(|tz| datetime.to_zoned(tz).ok())
+      {
+        Some(zoned) => write!(f, "{local} {} ({tzid})", zoned.strftime("%Z")),
+        None => write!(f, "{local} ({tzid})"),
+      },
+      None => write!(f, "{local}"),
+    }
+  }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Invite {
+  pub(crate) method: Option<String>,
+  pub(crate) uid: Option<String>,
+  pub(crate) sequence: Option<String>,
+  pub(crate) recurrence_id: Option<Time>,
+  pub(crate) summary: Option<String>,
+  pub(crate) organizer: Option<Person>,
+  pub(crate) start: Option<Time>,
+  pub(crate) end: Option<Time>,
+  pub(crate) attendees: Vec<Person>,
+  pub(crate) location: Option<String>,
+}
+
+impl Invite {
+  pub(crate) fn parse(ics: &str) -> Vec<Self> {
+    let mut unfolded = Vec::<String>::new();
+
+    for line in ics.lines() {
+      match line.strip_prefix([' ', '\t']) {
+        Some(continuation) if !unfolded.is_empty() => {
+          unfolded.last_mut().unwrap().push_str(continuation);
+        }
+        _ => unfolded.push(line.to_string()),
+      }
+    }
+
+    let mut method = None;
+    let mut invites = Vec::new();
+    let mut current = None::<Self>;
+    let mut depth = 0;
+
+    for property in unfolded.iter().filter_map(|line| Property::parse(line)) {
+      let value = property.value.trim().to_ascii_uppercase();
+
+      match (property.name.as_str(), current.as_mut()) {
+        ("BEGIN", None) if value == "VEVENT" => {
+          current = Some(Self {
+            method: method.clone(),
+            ..Self::default()
+          });
+        }
+        ("BEGIN", Some(_)) => depth += 1,
+        ("END", Some(_)) if depth > 0 => depth -= 1,
+        ("END", Some(_)) if value == "VEVENT" => invites.extend(current.take()),
+        ("METHOD", None) => method = Some(value),
+        (_, Some(_)) if depth > 0 => {}
+        ("UID", Some(invite)) => invite.uid = Some(property.text()),
+        ("SEQUENCE", Some(invite)) => invite.sequence = Some(property.value.trim().into()),
+        ("RECURRENCE-ID", Some(invite)) => {
+          invite.recurrence_id = Some(Time::from_property(&property));
+        }
+        ("SUMMARY", Some(invite)) => invite.summary = Some(property.text()),
+        ("LOCATION", Some(invite)) => invite.location = Some(property.text()),
+        ("ORGANIZER", Some(invite)) => invite.organizer = Some(Person::from_property(&property)),
+        ("ATTENDEE", Some(invite)) => invite.attendees.push(Person::from_property(&property)),
+        ("DTSTART", Some(invite)) => invite.start = Some(Time::from_property(&property)),
+        ("DTEND", Some(invite)) => invite.end = Some(Time::from_property(&property)),
+        _ => {}
+      }
+    }
+
+    invites
+  }
+
+  pub(crate) fn reply(&self, attendee: &str, rsvp: Rsvp) -> String {
+    let mut lines = vec![
+      "BEGIN:VCALENDAR".to_string(),
+      "PRODID:-//tulip.farm//lab//EN".into(),
+      "VERSION:2.0".into(),
+      "METHOD:REPLY".into(),
+      "BEGIN:VEVENT".into(),
+    ];
+
+    if let Some(uid) = &self.uid {
+      lines.push(format!("UID:{}", escape(uid)));
+    }
+
+    lines.push(format!(
+      "DTSTAMP:{}",
+      jiff::Timestamp::now().strftime("%Y%m%dT%H%M%SZ")
+    ));
+
+    if let Some(sequence) = &self.sequence {
+      lines.push(format!("SEQUENCE:{sequence}"));
+    }
+
+    if let Some(recurrence_id) = &self.recurrence_id {
+      lines.push(recurrence_id.line("RECURRENCE-ID"));
+    }
+
+    if let Some(organizer) = &self.organizer {
+      lines.push(match &organizer.name {
+        Some(name) => format!("ORGANIZER;CN=\"{name}\":mailto:{}", organizer.address),
+        None => format!("ORGANIZER:mailto:{}", organizer.address),
+      });
+    }
+
+    lines.push(format!(
+      "ATTENDEE;PARTSTAT={}:mailto:{attendee}",
+      rsvp.partstat()
+    ));
+
+    if let Some(summary) = &self.summary {
+      lines.push(format!("SUMMARY:{}", escape(summary)));
+    }
+
+    if let Some(start) = &self.start {
+      lines.push(start.line("DTSTART"));
+    }
+
+    if let Some(end) = &self.end {
+      lines.push(end.line("DTEND"));
+    }
+
+    lines.push("END:VEVENT".into());
+    lines.push("END:VCALENDAR".into());
+
+    let mut ics = String::new();
+
+    for line in lines {
+      ics.push_str(&fold(&line));
+      ics.push_str("\r\n");
+    }
+
+    ics
+  }
+}
+
+impl Display for Invite {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    match self.method.as_deref() {
+      Some("CANCEL") => writeln!(f, "Calendar cancellation:")?,
+      _ => writeln!(f, "Calendar invite:")?,
+    }
+
+    if let Some(summary) = &self.summary {
+      writeln!(f, "Title: {summary}")?;
+    }
+
+    if let Some(organizer) = &self.organizer {
+      writeln!(f, "Organizer: {organizer}")?;
+    }
+
+    if let Some(start) = &self.start {
+      writeln!(f, "Start: {start}")?;
+    }
+
+    if let Some(end) = &self.end {
+      writeln!(f, "End: {end}")?;
+    }
+
+    if let Some(location) = &self.location {
+      writeln!(f, "Location: {location}")?;
+    }
+
+    if !self.attendees.is_empty() {
+      writeln!(
+        f,
+        "Attendees: {}",
+        self
+          .attendees
+          .iter()
+          .map(ToString::to_string)
+          .collect::<Vec<String>>()
+          .join(", ")
+      )?;
+    }
+
+    Ok(())
+  }
+}
+
+fn escape(text: &str) -> String {
+  text
+    .replace('\\', "\\\\")
+    .replace(';', "\\;")
+    .replace(',', "\\,")
+    .replace('\n', "\\n")
+}
+
+fn fold(line: &str) -> String {
+  let mut folded = String::new();
+  let mut width = 0;
+
+  for c in line.chars() {
+    if width + c.len_utf8() > 75 {
+      folded.push_str("\r\n ");
+      width = 1;
+    }
+
+    folded.push(c);
+    width += c.len_utf8();
+  }
+
+  folded
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const INVITE: &str = "BEGIN:VCALENDAR\r
+METHOD:REQUEST\r
+BEGIN:VTIMEZONE\r
+TZID:America/Los_Angeles\r
+BEGIN:STANDARD\r
+DTSTART:19701101T020000\r
+END:STANDARD\r
+END:VTIMEZONE\r
+BEGIN:VEVENT\r
+UID:foo@bar.com\r
+SEQUENCE:2\r
+SUMMARY:Planning\\, round two\r
+ORGANIZER;CN=\"Foo Bar\":mailto:foo@bar.com\r
+ATTENDEE;CN=Root;PARTSTAT=NEEDS-ACTION:mailto:root@tulip.farm\r
+ATTENDEE:mailto:baz@bar.com\r
+DTSTART;TZID=America/Los_Angeles:20261020T150000\r
+DTEND;TZID=America/Los_Angeles:20261020T160000\r
+LOCATION:Room 1\r
+DESCRIPTION:a long description that is folded\r
+  across two lines\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+  #[test]
+  fn parse() {
+    let invites = Invite::parse(INVITE);
+
+    assert_eq!(invites.len(), 1);
+
+    let invite = &invites[0];
+
+    assert_eq!(invite.method.as_deref(), Some("REQUEST"));
+    assert_eq!(invite.uid.as_deref(), Some("foo@bar.com"));
+    assert_eq!(invite.sequence.as_deref(), Some("2"));
+    assert_eq!(invite.summary.as_deref(), Some("Planning, round two"));
+    assert_eq!(
+      invite.organizer,
+      Some(Person {
+        name: Some("Foo Bar".into()),
+        address: "foo@bar.com".into(),
+      }),
+    );
+    assert_eq!(invite.attendees.len(), 2);
+    assert_eq!(invite.location.as_deref(), Some("Room 1"));
+  }
+
+  #[test]
+  fn summary() {
+    assert_eq!(
+      Invite::parse(INVITE)[0].to_string(),
+      "Calendar invite:\n\
+       Title: Planning, round two\n\
+       Organizer: Foo Bar <foo@bar.com>\n\
+       Start: 2026-10-20 15:00 PDT (America/Los_Angeles)\n\
+       End: 2026-10-20 16:00 PDT (America/Los_Angeles)\n\
+       Location: Room 1\n\
+       Attendees: Root <root@tulip.farm>, baz@bar.com\n",
+    );
+  }
+
+  #[test]
+  fn time() {
+    #[track_caller]
+    fn case(value: &str, tzid: Option<&str>, expected: &str) {
+      let time = Time {
+        value: value.into(),
+        tzid: tzid.map(Into::into),
+      };
+      assert_eq!(time.to_string(), expected);
+    }
+
+    case("20261020", None, "2026-10-20 (all day)");
+    case("20261020T150000Z", None, "2026-10-20 15:00 UTC");
+    case("20261020T150000", None, "2026-10-20 15:00");
+    case(
+      "20261020T150000",
+      Some("Pacific Standard Time"),
+      "2026-10-20 15:00 (Pacific Standard Time)",
+    );
+  }
+
+  #[test]
+  fn reply() {
+    let ics = Invite::parse(INVITE)[0].reply("root@tulip.farm", Rsvp::Tentative);
+
+    assert!(ics.contains("METHOD:REPLY\r\n"));
+    assert!(ics.contains("UID:foo@bar.com\r\n"));
+    assert!(ics.contains("SEQUENCE:2\r\n"));
+    assert!(ics.contains("ORGANIZER;CN=\"Foo Bar\":mailto:foo@bar.com\r\n"));
+    assert!(ics.contains("ATTENDEE;PARTSTAT=TENTATIVE:mailto:root@tulip.farm\r\n"));
+    assert!(ics.contains("DTSTART;TZID=America/Los_Angeles:20261020T150000\r\n"));
+    assert!(ics.contains("SUMMARY:Planning\\, round two\r\n"));
+
+    let reparsed = Invite::parse(&ics);
+    assert_eq!(reparsed[0].method.as_deref(), Some("REPLY"));
+    assert_eq!(reparsed[0].summary.as_deref(), Some("Planning, round two"));
+  }
+
+  #[test]
+  fn fold() {
+    let line = "a".repeat(100);
+    let folded = super::fold(&line);
+    assert_eq!(folded, format!("{}\r\n {}", "a".repeat(75), "a".repeat(25)));
+  }
+
+  #[test]
+  fn extract_rsvp() {
+    #[track_caller]
+    fn case(response: &str, expected: &str, rsvp: Option<Rsvp>) {
+      assert_eq!(Rsvp::extract(response), (expected.to_string(), rsvp));
+    }
+
+    case("foo", "foo", None);
+    case("foo\nRSVP: ACCEPTED\n", "foo", Some(Rsvp::Accepted));
+    case("RSVP: declined\n\nfoo", "foo", Some(Rsvp::Declined));
+    case("foo\nRSVP: tentative", "foo", Some(Rsvp::Tentative));
+    case("foo\nRSVP: maybe", "foo\nRSVP: maybe", None);
+  }
+}
@@ -0,0 +1,660 @@
+use super::*;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+  pub(crate) chat: Chat,
+  pub(crate) irc: Irc,
+  pub(crate) notify: Notify,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Chat {
+  pub(crate) channels: Vec<Channel>,
+  pub(crate) fetch: Fetch,
+  pub(crate) paste: Option<Paste>,
+  pub(crate) progress: Progress,
+  pub(crate) quiet_window: u64,
+  pub(crate) users: Vec<User>,
+}
+
+impl Default for Chat {
+  fn default() -> Self {
+    Self {
+      channels: Vec::new(),
+      fetch: Fetch::default(),
+      paste: None,
+      progress: Progress::default(),
+      quiet_window: 1500,
+      users: vec![User {
+        account: "rodarmor".into(),
+        system_prompt: None,
+        fast: true,
+        format: Format::Plain,
+        namespace: None,
+      }],
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Channel {
+  pub(crate) name: String,
+  pub(crate) allow: Vec<String>,
+  #[serde(default = "Channel::default_backlog")]
+  pub(crate) backlog: usize,
+}
+
+impl Channel {
+  fn default_backlog() -> usize {
+    20
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Irc {
+  pub(crate) server: String,
+  pub(crate) port: u16,
+  pub(crate) password_file: PathBuf,
+  pub(crate) external: BTreeMap<String, External>,
+}
+
+impl Default for Irc {
+  fn default() -> Self {
+    Self {
+      server: "tulip.farm".into(),
+      port: 6697,
+      password_file: "/root/secrets/ergo-password".into(),
+      external: BTreeMap::new(),
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct External {
+  pub(crate) cert: PathBuf,
+  pub(crate) key: PathBuf,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Fetch {
+  pub(crate) allow: Vec<IpAddr>,
+  pub(crate) enabled: bool,
+  pub(crate) max_bytes: usize,
+  pub(crate) max_urls: usize,
+  pub(crate) timeout: u64,
+  pub(crate) types: Vec<String>,
+}
+
+impl Default for Fetch {
+  fn default() -> Self {
+    Self {
+      allow: Vec::new(),
+      enabled: true,
+      max_bytes: 10_000_000,
+      max_urls: 3,
+      timeout: 30,
+      types: vec!["image/".into(), "application/pdf".into(), "text/".into()],
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Format {
+  Irc,
+  #[default]
+  Plain,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Notify {
+  pub(crate) target: String,
+  pub(crate) routing: Routing,
+  pub(crate) fallback: Vec<Route>,
+  pub(crate) pushover: Pushover,
+  pub(crate) webhook: Option<Webhook>,
+  pub(crate) ntfy: Option<Ntfy>,
+  pub(crate) email: Option<Email>,
+  pub(crate) relay: Option<PathBuf>,
+  pub(crate) throttle: Throttle,
+}
+
+impl Default for Notify {
+  fn default() -> Self {
+    Self {
+      target: "rodarmor".into(),
+      routing: Routing::default(),
+      fallback: Vec::new(),
+      pushover: Pushover::default(),
+      webhook: None,
+      ntfy: None,
+      email: None,
+      relay: Some("/run/lab-relay.sock".into()),
+      throttle: Throttle::default(),
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Route {
+  Irc,
+  Pushover,
+  Webhook,
+  Ntfy,
+  Email,
+  Journal,
+}
+
+impl Display for Route {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    match self {
+      Self::Irc => write!(f, "irc"),
+      Self::Pushover => write!(f, "pushover"),
+      Self::Webhook => write!(f, "webhook"),
+      Self::Ntfy => write!(f, "ntfy"),
+      Self::Email => write!(f, "email"),
+      Self::Journal => write!(f, "journal"),
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Pushover {
+  pub(crate) api: String,
+  pub(crate) token_file: PathBuf,
+  pub(crate) user_file: PathBuf,
+}
+
+impl Default for Pushover {
+  fn default() -> Self {
+    Self {
+      api: "https://api.pushover.net/1".into(),
+      token_file: "/root/secrets/pushover-token".into(),
+      user_file: "/root/secrets/pushover-user".into(),
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Webhook {
+  pub(crate) url: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Ntfy {
+  pub(crate) url: String,
+  pub(crate) topic: String,
+  #[serde(default)]
+  pub(crate) token_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Email {
+  pub(crate) to: String,
+  #[serde(default = "Email::default_sendmail")]
+  pub(crate) sendmail: PathBuf,
+}
+
+impl Email {
+  fn default_sendmail() -> PathBuf {
+    "/run/wrappers/bin/sendmail".into()
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Routing {
+  pub(crate) active: Vec<Route>,
+  pub(crate) away: Vec<Route>,
+  pub(crate) offline: Vec<Route>,
+  pub(crate) unknown: Vec<Route>,
+}
+
+impl Default for Routing {
+  fn default() -> Self {
+    Self {
+      active: vec![Route::Irc],
+      away: vec![Route::Pushover],
+      offline: vec![Route::Pushover],
+      unknown: vec![Route::Irc, Route::Pushover],
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Throttle {
+  pub(crate) dedup_window: u64,
+  pub(crate) rate_limit: Option<u32>,
+  pub(crate) rate_window: u64,
+  pub(crate) quiet_hours: Option<QuietHours>,
+}
+
+impl Default for Throttle {
+  fn default() -> Self {
+    Self {
+      dedup_window: 600,
+      rate_limit: Some(10),
+      rate_window: 3600,
+      quiet_hours: None,
+    }
+  }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct QuietHours {
+  pub(crate) start: String,
+  pub(crate) end: String,
+  #[serde(default = "QuietHours::default_time_zone")]
+  pub(crate) time_zone: String,
+  #[serde(default = "QuietHours::default_bypass")]
+  pub(crate) bypass: bool,
+}
+
+impl QuietHours {
+  pub(crate) fn window(
+    &self,
+  ) -> Result<(jiff::civil::Time, jiff::civil::Time, jiff::tz::TimeZone)> {
+    let invalid = |message: String| Error::QuietHours { message };
+
+    let time_zone = jiff::tz::TimeZone::get(&self.time_zone)
+      .map_err(|err| invalid(format!("unknown time zone `{}`: {err}", self.time_zone)))?;
+
+    let parse = |time: &str| {
+      time
+        .parse::<jiff::civil::Time>()
+        .map_err(|err| invalid(format!("invalid time `{time}`: {err}")))
+    };
+
+    Ok((parse(&self.start)?, parse(&self.end)?, time_zone))
+  }
+
+  fn default_time_zone() -> String {
+    "UTC".into()
+  }
+
+  fn default_bypass() -> bool {
+    true
+  }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Paste {
+  pub(crate) listen: SocketAddr,
+  pub(crate) url: String,
+  #[serde(default = "Paste::default_max_lines")]
+  pub(crate) max_lines: usize,
+  #[serde(default = "Paste::default_max_bytes")]
+  pub(crate) max_bytes: usize,
+  #[serde(default = "Paste::default_ttl")]
+  pub(crate) ttl: u64,
+}
+
+impl Paste {
+  fn default_max_lines() -> usize {
+    5
+  }
+
+  fn default_max_bytes() -> usize {
+    1000
+  }
+
+  fn default_ttl() -> u64 {
+    30 * 24 * 60 * 60
+  }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Progress {
+  pub(crate) typing: bool,
+  pub(crate) working_notice_delay: Option<u64>,
+  pub(crate) tool_use: bool,
+}
+
+impl Default for Progress {
+  fn default() -> Self {
+    Self {
+      typing: true,
+      working_notice_delay: Some(30),
+      tool_use: false,
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct User {
+  pub(crate) account: String,
+  #[serde(default)]
+  pub(crate) system_prompt: Option<String>,
+  #[serde(default = "User::default_fast")]
+  pub(crate) fast: bool,
+  #[serde(default)]
+  pub(crate) format: Format,
+  #[serde(default)]
+  namespace: Option<String>,
+}
+
+impl User {
+  fn default_fast() -> bool {
+    true
+  }
+
+  pub(crate) fn namespace(&self) -> &str {
+    self.namespace.as_deref().unwrap_or(&self.account)
+  }
+}
+
+impl Config {
+  pub(crate) fn path() -> PathBuf {
+    dirs::home_dir().unwrap().join(".lab.json")
+  }
+
+  pub(crate) fn load(path: &Path) -> Result<Self> {
+    match fs::read_to_string(path) {
+      Ok(json) => {
+        let config = serde_json::from_str::<Self>(&json).context(error::ConfigParse { path })?;
+
+        if let Some(quiet_hours) = &config.notify.throttle.quiet_hours {
+          quiet_hours.window()?;
+        }
+
+        Ok(config)
+      }
+      Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+      Err(err) => Err(err).context(error::FilesystemIo { path }),
+    }
+  }
+
+  pub(crate) fn load_or_default(path: &Path) -> Self {
+    Self::load(path).unwrap_or_else(|err| {
+      ::log::error!("{err}, using default config");
+      Self::default()
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn missing() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let config = Config::load(&dir.path().join("foo.json")).unwrap();
+    assert!(config.chat.channels.is_empty());
+    assert_eq!(config.chat.quiet_window, 1500);
+  }
+
+  #[test]
+  fn channels() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("foo.json");
+
+    fs::write(
+      &path,
+      r##"{"chat":{"channels":[{"name":"#foo","allow":["bar"]}]}}"##,
+    )
+    .unwrap();
+
+    let config = Config::load(&path).unwrap();
+    assert_eq!(config.chat.channels.len(), 1);
+    assert_eq!(config.chat.channels[0].name, "#foo");
+    assert_eq!(config.chat.channels[0].allow, ["bar"]);
+    assert_eq!(config.chat.channels[0].backlog, 20);
+  }
+
+  #[test]
+  fn users() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("foo.json");
+
+    assert_eq!(
+      Config::load(&path).unwrap().chat.users[0].account,
+      "rodarmor"
+    );
+
+    fs::write(
+      &path,
+      r#"{"chat":{"users":[{"account":"foo","fast":false},{"account":"bar","namespace":"baz","format":"irc"}]}}"#,
+    )
+    .unwrap();
+
+    let config = Config::load(&path).unwrap();
+    assert_eq!(config.chat.users.len(), 2);
+    assert!(!config.chat.users[0].fast);
+    assert_eq!(config.chat.users[0].namespace(), "foo");
+    assert!(config.chat.users[1].fast);
+    assert_eq!(config.chat.users[1].namespace(), "baz");
+    assert_eq!(config.chat.users[0].format, Format::Plain);
+    assert_eq!(config.chat.users[1].format, Format::Irc);
+  }
+
+  #[test]
+  fn paste() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("foo.json");
+
+    assert!(Config::load(&path).unwrap().chat.paste.is_none());
+
+    fs::write(
+      &path,
+      r#"{"chat":{"paste":{"listen":"127.0.0.1:8000","url":"https://foo"}}}"#,
+    )
+    .unwrap();
+
+    let paste = Config::load(&path).unwrap().chat.paste.unwrap();
+    assert_eq!(paste.listen, "127.0.0.1:8000".parse().unwrap());
+    assert_eq!(paste.url, "https://foo");
+    assert_eq!(paste.max_lines, 5);
+    assert_eq!(paste.max_bytes, 1000);
+    assert_eq!(paste.ttl, 2_592_000);
+  }
+
+  #[test]
+  fn fetch() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("foo.json");
+
+    let fetch = Config::load(&path).unwrap().chat.fetch;
+    assert!(fetch.enabled);
+    assert_eq!(fetch.max_bytes, 10_000_000);
+    assert_eq!(fetch.max_urls, 3);
+    assert_eq!(fetch.timeout, 30);
+    assert_eq!(fetch.types, ["image/", "application/pdf", "text/"]);
+    assert!(fetch.allow.is_empty());
+
+    fs::write(
+      &path,
+      r#"{"chat":{"fetch":{"max_urls":1,"types":["image/png"],"allow":["127.0.0.1","::1"]}}}"#,
+    )
+    .unwrap();
+
+    let fetch = Config::load(&path).unwrap().chat.fetch;
+    assert!(fetch.enabled);
+    assert_eq!(fetch.max_urls, 1);
+    assert_eq!(fetch.types, ["image/png"]);
+    assert_eq!(
+      fetch.allow,
+      [
+        IpAddr::from([127, 0, 0, 1]),
+        IpAddr::from(std::net::Ipv6Addr::LOCALHOST)
+      ]
+    );
+  }
+
+  #[test]
+  fn progress() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("foo.json");
+
+    let progress = Config::load(&path).unwrap().chat.progress;
+    assert!(progress.typing);
+    assert_eq!(progress.working_notice_delay, Some(30));
+    assert!(!progress.tool_use);
+
+    fs::write(
+      &path,
+      r#"{"chat":{"progress":{"working_notice_delay":null,"tool_use":true}}}"#,
+    )
+    .unwrap();
+
+    let progress = Config::load(&path).unwrap().chat.progress;
+    assert!(progress.typing);
+    assert_eq!(progress.working_notice_delay, None);
+    assert!(progress.tool_use);
+  }
+
+  #[test]
+  fn irc() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("foo.json");
+
+    let irc = Config::load(&path).unwrap().irc;
+    assert_eq!(irc.server, "tulip.farm");
+    assert_eq!(irc.port, 6697);
+    assert!(irc.external.is_empty());
+
+    fs::write(
+      &path,
+      r#"{"irc":{"port":6698,"external":{"root":{"cert":"/foo.pem","key":"/bar.pem"}}}}"#,
+    )
+    .unwrap();
+
+    let irc = Config::load(&path).unwrap().irc;
+    assert_eq!(irc.server, "tulip.farm");
+    assert_eq!(irc.port, 6698);
+    let external = &irc.external["root"];
+    assert_eq!(external.cert, Path::new("/foo.pem"));
+    assert_eq!(external.key, Path::new("/bar.pem"));
+    assert!(!irc.external.contains_key("system"));
+  }
+
+  #[test]
+  fn notify() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("foo.json");
+
+    let notify = Config::load(&path).unwrap().notify;
+    assert_eq!(notify.target, "rodarmor");
+    assert_eq!(notify.routing.active, [Route::Irc]);
+    assert_eq!(notify.routing.away, [Route::Pushover]);
+    assert_eq!(notify.routing.offline, [Route::Pushover]);
+    assert_eq!(notify.routing.unknown, [Route::Irc, Route::Pushover]);
+    assert_eq!(notify.relay.unwrap(), Path::new("/run/lab-relay.sock"));
+
+    fs::write(
+      &path,
+      r#"{"notify":{"target":"foo","routing":{"away":["irc","pushover"]},"relay":null}}"#,
+    )
+    .unwrap();
+
+    let notify = Config::load(&path).unwrap().notify;
+    assert_eq!(notify.target, "foo");
+    assert!(notify.fallback.is_empty());
+    assert!(notify.webhook.is_none());
+    assert!(notify.relay.is_none());
+    assert_eq!(notify.routing.away, [Route::Irc, Route::Pushover]);
+    assert_eq!(notify.routing.active, [Route::Irc]);
+  }
+
+  #[test]
+  fn notify_backends() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("foo.json");
+
+    fs::write(
+      &path,
+      r#"{"notify":{
+        "routing":{"active":["irc","webhook"],"offline":["ntfy"]},
+        "fallback":["email","journal"],
+        "pushover":{"api":"http://localhost"},
+        "webhook":{"url":"http://localhost/hook"},
+        "ntfy":{"url":"http://localhost","topic":"foo"},
+        "email":{"to":"foo@bar.com"}
+      }}"#,
+    )
+    .unwrap();
+
+    let notify = Config::load(&path).unwrap().notify;
+    assert_eq!(notify.routing.active, [Route::Irc, Route::Webhook]);
+    assert_eq!(notify.routing.offline, [Route::Ntfy]);
+    assert_eq!(notify.fallback, [Route::Email, Route::Journal]);
+    assert_eq!(notify.pushover.api, "http://localhost");
+    assert_eq!(
+      notify.pushover.token_file,
+      Path::new("/root/secrets/pushover-token")
+    );
+    assert_eq!(notify.webhook.unwrap().url, "http://localhost/hook");
+    let ntfy = notify.ntfy.unwrap();
+    assert_eq!(ntfy.topic, "foo");
+    assert_eq!(ntfy.token_file, None);
+    let email = notify.email.unwrap();
+    assert_eq!(email.to, "foo@bar.com");
+    assert_eq!(email.sendmail, Path::new("/run/wrappers/bin/sendmail"));
+  }
+
+  #[test]
+  fn notify_throttle() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("foo.json");
+
+    let throttle = Config::load(&path).unwrap().notify.throttle;
+    assert_eq!(throttle.dedup_window, 600);
+    assert_eq!(throttle.rate_limit, Some(10));
+    assert_eq!(throttle.rate_window, 3600);
+    assert!(throttle.quiet_hours.is_none());
+
+    fs::write(
+      &path,
+      r#"{"notify":{"throttle":{
+        "rate_limit":null,
+        "quiet_hours":{"start":"22:00","end":"08:00","time_zone":"America/New_York"}
+      }}}"#,
+    )
+    .unwrap();
+
+    let throttle = Config::load(&path).unwrap().notify.throttle;
+    assert_eq!(throttle.dedup_window, 600);
+    assert_eq!(throttle.rate_limit, None);
+    let quiet_hours = throttle.quiet_hours.unwrap();
+    assert_eq!(quiet_hours.start, "22:00");
+    assert_eq!(quiet_hours.end, "08:00");
+    assert_eq!(quiet_hours.time_zone, "America/New_York");
+    assert!(quiet_hours.bypass);
+
+    fs::write(
+      &path,
+      r#"{"notify":{"throttle":{"quiet_hours":{"start":"22:00","end":"8am"}}}}"#,
+    )
+    .unwrap();
+
+    assert!(matches!(
+      Config::load(&path).unwrap_err(),
+      Error::QuietHours { .. }
+    ));
+  }
+
+  #[test]
+  fn unknown_field() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("foo.json");
+    fs::write(&path, r#"{"foo":1}"#).unwrap();
+    assert!(Config::load(&path).is_err());
+  }
+}
@@ -0,0 +1,122 @@
+use {super::*, std::ops::Deref};
+
+type Shared = Arc<Mutex<Option<Arc<redb::Database>>>>;
+
+#[derive(Clone)]
+pub(crate) struct Database {
+  path: PathBuf,
+  shared: Shared,
+}
+
+pub(crate) struct Handle {
+  database: Option<Arc<redb::Database>>,
+  shared: Shared,
+}
+
+impl Database {
+  pub(crate) fn new(path: PathBuf) -> Self {
+    Self {
+      path,
+      shared: Shared::default(),
+    }
+  }
+
+  pub(crate) fn path(&self) -> &Path {
+    &self.path
+  }
+
+  pub(crate) fn open(&self) -> Result<Handle> {
+    let mut shared = self.shared.lock().unwrap();
+
+    let database = match &*shared {
+      Some(database) => database.clone(),
+      None => {
+        let database = Arc::new(redb::Database::create(&self.path).context(
+          error::DatabaseOpen {
+            path: self.path.clone(),
+          },
+        )?);
+        *shared = Some(database.clone());
+        database
+      }
+    };
+
+    Ok(Handle {
+      database: Some(database),
+      shared: self.shared.clone(),
+    })
+  }
+}
+
+impl Deref for Handle {
+  type Target = redb::Database;
+
+  fn deref(&self) -> &redb::Database {
+    self.database.as_ref().unwrap()
+  }
+}
+
+impl Drop for Handle {
+  fn drop(&mut self) {
+    let mut shared = self.shared.lock().unwrap();
+
+    self.database.take();
+
+    if shared
+      .as_ref()
+      .is_some_and(|database| Arc::strong_count(database) == 1)
+    {
+      *shared = None;
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn shared() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("foo.redb");
+
+    let database = Database::new(path.clone());
+
+    let a = database.open().unwrap();
+    let b = database.clone().open().unwrap();
+
+    assert!(redb::Database::create(&path).is_err());
+
+    drop(a);
+
+    assert!(redb::Database::create(&path).is_err());
+
+    b.begin_read().unwrap();
+
+    drop(b);
+
+    redb::Database::create(&path).unwrap();
+  }
+
+  #[test]
+  fn concurrent() {
+    let dir = tempfile::TempDir::new().unwrap();
+
+    let database = Database::new(dir.path().join("foo.redb"));
+
+    let threads = (0..8)
+      .map(|_| {
+        let database = database.clone();
+        std::thread::spawn(move || {
+          for _ in 0..100 {
+            database.open().unwrap().begin_read().unwrap();
+          }
+        })
+      })
+      .collect::<Vec<_>>();
+
+    for thread in threads {
+      thread.join().unwrap();
+    }
+  }
+}
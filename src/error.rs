@@ -5,6 +5,16 @@ use super::*;
 pub(crate) enum Error {
   #[snafu(display("I/O error at `{}`", path.display()))]
   FilesystemIo { path: PathBuf, source: io::Error },
+  #[snafu(display("failed to parse config file at `{}`", path.display()))]
+  ConfigParse {
+    path: PathBuf,
+    source: serde_json::Error,
+  },
+  #[snafu(display("invalid date `{date}`"))]
+  Date {
+    date: jiff::civil::Date,
+    source: jiff::Error,
+  },
   #[snafu(display("failed to parse message"))]
   MailParse { source: mailparse::MailParseError },
   #[snafu(display("message has no Message-ID header"))]
@@ -43,16 +53,24 @@ pub(crate) enum Error {
   },
   #[snafu(display("agent output is not valid UTF-8"))]
   AgentOutput { source: std::string::FromUtf8Error },
+  #[snafu(display("failed to fetch `{url}`"))]
+  Fetch { url: String, source: reqwest::Error },
+  #[snafu(display("refusing to fetch `{url}`: {reason}"))]
+  FetchRejected { url: String, reason: String },
+  #[snafu(display("progress directory `{}` is not private", path.display()))]
+  ProgressDir { path: PathBuf },
   #[snafu(display("failed to create session directory at `{}`", path.display()))]
   SessionDir { path: PathBuf, source: io::Error },
+  #[snafu(display("paste server error"))]
+  PasteServer { source: io::Error },
   #[snafu(display("IRC error"))]
-  Irc { source: irc::error::Error },
+  Irc { source: ::irc::error::Error },
+  #[snafu(display("IRC connection closed"))]
+  IrcClosed,
   #[snafu(display("failed to read password file `{}`", path.display()))]
   PasswordFile { path: PathBuf, source: io::Error },
   #[snafu(display("failed to create tokio runtime"))]
   TokioRuntime { source: io::Error },
-  #[snafu(display("spawn_blocking task panicked"))]
-  TokioJoin { source: tokio::task::JoinError },
   #[snafu(display("IRC protocol error: {message}"))]
   IrcProtocol { message: String },
   #[snafu(display("failed to parse JSON"))]
@@ -61,6 +79,12 @@ pub(crate) enum Error {
   SocketSend { source: io::Error },
   #[snafu(display("failed to receive from notebook socket"))]
   SocketRecv { source: io::Error },
+  #[snafu(display("I/O error on relay socket `{}`", path.display()))]
+  RelayIo { path: PathBuf, source: io::Error },
+  #[snafu(display("relay error: {message}"))]
+  Relay { message: String },
+  #[snafu(display("relay does not accept requests for `{target}`"))]
+  RelayTarget { target: String },
   #[snafu(display("failed to sync notebook repo"))]
   GitSync { source: io::Error },
   #[snafu(display("failed to read commit info"))]
@@ -70,6 +94,17 @@ pub(crate) enum Error {
   JournalSend { source: io::Error },
   #[snafu(display("failed to send Pushover notification"))]
   PushoverSend { source: reqwest::Error },
+  #[snafu(display("failed to send {route} notification"))]
+  NotificationSend {
+    route: config::Route,
+    source: reqwest::Error,
+  },
+  #[snafu(display("{route} notifications are not configured"))]
+  NotifierUnconfigured { route: config::Route },
+  #[snafu(display("Pushover receipt error: {message}"))]
+  PushoverReceipt { message: String },
+  #[snafu(display("invalid quiet hours: {message}"))]
+  QuietHours { message: String },
   #[snafu(display("session `{name}` not found"))]
   SessionNotFound { name: String },
 }
@@ -0,0 +1,1111 @@
+use {
+  super::*,
+  ::irc::{
+    client::{Client, ClientStream, Sender, data::Config as ClientConfig, prelude::Capability},
+    proto::{CapSubCommand, Command, Message, Prefix, Response, message::Tag},
+  },
+  base64::Engine,
+  std::sync::atomic::{self, AtomicBool},
+  tokio::sync::{mpsc, watch},
+  tokio_stream::StreamExt,
+};
+
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(300);
+const FLOOD_BURST: u32 = 5;
+const FLOOD_INTERVAL: Duration = Duration::from_millis(500);
+const HOSTLEN: usize = 63;
+const LINE_MAX: usize = 512;
+const USERLEN: usize = 10;
+
+pub(crate) struct Connection {
+  pub(crate) capabilities: Vec<String>,
+  pub(crate) client: Client,
+  pub(crate) stream: ClientStream,
+}
+
+impl Connection {
+  pub(crate) async fn open(
+    config: &config::Irc,
+    nick: &str,
+    capabilities: Vec<Capability>,
+    channels: Vec<String>,
+  ) -> Result<Self> {
+    let mut client_config = ClientConfig {
+      server: Some(config.server.clone()),
+      port: Some(config.port),
+      nickname: Some(nick.into()),
+      channels,
+      use_tls: Some(true),
+      ping_time: Some(30),
+      ping_timeout: Some(20),
+      ..ClientConfig::default()
+    };
+
+    let external = config.external.get(nick);
+
+    if let Some(external) = external {
+      client_config.client_cert_path = Some(external.cert.to_string_lossy().into_owned());
+      client_config.client_cert_pass = Some(fs::read_to_string(&external.key).context(
+        error::FilesystemIo {
+          path: &external.key,
+        },
+      )?);
+    }
+
+    let mut client = Client::from_config(client_config)
+      .await
+      .context(error::Irc)?;
+    let mut stream = client.stream().context(error::Irc)?;
+
+    let capabilities = Self::negotiate(&client, &mut stream, capabilities).await?;
+
+    match external {
+      Some(_) => Self::sasl_external(&client, &mut stream).await?,
+      None => {
+        let password = fs::read_to_string(&config.password_file)
+          .context(error::PasswordFile {
+            path: &config.password_file,
+          })?
+          .trim()
+          .to_string();
+
+        Self::sasl_plain(&client, &mut stream, nick, &password).await?;
+      }
+    }
+
+    client.identify().context(error::Irc)?;
+
+    Ok(Self {
+      capabilities,
+      client,
+      stream,
+    })
+  }
+
+  async fn negotiate(
+    client: &Client,
+    stream: &mut ClientStream,
+    capabilities: Vec<Capability>,
+  ) -> Result<Vec<String>> {
+    let (mut negotiation, command) = Negotiation::new(capabilities);
+
+    client.send(command).context(error::Irc)?;
+
+    while let Some(message) = stream.next().await.transpose().context(error::Irc)? {
+      match negotiation.observe(&message)? {
+        NegotiationStep::Pending => {}
+        NegotiationStep::Send(command) => client.send(command).context(error::Irc)?,
+        NegotiationStep::Done(capabilities) => return Ok(capabilities),
+      }
+    }
+
+    Err(Error::IrcProtocol {
+      message: "connection closed during capability negotiation".into(),
+    })
+  }
+
+  async fn sasl_plain(
+    client: &Client,
+    stream: &mut ClientStream,
+    nick: &str,
+    password: &str,
+  ) -> Result {
+    client.send_sasl_plain().context(error::Irc)?;
+
+    Self::await_continuation(stream).await?;
+
+    let credentials = format!("\0{nick}\0{password}");
+    let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+    client.send_sasl(&encoded).context(error::Irc)?;
+
+    Self::await_success(stream).await
+  }
+
+  async fn sasl_external(client: &Client, stream: &mut ClientStream) -> Result {
+    client.send_sasl_external().context(error::Irc)?;
+
+    Self::await_continuation(stream).await?;
+
+    client.send_sasl("+").context(error::Irc)?;
+
+    Self::await_success(stream).await
+  }
+
+  async fn await_continuation(stream: &mut ClientStream) -> Result {
+    while let Some(message) = stream.next().await.transpose().context(error::Irc)? {
+      match message.command {
+        Command::AUTHENTICATE(ref param) if param == "+" => return Ok(()),
+        Command::Response(Response::ERR_SASLFAIL, _) => {
+          return Err(Error::IrcProtocol {
+            message: "SASL authentication failed".into(),
+          });
+        }
+        _ => {}
+      }
+    }
+
+    Err(Error::IrcProtocol {
+      message: "connection closed during SASL authentication".into(),
+    })
+  }
+
+  async fn await_success(stream: &mut ClientStream) -> Result {
+    while let Some(message) = stream.next().await.transpose().context(error::Irc)? {
+      if let Command::Response(ref response, _) = message.command {
+        if *response == Response::RPL_SASLSUCCESS {
+          return Ok(());
+        }
+        if *response == Response::ERR_SASLFAIL {
+          return Err(Error::IrcProtocol {
+            message: "SASL authentication failed".into(),
+          });
+        }
+      }
+    }
+
+    Err(Error::IrcProtocol {
+      message: "connection closed during SASL authentication".into(),
+    })
+  }
+}
+
+struct Negotiation {
+  acknowledged: Vec<String>,
+  batch: bool,
+  offered: Vec<String>,
+  queue: VecDeque<String>,
+  requested: Vec<String>,
+  wanted: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum NegotiationStep {
+  Done(Vec<String>),
+  Pending,
+  Send(Command),
+}
+
+impl Negotiation {
+  fn new(capabilities: Vec<Capability>) -> (Self, Command) {
+    let mut wanted = vec![Capability::Sasl.as_ref().to_string()];
+    wanted.extend(
+      capabilities
+        .iter()
+        .map(|capability| capability.as_ref().to_string()),
+    );
+
+    (
+      Self {
+        acknowledged: Vec::new(),
+        batch: true,
+        offered: Vec::new(),
+        queue: VecDeque::new(),
+        requested: Vec::new(),
+        wanted,
+      },
+      Command::CAP(None, CapSubCommand::LS, Some("302".into()), None),
+    )
+  }
+
+  fn observe(&mut self, message: &Message) -> Result<NegotiationStep> {
+    let Command::CAP(_, subcommand, first, second) = &message.command else {
+      return Ok(NegotiationStep::Pending);
+    };
+
+    let capabilities = second
+      .as_deref()
+      .or(first.as_deref())
+      .unwrap_or_default()
+      .split_whitespace()
+      .map(|capability| {
+        capability
+          .split_once('=')
+          .map_or(capability, |(name, _)| name)
+          .to_string()
+      })
+      .collect::<Vec<String>>();
+
+    match subcommand {
+      CapSubCommand::LS => {
+        self.offered.extend(capabilities);
+
+        if second.is_some() && first.as_deref() == Some("*") {
+          return Ok(NegotiationStep::Pending);
+        }
+
+        self.requested = self
+          .wanted
+          .iter()
+          .filter(|capability| self.offered.contains(capability))
+          .cloned()
+          .collect();
+
+        for capability in &self.wanted {
+          if !self.offered.contains(capability) {
+            ::log::warn!("server does not offer capability {capability}");
+          }
+        }
+
+        if !self.requested.iter().any(|capability| capability == "sasl") {
+          return Err(Error::IrcProtocol {
+            message: "server does not support SASL".into(),
+          });
+        }
+
+        Ok(NegotiationStep::Send(Self::request(&self.requested)))
+      }
+      CapSubCommand::ACK => {
+        self.acknowledged.extend(capabilities);
+        self.next()
+      }
+      CapSubCommand::NAK => {
+        ::log::warn!("server rejected capabilities {}", capabilities.join(" "),);
+
+        if self.batch {
+          let remaining = self
+            .requested
+            .iter()
+            .filter(|capability| !capabilities.contains(capability))
+            .cloned()
+            .collect::<Vec<String>>();
+
+          if !remaining.is_empty() && remaining.len() < self.requested.len() {
+            self.requested = remaining;
+            return Ok(NegotiationStep::Send(Self::request(&self.requested)));
+          }
+
+          self.batch = false;
+          self.queue = self.requested.drain(..).collect();
+        }
+
+        self.next()
+      }
+      _ => Ok(NegotiationStep::Pending),
+    }
+  }
+
+  fn next(&mut self) -> Result<NegotiationStep> {
+    if let Some(capability) = self.queue.pop_front() {
+      return Ok(NegotiationStep::Send(Self::request(&[capability])));
+    }
+
+    if !self
+      .acknowledged
+      .iter()
+      .any(|capability| capability == "sasl")
+    {
+      return Err(Error::IrcProtocol {
+        message: "server does not support SASL".into(),
+      });
+    }
+
+    Ok(NegotiationStep::Done(self.acknowledged.clone()))
+  }
+
+  fn request(capabilities: &[String]) -> Command {
+    Command::CAP(None, CapSubCommand::REQ, None, Some(capabilities.join(" ")))
+  }
+}
+
+pub(crate) struct Backoff {
+  failures: u32,
+}
+
+impl Backoff {
+  pub(crate) fn new() -> Self {
+    Self { failures: 0 }
+  }
+
+  pub(crate) fn reset(&mut self) {
+    self.failures = 0;
+  }
+
+  pub(crate) fn next(&mut self) -> Duration {
+    let delay = BACKOFF_BASE
+      .saturating_mul(2u32.saturating_pow(self.failures))
+      .min(BACKOFF_MAX);
+
+    self.failures = self.failures.saturating_add(1);
+
+    let jitter = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default()
+      .subsec_nanos();
+
+    delay / 2 + (delay / 2).mul_f64(f64::from(jitter) / 1e9)
+  }
+}
+
+struct Bucket {
+  capacity: u32,
+  interval: Duration,
+  refilled: Instant,
+  tokens: u32,
+}
+
+impl Bucket {
+  fn new(capacity: u32, interval: Duration, now: Instant) -> Self {
+    Self {
+      capacity,
+      interval,
+      refilled: now,
+      tokens: capacity,
+    }
+  }
+
+  fn take(&mut self, now: Instant) -> Duration {
+    let elapsed = now.saturating_duration_since(self.refilled);
+    let refill = u32::try_from(elapsed.as_nanos() / self.interval.as_nanos()).unwrap_or(u32::MAX);
+
+    if self.tokens.saturating_add(refill) >= self.capacity {
+      self.tokens = self.capacity;
+      self.refilled = now;
+    } else {
+      self.tokens += refill;
+      self.refilled += self.interval * refill;
+    }
+
+    if self.tokens > 0 {
+      self.tokens -= 1;
+      Duration::ZERO
+    } else {
+      self.interval - now.saturating_duration_since(self.refilled)
+    }
+  }
+}
+
+type Link = Arc<dyn Fn(Message) -> bool + Send + Sync>;
+
+#[derive(Clone)]
+pub(crate) struct Outbox {
+  link: Arc<watch::Sender<Option<Link>>>,
+  nick: String,
+  queue: mpsc::UnboundedSender<Message>,
+  source: Arc<Mutex<(usize, usize)>>,
+  tags: Arc<AtomicBool>,
+}
+
+impl Outbox {
+  pub(crate) fn new(sender: Sender, nick: &str, tags: bool) -> Self {
+    let outbox = Self::detached(nick);
+    outbox.attach(sender, tags);
+    outbox
+  }
+
+  pub(crate) fn detached(nick: &str) -> Self {
+    let (link, mut current) = watch::channel(None::<Link>);
+    let (queue, mut receiver) = mpsc::unbounded_channel::<Message>();
+
+    tokio::spawn(async move {
+      let mut bucket = Bucket::new(FLOOD_BURST, FLOOD_INTERVAL, Instant::now());
+
+      while let Some(message) = receiver.recv().await {
+        loop {
+          let wait = bucket.take(Instant::now());
+
+          if wait.is_zero() {
+            break;
+          }
+
+          tokio::time::sleep(wait).await;
+        }
+
+        loop {
+          let link = current.borrow_and_update().clone();
+
+          if let Some(link) = link
+            && link(message.clone())
+          {
+            break;
+          }
+
+          if current.changed().await.is_err() {
+            return;
+          }
+        }
+      }
+    });
+
+    Self {
+      link: Arc::new(link),
+      nick: nick.into(),
+      queue,
+      source: Arc::new(Mutex::new((USERLEN, HOSTLEN))),
+      tags: Arc::new(AtomicBool::new(false)),
+    }
+  }
+
+  pub(crate) fn attach(&self, sender: Sender, tags: bool) {
+    self.connect(
+      Arc::new(move |message| match sender.send(message) {
+        Ok(()) => true,
+        Err(err) => {
+          ::log::error!("failed to send IRC message: {err}");
+          false
+        }
+      }),
+      tags,
+    );
+  }
+
+  fn connect(&self, link: Link, tags: bool) {
+    *self.source.lock().unwrap() = (USERLEN, HOSTLEN);
+    self.tags.store(tags, atomic::Ordering::SeqCst);
+    self.link.send_replace(Some(link));
+  }
+
+  pub(crate) fn detach(&self) {
+    self.link.send_replace(None);
+  }
+
+  #[cfg(test)]
+  pub(crate) fn test(tags: bool) -> (Self, mpsc::UnboundedReceiver<Message>) {
+    let (queue, receiver) = mpsc::unbounded_channel();
+
+    let outbox = Self {
+      link: Arc::new(watch::channel(None).0),
+      nick: "foo".into(),
+      queue,
+      source: Arc::new(Mutex::new((USERLEN, HOSTLEN))),
+      tags: Arc::new(AtomicBool::new(tags)),
+    };
+
+    (outbox, receiver)
+  }
+
+  pub(crate) fn observe(&self, message: &Message) {
+    let mut source = self.source.lock().unwrap();
+
+    match (&message.prefix, &message.command) {
+      (Some(Prefix::Nickname(nick, user, host)), _) if *nick == self.nick => {
+        *source = (user.len(), host.len());
+      }
+      (_, Command::Response(Response::RPL_HOSTHIDDEN, args)) if args.len() > 1 => {
+        source.1 = args[1].len();
+      }
+      _ => {}
+    }
+  }
+
+  pub(crate) fn budget(&self, command: &str, target: &str) -> usize {
+    let (user, host) = *self.source.lock().unwrap();
+
+    LINE_MAX
+      .saturating_sub(format!(":{}!@ {command} {target} :\r\n", self.nick).len() + user + host)
+  }
+
+  pub(crate) fn send(&self, message: impl Into<Message>) -> Result {
+    self
+      .queue
+      .send(message.into())
+      .map_err(|_| Error::IrcClosed)
+  }
+
+  pub(crate) fn privmsg(&self, target: &str, text: &str) -> Result {
+    self.reply(target, text, None)
+  }
+
+  pub(crate) fn reply(&self, target: &str, text: &str, msgid: Option<&str>) -> Result {
+    for chunk in split_utf8(text, self.budget("PRIVMSG", target)) {
+      self.send(Message {
+        tags: self.reply_tags(msgid, Vec::new()),
+        prefix: None,
+        command: Command::PRIVMSG(target.into(), chunk.into()),
+      })?;
+    }
+
+    Ok(())
+  }
+
+  pub(crate) fn react(&self, target: &str, msgid: &str, reaction: &str) -> Result {
+    let Some(tags) = self.reply_tags(
+      Some(msgid),
+      vec![Tag("+draft/react".into(), Some(reaction.into()))],
+    ) else {
+      return Ok(());
+    };
+
+    self.send(Message {
+      tags: Some(tags),
+      prefix: None,
+      command: Command::Raw("TAGMSG".into(), vec![target.into()]),
+    })
+  }
+
+  fn reply_tags(&self, msgid: Option<&str>, mut tags: Vec<Tag>) -> Option<Vec<Tag>> {
+    if !self.tags.load(atomic::Ordering::SeqCst) {
+      return None;
+    }
+
+    tags.push(Tag("+draft/reply".into(), Some(msgid?.into())));
+
+    Some(tags)
+  }
+
+  pub(crate) fn notice(&self, target: &str, text: &str) -> Result {
+    for chunk in split_utf8(text, self.budget("NOTICE", target)) {
+      self.send(Command::NOTICE(target.into(), chunk.into()))?;
+    }
+
+    Ok(())
+  }
+}
+
+pub(crate) struct PresenceQuery {
+  away: bool,
+  target: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum PresenceStep {
+  Done(Option<bool>),
+  Pending,
+  Send(Command),
+}
+
+impl PresenceQuery {
+  pub(crate) fn new(target: &str) -> (Self, Command) {
+    (
+      Self {
+        away: false,
+        target: target.into(),
+      },
+      Command::ISON(vec![target.into()]),
+    )
+  }
+
+  pub(crate) fn observe(&mut self, message: &Message) -> PresenceStep {
+    let Command::Response(response, args) = &message.command else {
+      return PresenceStep::Pending;
+    };
+
+    let is_target = |nick: &str| nick.eq_ignore_ascii_case(&self.target);
+
+    match response {
+      Response::RPL_ISON => {
+        if args
+          .get(1)
+          .is_some_and(|nicks| nicks.split_whitespace().any(is_target))
+        {
+          PresenceStep::Send(Command::WHOIS(None, self.target.clone()))
+        } else {
+          PresenceStep::Done(None)
+        }
+      }
+      Response::RPL_AWAY if args.get(1).is_some_and(|nick| is_target(nick)) => {
+        self.away = true;
+        PresenceStep::Pending
+      }
+      Response::RPL_ENDOFWHOIS if args.get(1).is_some_and(|nick| is_target(nick)) => {
+        PresenceStep::Done(Some(self.away))
+      }
+      Response::ERR_NOSUCHNICK if args.get(1).is_some_and(|nick| is_target(nick)) => {
+        PresenceStep::Done(None)
+      }
+      _ => PresenceStep::Pending,
+    }
+  }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct Health(Arc<Mutex<State>>);
+
+#[derive(Default)]
+struct State {
+  connected: Option<jiff::Timestamp>,
+  failures: u32,
+  last_error: Option<String>,
+}
+
+impl Health {
+  pub(crate) fn connected(&self) {
+    let mut state = self.0.lock().unwrap();
+    state.connected = Some(jiff::Timestamp::now());
+    state.failures = 0;
+  }
+
+  pub(crate) fn disconnected(&self, error: Option<&Error>) {
+    let mut state = self.0.lock().unwrap();
+    state.connected = None;
+    state.failures += 1;
+    if let Some(error) = error {
+      state.last_error = Some(error.to_string());
+    }
+  }
+}
+
+impl Display for Health {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    let state = self.0.lock().unwrap();
+
+    match state.connected {
+      Some(since) => write!(
+        f,
+        "connected since {}",
+        since.strftime("%Y-%m-%d %H:%M:%S UTC")
+      )?,
+      None => write!(f, "disconnected after {} failed attempt(s)", state.failures)?,
+    }
+
+    if let Some(error) = &state.last_error {
+      write!(f, ", last error: {error}")?;
+    }
+
+    Ok(())
+  }
+}
+
+pub(crate) fn split_utf8(s: &str, max_bytes: usize) -> Vec<&str> {
+  let mut chunks = Vec::new();
+  let mut start = 0;
+
+  while start < s.len() {
+    let remaining = &s[start..];
+
+    if remaining.len() <= max_bytes {
+      chunks.push(remaining);
+      break;
+    }
+
+    let mut end = start + max_bytes;
+
+    while !s.is_char_boundary(end) {
+      end -= 1;
+    }
+
+    let at_boundary = end >= s.len() || s.as_bytes()[end] == b' ';
+
+    if !at_boundary && let Some(space) = s[start..end].rfind(' ') {
+      end = start + space;
+    }
+
+    chunks.push(&s[start..end]);
+
+    start = end;
+    if start < s.len() && s.as_bytes()[start] == b' ' {
+      start += 1;
+    }
+  }
+
+  chunks
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn split_utf8_short() {
+    assert_eq!(split_utf8("foo", 400), vec!["foo"]);
+  }
+
+  #[test]
+  fn split_utf8_exact() {
+    let s = "a".repeat(400);
+    assert_eq!(split_utf8(&s, 400), vec![s.as_str()]);
+  }
+
+  #[test]
+  fn split_utf8_long() {
+    let s = "a".repeat(801);
+    let chunks = split_utf8(&s, 400);
+    assert_eq!(chunks.len(), 3);
+    assert_eq!(chunks[0].len(), 400);
+    assert_eq!(chunks[1].len(), 400);
+    assert_eq!(chunks[2].len(), 1);
+  }
+
+  #[test]
+  fn split_utf8_multibyte() {
+    let s = "\u{1F600}".repeat(101);
+    let chunks = split_utf8(&s, 400);
+    assert_eq!(chunks[0].len(), 400);
+    for chunk in &chunks {
+      assert!(chunk.len() <= 400);
+    }
+    let reassembled = chunks.join("");
+    assert_eq!(reassembled, s);
+  }
+
+  #[test]
+  fn split_utf8_word_boundary() {
+    let chunks = split_utf8("foo bar baz", 7);
+    assert_eq!(chunks, vec!["foo bar", "baz"]);
+  }
+
+  #[test]
+  fn split_utf8_no_space_fallback() {
+    let s = "a".repeat(10);
+    let chunks = split_utf8(&s, 4);
+    assert_eq!(chunks, vec!["aaaa", "aaaa", "aa"]);
+  }
+
+  #[test]
+  fn split_utf8_empty() {
+    assert!(split_utf8("", 400).is_empty());
+  }
+
+  #[test]
+  fn split_utf8_multibyte_word_boundary() {
+    let chunks = split_utf8("é é é", 5);
+    assert_eq!(chunks, vec!["é é", "é"]);
+  }
+
+  #[test]
+  fn split_utf8_fits_line_limit() {
+    let (outbox, _receiver) = Outbox::test(false);
+
+    let budget = outbox.budget("PRIVMSG", "#bar");
+
+    let text = "\u{1F600} ".repeat(200);
+
+    for chunk in split_utf8(&text, budget) {
+      let line = format!(
+        ":foo!{}@{} PRIVMSG #bar :{chunk}\r\n",
+        "u".repeat(USERLEN),
+        "h".repeat(HOSTLEN),
+      );
+      assert!(line.len() <= LINE_MAX, "{}", line.len());
+    }
+  }
+
+  #[test]
+  fn outbox_budget() {
+    let (outbox, _receiver) = Outbox::test(false);
+
+    assert_eq!(
+      outbox.budget("PRIVMSG", "bar"),
+      512 - ":foo!@ PRIVMSG bar :\r\n".len() - USERLEN - HOSTLEN
+    );
+
+    outbox.observe(&":foo!~baz@qux JOIN #bar".parse().unwrap());
+    assert_eq!(
+      outbox.budget("PRIVMSG", "bar"),
+      512 - ":foo!~baz@qux PRIVMSG bar :\r\n".len()
+    );
+
+    outbox.observe(&":quux!~baz@somewhere.long JOIN #bar".parse().unwrap());
+    assert_eq!(
+      outbox.budget("PRIVMSG", "bar"),
+      512 - ":foo!~baz@qux PRIVMSG bar :\r\n".len()
+    );
+
+    outbox.observe(
+      &":server 396 foo a.b :is now your displayed host"
+        .parse()
+        .unwrap(),
+    );
+    assert_eq!(
+      outbox.budget("NOTICE", "bar"),
+      512 - ":foo!~baz@a.b NOTICE bar :\r\n".len()
+    );
+  }
+
+  #[test]
+  fn outbox_reply() {
+    let (outbox, mut receiver) = Outbox::test(true);
+
+    outbox.reply("bar", "baz", Some("123")).unwrap();
+    assert_eq!(
+      receiver.try_recv().unwrap().to_string(),
+      "@+draft/reply=123 PRIVMSG bar baz\r\n"
+    );
+
+    outbox.reply("bar", "baz", None).unwrap();
+    assert_eq!(
+      receiver.try_recv().unwrap().to_string(),
+      "PRIVMSG bar baz\r\n"
+    );
+
+    outbox.react("bar", "123", "👀").unwrap();
+    assert_eq!(
+      receiver.try_recv().unwrap().to_string(),
+      "@+draft/react=👀;+draft/reply=123 TAGMSG bar\r\n"
+    );
+  }
+
+  #[test]
+  fn outbox_reply_without_tags() {
+    let (outbox, mut receiver) = Outbox::test(false);
+
+    outbox.reply("bar", "baz", Some("123")).unwrap();
+    assert_eq!(
+      receiver.try_recv().unwrap().to_string(),
+      "PRIVMSG bar baz\r\n"
+    );
+
+    outbox.react("bar", "123", "👀").unwrap();
+    assert!(receiver.try_recv().is_err());
+  }
+
+  #[test]
+  fn bucket() {
+    let start = Instant::now();
+    let interval = Duration::from_millis(500);
+    let mut bucket = Bucket::new(2, interval, start);
+
+    assert_eq!(bucket.take(start), Duration::ZERO);
+    assert_eq!(bucket.take(start), Duration::ZERO);
+    assert_eq!(bucket.take(start), interval);
+
+    let later = start + Duration::from_millis(200);
+    assert_eq!(bucket.take(later), Duration::from_millis(300));
+
+    let later = start + interval;
+    assert_eq!(bucket.take(later), Duration::ZERO);
+    assert_eq!(bucket.take(later), interval);
+
+    let later = start + interval * 10;
+    assert_eq!(bucket.take(later), Duration::ZERO);
+    assert_eq!(bucket.take(later), Duration::ZERO);
+    assert_eq!(bucket.take(later), interval);
+  }
+
+  #[test]
+  fn backoff() {
+    let mut backoff = Backoff::new();
+
+    for expected in [1, 2, 4, 8, 16, 32, 64, 128, 256, 300, 300] {
+      let delay = backoff.next();
+      let expected = Duration::from_secs(expected);
+      assert!(delay >= expected / 2 && delay <= expected, "{delay:?}");
+    }
+
+    backoff.reset();
+
+    assert!(backoff.next() <= BACKOFF_BASE);
+  }
+
+  #[tokio::test]
+  async fn outbox_reconnect() {
+    let outbox = Outbox::detached("foo");
+
+    outbox.privmsg("bar", "baz").unwrap();
+
+    let (first, mut first_receiver) = mpsc::unbounded_channel();
+
+    outbox.connect(Arc::new(move |message| first.send(message).is_ok()), false);
+
+    assert_eq!(
+      first_receiver.recv().await.unwrap().to_string(),
+      "PRIVMSG bar baz\r\n"
+    );
+
+    drop(first_receiver);
+
+    outbox.privmsg("bar", "qux").unwrap();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let (second, mut second_receiver) = mpsc::unbounded_channel();
+
+    outbox.connect(Arc::new(move |message| second.send(message).is_ok()), true);
+
+    assert_eq!(
+      second_receiver.recv().await.unwrap().to_string(),
+      "PRIVMSG bar qux\r\n"
+    );
+
+    outbox.detach();
+
+    outbox.reply("bar", "quux", Some("a")).unwrap();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    assert!(second_receiver.try_recv().is_err());
+
+    let (third, mut third_receiver) = mpsc::unbounded_channel();
+
+    outbox.connect(Arc::new(move |message| third.send(message).is_ok()), true);
+
+    assert_eq!(
+      third_receiver.recv().await.unwrap().to_string(),
+      "@+draft/reply=a PRIVMSG bar quux\r\n"
+    );
+  }
+
+  #[test]
+  fn negotiation() {
+    let (mut negotiation, command) = Negotiation::new(vec![
+      Capability::AccountTag,
+      Capability::Custom("message-tags"),
+      Capability::Custom("draft/chathistory"),
+    ]);
+
+    assert_eq!(Message::from(command).to_string(), "CAP LS 302\r\n");
+
+    assert_eq!(
+      negotiation
+        .observe(
+          &":server CAP * LS * :sasl=PLAIN,EXTERNAL account-tag"
+            .parse()
+            .unwrap()
+        )
+        .unwrap(),
+      NegotiationStep::Pending,
+    );
+
+    let NegotiationStep::Send(command) = negotiation
+      .observe(&":server CAP * LS :draft/chathistory batch".parse().unwrap())
+      .unwrap()
+    else {
+      panic!("expected capability request");
+    };
+
+    assert_eq!(
+      Message::from(command).to_string(),
+      "CAP REQ :sasl account-tag draft/chathistory\r\n",
+    );
+
+    let NegotiationStep::Send(command) = negotiation
+      .observe(&":server CAP * NAK :draft/chathistory".parse().unwrap())
+      .unwrap()
+    else {
+      panic!("expected capability request");
+    };
+
+    assert_eq!(
+      Message::from(command).to_string(),
+      "CAP REQ :sasl account-tag\r\n",
+    );
+
+    assert_eq!(
+      negotiation
+        .observe(&":server CAP * ACK :sasl account-tag".parse().unwrap())
+        .unwrap(),
+      NegotiationStep::Done(vec!["sasl".into(), "account-tag".into()]),
+    );
+  }
+
+  #[test]
+  fn negotiation_atomic_nak() {
+    let (mut negotiation, _) = Negotiation::new(vec![
+      Capability::AccountTag,
+      Capability::Custom("draft/chathistory"),
+    ]);
+
+    negotiation
+      .observe(
+        &":server CAP * LS :sasl account-tag draft/chathistory"
+          .parse()
+          .unwrap(),
+      )
+      .unwrap();
+
+    let mut requests = Vec::new();
+
+    let mut step = negotiation
+      .observe(
+        &":server CAP * NAK :sasl account-tag draft/chathistory"
+          .parse()
+          .unwrap(),
+      )
+      .unwrap();
+
+    while let NegotiationStep::Send(command) = step {
+      let request = Message::from(command).to_string();
+
+      let reply = if request.contains("draft/chathistory") {
+        ":server CAP * NAK :draft/chathistory"
+      } else {
+        &request.trim_end().replace("CAP REQ", ":server CAP * ACK")
+      };
+
+      requests.push(request);
+
+      step = negotiation.observe(&reply.parse().unwrap()).unwrap();
+    }
+
+    assert_eq!(
+      requests,
+      [
+        "CAP REQ sasl\r\n",
+        "CAP REQ account-tag\r\n",
+        "CAP REQ draft/chathistory\r\n",
+      ],
+    );
+
+    assert_eq!(
+      step,
+      NegotiationStep::Done(vec!["sasl".into(), "account-tag".into()]),
+    );
+  }
+
+  #[test]
+  fn negotiation_requires_sasl() {
+    let (mut negotiation, _) = Negotiation::new(vec![Capability::AccountTag]);
+
+    assert!(
+      negotiation
+        .observe(&":server CAP * LS :account-tag".parse().unwrap())
+        .is_err()
+    );
+  }
+
+  #[test]
+  fn presence_query() {
+    let (mut query, command) = PresenceQuery::new("Foo");
+
+    assert_eq!(Message::from(command).to_string(), "ISON Foo\r\n");
+
+    assert_eq!(
+      query.observe(&":server 303 bar :foo ".parse().unwrap()),
+      PresenceStep::Send(Command::WHOIS(None, "Foo".into())),
+    );
+    assert_eq!(
+      query.observe(&":server 311 bar baz ~baz host * :baz".parse().unwrap()),
+      PresenceStep::Pending,
+    );
+    assert_eq!(
+      query.observe(&":server 301 bar foo :gone".parse().unwrap()),
+      PresenceStep::Pending,
+    );
+    assert_eq!(
+      query.observe(&":server 318 bar foo :End of WHOIS".parse().unwrap()),
+      PresenceStep::Done(Some(true)),
+    );
+
+    let (mut query, _) = PresenceQuery::new("foo");
+
+    query.observe(&":server 303 bar :foo".parse().unwrap());
+
+    assert_eq!(
+      query.observe(&":server 318 bar foo :End of WHOIS".parse().unwrap()),
+      PresenceStep::Done(Some(false)),
+    );
+
+    let (mut query, _) = PresenceQuery::new("foo");
+
+    assert_eq!(
+      query.observe(&":server 303 bar :".parse().unwrap()),
+      PresenceStep::Done(None),
+    );
+
+    let (mut query, _) = PresenceQuery::new("foo");
+
+    query.observe(&":server 303 bar :foo".parse().unwrap());
+
+    assert_eq!(
+      query.observe(&":server 401 bar foo :No such nick".parse().unwrap()),
+      PresenceStep::Done(None),
+    );
+  }
+
+  #[test]
+  fn health() {
+    let health = Health::default();
+    assert_eq!(health.to_string(), "disconnected after 0 failed attempt(s)");
+
+    health.disconnected(Some(&Error::IrcProtocol {
+      message: "foo".into(),
+    }));
+    assert_eq!(
+      health.to_string(),
+      "disconnected after 1 failed attempt(s), last error: IRC protocol error: foo"
+    );
+
+    health.connected();
+    assert!(health.to_string().starts_with("connected since "));
+  }
+}
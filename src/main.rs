@@ -1,20 +1,36 @@
 use {
-  crate::{error::Error, message::Message, message::strip_quoted_reply, subcommand::Subcommand},
+  crate::{
+    calendar::{Invite, Rsvp},
+    database::Database,
+    error::Error,
+    message::{DeliveryReport, Message, strip_quoted_reply},
+    subcommand::Subcommand,
+  },
   clap::Parser,
   mailparse::MailHeaderMap,
   redb::ReadableDatabase,
+  serde::{Deserialize, Serialize},
   snafu::{ResultExt, Snafu},
   std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    fmt::{self, Display, Formatter},
     fs,
     io::{self, Read},
+    net::{IpAddr, SocketAddr},
     path::{Path, PathBuf},
     process::{self, Command, ExitCode},
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
   },
 };
 
+mod calendar;
+mod config;
+mod database;
 mod error;
+mod irc;
 mod message;
+mod relay;
 mod subcommand;
 
 type Result<T = (), E = Error> = std::result::Result<T, E>;
@@ -7,6 +7,19 @@ pub(crate) struct Message {
   pub(crate) message_id: String,
   pub(crate) in_reply_to: Option<String>,
   pub(crate) references: Vec<String>,
+  pub(crate) delivery_report: Option<DeliveryReport>,
+  pub(crate) invites: Vec<Invite>,
+}
+
+pub(crate) struct DeliveryReport {
+  pub(crate) original_message_id: Option<String>,
+  pub(crate) failures: Vec<DeliveryFailure>,
+}
+
+pub(crate) struct DeliveryFailure {
+  pub(crate) recipient: String,
+  pub(crate) status: String,
+  pub(crate) diagnostic: Option<String>,
 }
 
 impl Message {
@@ -62,6 +75,14 @@ impl Message {
 
     references.push(message_id.clone());
 
+    let delivery_report = Self::extract_delivery_report(&parsed).unwrap_or_else(|err| {
+      ::log::warn!("ignoring malformed delivery report in {message_id}: {err}");
+      None
+    });
+
+    let mut invites = Vec::new();
+    Self::extract_invites(&parsed, &mut invites);
+
     Ok(Self {
       sender,
       subject,
@@ -69,10 +90,115 @@ impl Message {
       message_id,
       in_reply_to,
       references,
+      delivery_report,
+      invites,
     })
   }
 
-  fn extract_body(parsed: &mailparse::ParsedMail) -> Option<String> {
+  fn extract_invites(parsed: &mailparse::ParsedMail, invites: &mut Vec<Invite>) {
+    if parsed.ctype.mimetype.starts_with("multipart/") {
+      for subpart in &parsed.subparts {
+        Self::extract_invites(subpart, invites);
+      }
+    } else if (parsed.ctype.mimetype == "text/calendar"
+      || parsed.ctype.mimetype == "application/ics")
+      && let Ok(body) = parsed.get_body()
+    {
+      invites.extend(Invite::parse(&body));
+    }
+  }
+
+  fn extract_delivery_report(parsed: &mailparse::ParsedMail) -> Result<Option<DeliveryReport>> {
+    if parsed.ctype.mimetype != "multipart/report"
+      || parsed
+        .ctype
+        .params
+        .get("report-type")
+        .is_none_or(|report_type| !report_type.eq_ignore_ascii_case("delivery-status"))
+    {
+      return Ok(None);
+    }
+
+    let mut original_message_id = None;
+    let mut failures = Vec::new();
+
+    for subpart in &parsed.subparts {
+      match subpart.ctype.mimetype.as_str() {
+        "message/delivery-status" | "message/global-delivery-status" => {
+          let status = subpart.get_body_raw().context(error::MailParse)?;
+          let mut remaining = status.as_slice();
+
+          loop {
+            while let Some(rest) = remaining
+              .strip_prefix(b"\r\n")
+              .or_else(|| remaining.strip_prefix(b"\n"))
+            {
+              remaining = rest;
+            }
+
+            if remaining.is_empty() {
+              break;
+            }
+
+            let (fields, end) = mailparse::parse_headers(remaining).context(error::MailParse)?;
+            remaining = &remaining[end..];
+
+            let Some(action) = fields.get_first_value("Action") else {
+              continue;
+            };
+
+            if !action.trim().eq_ignore_ascii_case("failed") {
+              continue;
+            }
+
+            let Some(recipient) = fields
+              .get_first_value("Final-Recipient")
+              .or_else(|| fields.get_first_value("Original-Recipient"))
+            else {
+              continue;
+            };
+
+            let recipient = match recipient.split_once(';') {
+              Some((_, address)) => address.trim().to_string(),
+              None => recipient.trim().to_string(),
+            };
+
+            failures.push(DeliveryFailure {
+              recipient,
+              status: fields
+                .get_first_value("Status")
+                .map(|status| status.trim().to_string())
+                .unwrap_or_default(),
+              diagnostic: fields.get_first_value("Diagnostic-Code").map(|diagnostic| {
+                match diagnostic.split_once(';') {
+                  Some((_, text)) => text.trim().to_string(),
+                  None => diagnostic.trim().to_string(),
+                }
+              }),
+            });
+          }
+        }
+        "message/rfc822" | "text/rfc822-headers" | "message/global-headers" => {
+          let original = subpart.get_body_raw().context(error::MailParse)?;
+          let (headers, _) = mailparse::parse_headers(&original).context(error::MailParse)?;
+          original_message_id = headers.get_first_value("Message-ID").map(|id| {
+            id.trim()
+              .trim_start_matches('<')
+              .trim_end_matches('>')
+              .to_string()
+          });
+        }
+        _ => {}
+      }
+    }
+
+    Ok(Some(DeliveryReport {
+      original_message_id,
+      failures,
+    }))
+  }
+
+  pub(crate) fn extract_body(parsed: &mailparse::ParsedMail) -> Option<String> {
     if parsed.ctype.mimetype.starts_with("multipart/") {
       for subpart in &parsed.subparts {
         if let Some(body) = Self::extract_body(subpart) {
@@ -291,4 +417,95 @@ mod tests {
     let message = Message::parse(raw).unwrap();
     assert_eq!(message.references, ["baz@bar"]);
   }
+
+  #[test]
+  fn delivery_report() {
+    let raw = b"From: MAILER-DAEMON@tulip.farm\r\nMessage-ID: <bounce@tulip.farm>\r\n\
+                Content-Type: multipart/report; report-type=delivery-status; boundary=bound\r\n\r\n\
+                --bound\r\n\
+                Content-Type: text/plain\r\n\r\n\
+                I'm sorry to have to inform you that your message could not be delivered.\r\n\
+                --bound\r\n\
+                Content-Type: message/delivery-status\r\n\r\n\
+                Reporting-MTA: dns; tulip.farm\r\n\r\n\
+                Final-Recipient: rfc822; foo@bar.com\r\n\
+                Action: failed\r\n\
+                Status: 5.1.1\r\n\
+                Diagnostic-Code: smtp; 550 5.1.1 no such user\r\n\r\n\
+                Final-Recipient: rfc822; baz@bar.com\r\n\
+                Action: delayed\r\n\
+                Status: 4.4.1\r\n\r\n\
+                --bound\r\n\
+                Content-Type: text/rfc822-headers\r\n\r\n\
+                From: Root <root@tulip.farm>\r\n\
+                Message-ID: <reply@tulip.farm>\r\n\
+                --bound--\r\n";
+
+    let message = Message::parse(raw).unwrap();
+    let report = message.delivery_report.unwrap();
+
+    assert_eq!(
+      report.original_message_id.as_deref(),
+      Some("reply@tulip.farm")
+    );
+    assert_eq!(report.failures.len(), 1);
+    assert_eq!(report.failures[0].recipient, "foo@bar.com");
+    assert_eq!(report.failures[0].status, "5.1.1");
+    assert_eq!(
+      report.failures[0].diagnostic.as_deref(),
+      Some("550 5.1.1 no such user"),
+    );
+  }
+
+  #[test]
+  fn delivery_report_malformed() {
+    let raw = b"From: MAILER-DAEMON@tulip.farm\r\nMessage-ID: <bounce@tulip.farm>\r\n\
+                Content-Type: multipart/report; report-type=delivery-status; boundary=bound\r\n\r\n\
+                --bound\r\n\
+                Content-Type: text/plain\r\n\r\n\
+                foo\r\n\
+                --bound\r\n\
+                Content-Type: message/delivery-status\r\n\
+                Content-Transfer-Encoding: base64\r\n\r\n\
+                !!!\r\n\
+                --bound--\r\n";
+
+    let message = Message::parse(raw).unwrap();
+    assert_eq!(message.body, "foo\r\n");
+    assert!(message.delivery_report.is_none());
+  }
+
+  #[test]
+  fn delivery_report_missing() {
+    let raw = b"From: foo@bar.com\r\nMessage-ID: <foo@bar>\r\n\
+                Content-Type: multipart/report; report-type=disposition-notification; boundary=bound\r\n\r\n\
+                --bound\r\n\
+                Content-Type: text/plain\r\n\r\n\
+                baz\r\n\
+                --bound--\r\n";
+    assert!(Message::parse(raw).unwrap().delivery_report.is_none());
+  }
+
+  #[test]
+  fn invites() {
+    let raw = b"From: foo@bar.com\r\nMessage-ID: <foo@bar>\r\n\
+                Content-Type: multipart/alternative; boundary=bound\r\n\r\n\
+                --bound\r\n\
+                Content-Type: text/plain\r\n\r\n\
+                baz\r\n\
+                --bound\r\n\
+                Content-Type: text/calendar; method=REQUEST\r\n\r\n\
+                BEGIN:VCALENDAR\r\n\
+                METHOD:REQUEST\r\n\
+                BEGIN:VEVENT\r\n\
+                UID:qux@bar.com\r\n\
+                SUMMARY:qux\r\n\
+                END:VEVENT\r\n\
+                END:VCALENDAR\r\n\
+                --bound--\r\n";
+    let message = Message::parse(raw).unwrap();
+    assert_eq!(message.body, "baz\r\n");
+    assert_eq!(message.invites.len(), 1);
+    assert_eq!(message.invites[0].summary.as_deref(), Some("qux"));
+  }
 }
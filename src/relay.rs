@@ -0,0 +1,513 @@
+use {
+  super::*,
+  ::irc::proto::{Command, Message, Response as IrcResponse},
+  std::os::unix::fs::{DirBuilderExt, PermissionsExt},
+  tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    sync::oneshot,
+  },
+};
+
+const PREFIX: &str = "[system] ";
+const PRESENCE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Request {
+  Ping,
+  Presence { target: String },
+  Privmsg { target: String, lines: Vec<String> },
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Response {
+  Error { message: String },
+  Ok,
+  Presence { away: Option<bool> },
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct Relay(Arc<Mutex<State>>);
+
+#[derive(Default)]
+struct State {
+  ison: VecDeque<String>,
+  outbox: Option<irc::Outbox>,
+  presence: HashMap<String, Lookup>,
+}
+
+struct Lookup {
+  query: irc::PresenceQuery,
+  waiters: Vec<oneshot::Sender<Option<bool>>>,
+}
+
+impl Relay {
+  pub(crate) fn connected(&self, outbox: irc::Outbox) {
+    self.0.lock().unwrap().outbox = Some(outbox);
+  }
+
+  pub(crate) fn disconnected(&self) {
+    *self.0.lock().unwrap() = State::default();
+  }
+
+  pub(crate) fn observe(&self, message: &Message) {
+    let Command::Response(response, args) = &message.command else {
+      return;
+    };
+
+    let mut state = self.0.lock().unwrap();
+    let state = &mut *state;
+
+    let target = if *response == IrcResponse::RPL_ISON {
+      state.ison.pop_front()
+    } else {
+      args.get(1).map(|nick| nick.to_lowercase())
+    };
+
+    let Some(target) = target else {
+      return;
+    };
+
+    let Some(lookup) = state.presence.get_mut(&target) else {
+      return;
+    };
+
+    match lookup.query.observe(message) {
+      irc::PresenceStep::Pending => {}
+      irc::PresenceStep::Send(command) => {
+        if let Some(outbox) = &state.outbox
+          && let Err(err) = outbox.send(command)
+        {
+          ::log::error!("failed to check presence of {target}: {err}");
+        }
+      }
+      irc::PresenceStep::Done(away) => {
+        if let Some(lookup) = state.presence.remove(&target) {
+          for waiter in lookup.waiters {
+            waiter.send(away).ok();
+          }
+        }
+      }
+    }
+  }
+
+  pub(crate) async fn serve(self, path: &Path, target: &str) -> Result {
+    let listener = Self::bind(path)?;
+
+    loop {
+      let (stream, _) = listener.accept().await.context(error::RelayIo { path })?;
+
+      let relay = self.clone();
+      let path = path.to_owned();
+      let target = target.to_owned();
+
+      tokio::spawn(async move {
+        if let Err(err) = relay.handle(&path, &target, stream).await {
+          ::log::error!("relay connection failed: {err}");
+        }
+      });
+    }
+  }
+
+  fn bind(path: &Path) -> Result<UnixListener> {
+    let staging = path.with_extension("staging");
+
+    for result in [fs::remove_file(path), fs::remove_dir_all(&staging)] {
+      match result {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+          return Err(err).context(error::RelayIo { path });
+        }
+        _ => {}
+      }
+    }
+
+    fs::DirBuilder::new()
+      .mode(0o700)
+      .create(&staging)
+      .context(error::RelayIo { path: &staging })?;
+
+    let staged = staging.join("socket");
+
+    let listener = UnixListener::bind(&staged).context(error::RelayIo { path: &staged })?;
+
+    fs::set_permissions(&staged, fs::Permissions::from_mode(0o600))
+      .context(error::RelayIo { path: &staged })?;
+
+    fs::rename(&staged, path).context(error::RelayIo { path })?;
+
+    fs::remove_dir(&staging).context(error::RelayIo { path: &staging })?;
+
+    Ok(listener)
+  }
+
+  async fn handle(&self, path: &Path, target: &str, stream: UnixStream) -> Result {
+    let (reader, mut writer) = stream.into_split();
+
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await.context(error::RelayIo { path })? {
+      let response = match serde_json::from_str::<Request>(&line) {
+        Ok(request) => self
+          .request(target, request)
+          .await
+          .unwrap_or_else(|err| Response::Error {
+            message: err.to_string(),
+          }),
+        Err(err) => Response::Error {
+          message: format!("invalid request: {err}"),
+        },
+      };
+
+      let mut json = serde_json::to_string(&response).context(error::JsonParse)?;
+      json.push('\n');
+
+      writer
+        .write_all(json.as_bytes())
+        .await
+        .context(error::RelayIo { path })?;
+    }
+
+    Ok(())
+  }
+
+  async fn request(&self, allowed: &str, request: Request) -> Result<Response> {
+    let check = |target: &str| {
+      if target.eq_ignore_ascii_case(allowed) {
+        Ok(())
+      } else {
+        Err(Error::RelayTarget {
+          target: target.into(),
+        })
+      }
+    };
+
+    match request {
+      Request::Ping => {
+        self.outbox()?;
+        Ok(Response::Ok)
+      }
+      Request::Privmsg { target, lines } => {
+        check(&target)?;
+
+        let outbox = self.outbox()?;
+
+        for line in lines {
+          outbox.privmsg(&target, &format!("{PREFIX}{line}"))?;
+        }
+
+        Ok(Response::Ok)
+      }
+      Request::Presence { target } => {
+        check(&target)?;
+
+        Ok(Response::Presence {
+          away: self.presence(&target).await?,
+        })
+      }
+    }
+  }
+
+  fn outbox(&self) -> Result<irc::Outbox> {
+    self
+      .0
+      .lock()
+      .unwrap()
+      .outbox
+      .clone()
+      .ok_or(Error::IrcClosed)
+  }
+
+  async fn presence(&self, target: &str) -> Result<Option<bool>> {
+    let receiver = {
+      let mut state = self.0.lock().unwrap();
+      let state = &mut *state;
+
+      let outbox = state.outbox.clone().ok_or(Error::IrcClosed)?;
+
+      let key = target.to_lowercase();
+
+      let (sender, receiver) = oneshot::channel();
+
+      if let Some(lookup) = state.presence.get_mut(&key) {
+        lookup.waiters.retain(|waiter| !waiter.is_closed());
+      }
+
+      match state.presence.get_mut(&key) {
+        Some(lookup) if !lookup.waiters.is_empty() => lookup.waiters.push(sender),
+        _ => {
+          let (query, command) = irc::PresenceQuery::new(target);
+
+          state.ison.retain(|pending| *pending != key);
+          state.ison.push_back(key.clone());
+
+          state.presence.insert(
+            key,
+            Lookup {
+              query,
+              waiters: vec![sender],
+            },
+          );
+
+          outbox.send(command)?;
+        }
+      }
+
+      receiver
+    };
+
+    tokio::time::timeout(PRESENCE_TIMEOUT, receiver)
+      .await
+      .map_err(|_| Error::Relay {
+        message: format!("timed out checking presence of {target}"),
+      })?
+      .map_err(|_| Error::IrcClosed)
+  }
+}
+
+pub(crate) struct Client {
+  path: PathBuf,
+}
+
+impl Client {
+  pub(crate) async fn connect(path: &Path) -> Result<Self> {
+    let client = Self { path: path.into() };
+
+    match client.request(&Request::Ping).await? {
+      Response::Ok => Ok(client),
+      response => Err(Self::unexpected(response)),
+    }
+  }
+
+  pub(crate) async fn privmsg(&self, target: &str, lines: Vec<String>) -> Result {
+    match self
+      .request(&Request::Privmsg {
+        target: target.into(),
+        lines,
+      })
+      .await?
+    {
+      Response::Ok => Ok(()),
+      response => Err(Self::unexpected(response)),
+    }
+  }
+
+  pub(crate) async fn presence(&self, target: &str) -> Result<Option<bool>> {
+    match self
+      .request(&Request::Presence {
+        target: target.into(),
+      })
+      .await?
+    {
+      Response::Presence { away } => Ok(away),
+      response => Err(Self::unexpected(response)),
+    }
+  }
+
+  async fn request(&self, request: &Request) -> Result<Response> {
+    let path = &self.path;
+
+    let mut stream = UnixStream::connect(path)
+      .await
+      .context(error::RelayIo { path })?;
+
+    let mut json = serde_json::to_string(request).context(error::JsonParse)?;
+    json.push('\n');
+
+    stream
+      .write_all(json.as_bytes())
+      .await
+      .context(error::RelayIo { path })?;
+
+    let mut line = String::new();
+
+    BufReader::new(stream)
+      .read_line(&mut line)
+      .await
+      .context(error::RelayIo { path })?;
+
+    if line.is_empty() {
+      return Err(Error::Relay {
+        message: "connection closed".into(),
+      });
+    }
+
+    match serde_json::from_str(&line).context(error::JsonParse)? {
+      Response::Error { message } => Err(Error::Relay { message }),
+      response => Ok(response),
+    }
+  }
+
+  fn unexpected(response: Response) -> Error {
+    Error::Relay {
+      message: format!("unexpected response {response:?}"),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  async fn serve(relay: &Relay, dir: &tempfile::TempDir) -> PathBuf {
+    let path = dir.path().join("relay.sock");
+
+    tokio::spawn({
+      let relay = relay.clone();
+      let path = path.clone();
+      async move { relay.serve(&path, "bar").await.unwrap() }
+    });
+
+    while !path.exists() {
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    path
+  }
+
+  #[test]
+  fn protocol() {
+    assert_eq!(serde_json::to_string(&Request::Ping).unwrap(), r#""ping""#);
+    assert_eq!(
+      serde_json::to_string(&Request::Privmsg {
+        target: "foo".into(),
+        lines: vec!["bar".into()],
+      })
+      .unwrap(),
+      r#"{"privmsg":{"target":"foo","lines":["bar"]}}"#,
+    );
+    assert_eq!(
+      serde_json::from_str::<Response>(r#"{"presence":{"away":true}}"#).unwrap(),
+      Response::Presence { away: Some(true) },
+    );
+    assert_eq!(
+      serde_json::from_str::<Response>(r#"{"presence":{"away":null}}"#).unwrap(),
+      Response::Presence { away: None },
+    );
+  }
+
+  #[tokio::test]
+  async fn disconnected() {
+    let dir = tempfile::TempDir::new().unwrap();
+
+    let relay = Relay::default();
+
+    let path = serve(&relay, &dir).await;
+
+    assert_eq!(
+      Client::connect(&path).await.err().unwrap().to_string(),
+      "relay error: IRC connection closed",
+    );
+
+    assert!(
+      Client::connect(&dir.path().join("missing.sock"))
+        .await
+        .is_err()
+    );
+  }
+
+  #[tokio::test]
+  async fn privmsg() {
+    let dir = tempfile::TempDir::new().unwrap();
+
+    let relay = Relay::default();
+
+    let path = serve(&relay, &dir).await;
+
+    let (outbox, mut receiver) = irc::Outbox::test(false);
+
+    relay.connected(outbox);
+
+    let client = Client::connect(&path).await.unwrap();
+
+    client
+      .privmsg("bar", vec!["baz".into(), "qux".into()])
+      .await
+      .unwrap();
+
+    assert_eq!(
+      receiver.recv().await.unwrap().to_string(),
+      "PRIVMSG bar :[system] baz\r\n"
+    );
+    assert_eq!(
+      receiver.recv().await.unwrap().to_string(),
+      "PRIVMSG bar :[system] qux\r\n"
+    );
+
+    assert_eq!(
+      client
+        .privmsg("baz", vec!["qux".into()])
+        .await
+        .unwrap_err()
+        .to_string(),
+      "relay error: relay does not accept requests for `baz`",
+    );
+
+    assert!(receiver.try_recv().is_err());
+
+    relay.disconnected();
+
+    assert!(client.privmsg("bar", vec!["baz".into()]).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn presence() {
+    let dir = tempfile::TempDir::new().unwrap();
+
+    let relay = Relay::default();
+
+    let path = serve(&relay, &dir).await;
+
+    let (outbox, mut receiver) = irc::Outbox::test(false);
+
+    relay.connected(outbox);
+
+    let client = Client::connect(&path).await.unwrap();
+
+    let presence = tokio::spawn(async move { client.presence("Bar").await });
+
+    assert_eq!(receiver.recv().await.unwrap().to_string(), "ISON Bar\r\n");
+
+    relay.observe(&":server 303 foo :bar".parse().unwrap());
+
+    assert_eq!(receiver.recv().await.unwrap().to_string(), "WHOIS Bar\r\n");
+
+    relay.observe(&":server 301 foo bar :gone".parse().unwrap());
+    relay.observe(&":server 318 foo bar :End of WHOIS".parse().unwrap());
+
+    assert_eq!(presence.await.unwrap().unwrap(), Some(true));
+
+    let client = Client::connect(&path).await.unwrap();
+
+    let presence = tokio::spawn(async move { client.presence("bar").await });
+
+    assert_eq!(receiver.recv().await.unwrap().to_string(), "ISON bar\r\n");
+
+    relay.observe(&":server 303 foo :".parse().unwrap());
+
+    assert_eq!(presence.await.unwrap().unwrap(), None);
+
+    let client = Client::connect(&path).await.unwrap();
+
+    assert!(client.presence("baz").await.is_err());
+
+    assert!(receiver.try_recv().is_err());
+  }
+
+  #[tokio::test]
+  async fn permissions() {
+    let dir = tempfile::TempDir::new().unwrap();
+
+    let relay = Relay::default();
+
+    let path = serve(&relay, &dir).await;
+
+    assert_eq!(
+      fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+      0o600
+    );
+
+    assert!(!path.with_extension("staging").exists());
+  }
+}
@@ -10,17 +10,45 @@ mod resume;
 mod sessions;
 mod task;
 
-use super::*;
+use {super::*, redb::ReadableTable};
 
 const SESSION_DIR: &str = "/root/sessions";
 const SESSIONS: redb::TableDefinition<&str, &str> = redb::TableDefinition::new("sessions");
 
+static AGENTS: Mutex<BTreeMap<String, u32>> = Mutex::new(BTreeMap::new());
+
 pub(crate) fn db_path() -> PathBuf {
   dirs::home_dir().unwrap().join(".lab.redb")
 }
 
-pub(crate) fn lookup_session(db_path: &Path, name: &str) -> Result<(String, bool)> {
-  let db = redb::Database::create(db_path).context(error::DatabaseOpen { path: db_path })?;
+pub(crate) fn progress_path(session: &str) -> Result<PathBuf> {
+  use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+  let uid = unsafe { libc::getuid() };
+
+  let dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+    Some(runtime) => PathBuf::from(runtime).join("lab"),
+    None => std::env::temp_dir().join(format!("lab-{uid}")),
+  };
+
+  match fs::DirBuilder::new().mode(0o700).create(&dir) {
+    Err(err) if err.kind() != io::ErrorKind::AlreadyExists => {
+      return Err(err).context(error::FilesystemIo { path: dir });
+    }
+    _ => {}
+  }
+
+  let metadata = fs::symlink_metadata(&dir).context(error::FilesystemIo { path: &dir })?;
+
+  if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+    return Err(Error::ProgressDir { path: dir });
+  }
+
+  Ok(dir.join(format!("progress-{session}")))
+}
+
+pub(crate) fn lookup_session(db: &Database, name: &str) -> Result<(String, bool)> {
+  let db = db.open()?;
 
   let read_txn = db.begin_read().context(error::DatabaseTransaction)?;
   let table = read_txn.open_table(SESSIONS);
@@ -40,8 +68,8 @@ pub(crate) fn lookup_session(db_path: &Path, name: &str) -> Result<(String, bool
   Ok((session, resume))
 }
 
-pub(crate) fn save_session(db_path: &Path, name: &str, session: &str) -> Result {
-  let db = redb::Database::create(db_path).context(error::DatabaseOpen { path: db_path })?;
+pub(crate) fn save_session(db: &Database, name: &str, session: &str) -> Result {
+  let db = db.open()?;
 
   let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
   {
@@ -57,8 +85,29 @@ pub(crate) fn save_session(db_path: &Path, name: &str, session: &str) -> Result
   Ok(())
 }
 
-pub(crate) fn reset_session(db_path: &Path, name: &str) -> Result {
-  let db = redb::Database::create(db_path).context(error::DatabaseOpen { path: db_path })?;
+pub(crate) fn list_sessions(db: &Database) -> Result<BTreeMap<String, String>> {
+  let db = db.open()?;
+
+  let read_txn = db.begin_read().context(error::DatabaseTransaction)?;
+
+  let table = match read_txn.open_table(SESSIONS) {
+    Ok(table) => table,
+    Err(redb::TableError::TableDoesNotExist(_)) => return Ok(BTreeMap::new()),
+    Err(e) => return Err(e).context(error::DatabaseTable),
+  };
+
+  let mut sessions = BTreeMap::new();
+
+  for entry in table.iter().context(error::DatabaseStorage)? {
+    let (name, session) = entry.context(error::DatabaseStorage)?;
+    sessions.insert(name.value().to_string(), session.value().to_string());
+  }
+
+  Ok(sessions)
+}
+
+pub(crate) fn reset_session(db: &Database, name: &str) -> Result {
+  let db = db.open()?;
 
   let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
   {
@@ -118,13 +167,7 @@ pub(crate) fn invoke_agent(
     .stderr(process::Stdio::piped())
     .current_dir(&session_dir)
     .spawn()
-    .and_then(|mut child| {
-      use io::Write;
-      if let Some(mut stdin) = child.stdin.take() {
-        stdin.write_all(body.as_bytes())?;
-      }
-      child.wait_with_output()
-    })
+    .and_then(|child| wait_for_agent(session, child, body.as_bytes()))
     .context(error::AgentInvocation)?;
 
   if !output.status.success() {
@@ -148,13 +191,7 @@ pub(crate) fn invoke_agent(
       .stderr(process::Stdio::piped())
       .current_dir(&session_dir)
       .spawn()
-      .and_then(|mut child| {
-        use io::Write;
-        if let Some(mut stdin) = child.stdin.take() {
-          stdin.write_all(b"Briefly summarize what you just did.")?;
-        }
-        child.wait_with_output()
-      })
+      .and_then(|child| wait_for_agent(session, child, b"Briefly summarize what you just did."))
       .context(error::AgentInvocation)?;
 
     if !output.status.success() {
@@ -170,6 +207,87 @@ pub(crate) fn invoke_agent(
   Ok(response)
 }
 
+fn wait_for_agent(
+  session: &str,
+  mut child: process::Child,
+  input: &[u8],
+) -> io::Result<process::Output> {
+  use io::{Read, Write};
+
+  fn read(
+    pipe: Option<impl Read + Send + 'static>,
+  ) -> std::thread::JoinHandle<io::Result<Vec<u8>>> {
+    std::thread::spawn(move || {
+      let mut buffer = Vec::new();
+      if let Some(mut pipe) = pipe {
+        pipe.read_to_end(&mut buffer)?;
+      }
+      Ok(buffer)
+    })
+  }
+
+  let pid = child.id();
+
+  AGENTS.lock().unwrap().insert(session.to_string(), pid);
+
+  let stdout = read(child.stdout.take());
+  let stderr = read(child.stderr.take());
+
+  let written = match child.stdin.take() {
+    Some(mut stdin) => stdin.write_all(input),
+    None => Ok(()),
+  };
+
+  // Unregister before reaping so `cancel_agent` never signals a reused pid.
+  let exited = exited(pid);
+
+  AGENTS.lock().unwrap().remove(session);
+
+  let status = child.wait()?;
+
+  let stdout = stdout.join().expect("stdout reader panicked")?;
+  let stderr = stderr.join().expect("stderr reader panicked")?;
+
+  written?;
+  exited?;
+
+  Ok(process::Output {
+    status,
+    stdout,
+    stderr,
+  })
+}
+
+fn exited(pid: u32) -> io::Result<()> {
+  loop {
+    let mut info = unsafe { std::mem::zeroed::<libc::siginfo_t>() };
+
+    if unsafe { libc::waitid(libc::P_PID, pid, &mut info, libc::WEXITED | libc::WNOWAIT) } == 0 {
+      return Ok(());
+    }
+
+    let err = io::Error::last_os_error();
+
+    if err.kind() != io::ErrorKind::Interrupted {
+      return Err(err);
+    }
+  }
+}
+
+pub(crate) fn cancel_agent(session: &str) -> Result<bool> {
+  let agents = AGENTS.lock().unwrap();
+
+  let Some(pid) = agents.get(session).copied() else {
+    return Ok(false);
+  };
+
+  if unsafe { libc::kill(pid.cast_signed(), libc::SIGTERM) } != 0 {
+    return Err(io::Error::last_os_error()).context(error::AgentInvocation);
+  }
+
+  Ok(true)
+}
+
 #[derive(clap::Subcommand)]
 pub(crate) enum Subcommand {
   Chat(chat::Chat),
@@ -1,22 +1,46 @@
 use super::*;
 
 use {
-  base64::Engine,
-  irc::client::{
-    Client, ClientStream,
-    data::Config,
-    prelude::{Capability, Response, Sender},
+  self::{
+    away::Away,
+    bang::BangCommand,
+    history::History,
+    paste::Paster,
+    progress::Progress,
+    queue::{Coalesce, Queues},
   },
-  irc::proto::{CapSubCommand, Command as IrcCommand},
+  ::irc::{
+    client::prelude::{Capability, Response},
+    proto::{Command as IrcCommand, Message as IrcMessage, message::Tag},
+  },
+  config::Format,
+  std::os::unix::process::ExitStatusExt,
   tokio_stream::StreamExt,
 };
 
-const SERVER: &str = "tulip.farm";
-const PORT: u16 = 6697;
+mod accounts;
+mod away;
+mod bang;
+mod fetch;
+mod history;
+mod paste;
+mod progress;
+mod queue;
+
 const NICK: &str = "root";
-const PASSWORD_FILE: &str = "/root/secrets/ergo-password";
-const ALLOWED_SENDER: &str = "rodarmor";
-const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const NOTIFY_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+const REACTION: &str = "👀";
+const STABLE_CONNECTION: Duration = Duration::from_secs(60);
+const BOLD: &str = "\x02";
+const ITALICS: &str = "\x1d";
+const MONOSPACE: &str = "\x11";
+const STRIKETHROUGH: &str = "\x1e";
+const CODE_BLOCK: &str = "\x11\x0310,99";
+const QUOTE_COLOR: &str = "\x0303,99";
+const RESET: &str = "\x0f";
+const FAST_MODE: redb::TableDefinition<&str, bool> = redb::TableDefinition::new("chat_fast_mode");
+
+static RUNNING: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
 
 #[derive(clap::Args)]
 pub(crate) struct Chat {
@@ -24,6 +48,39 @@ pub(crate) struct Chat {
   db: Option<PathBuf>,
   #[arg(long, default_value = "claude")]
   claude: PathBuf,
+  #[arg(long)]
+  config: Option<PathBuf>,
+}
+
+struct Request {
+  target: String,
+  context: String,
+  prompt: String,
+  system_prompt: String,
+  fast: bool,
+  format: Format,
+  msgid: Option<String>,
+  received: Vec<(jiff::Timestamp, Option<String>)>,
+}
+
+impl Coalesce for Request {
+  fn continued(&self) -> bool {
+    self.prompt.ends_with('\\')
+  }
+
+  fn merge(&mut self, other: Self) {
+    if self.continued() {
+      self.prompt.pop();
+    }
+
+    self.prompt.push('\n');
+    self.prompt.push_str(&other.prompt);
+    self.system_prompt = other.system_prompt;
+    self.fast = other.fast;
+    self.format = other.format;
+    self.msgid = other.msgid;
+    self.received.extend(other.received);
+  }
 }
 
 impl Chat {
@@ -33,166 +90,676 @@ impl Chat {
   }
 
   async fn run_async(&self) -> Result {
-    let password = fs::read_to_string(PASSWORD_FILE)
-      .context(error::PasswordFile {
-        path: Path::new(PASSWORD_FILE),
-      })?
-      .trim()
-      .to_string();
+    let config = Arc::new(config::Config::load(
+      &self.config.clone().unwrap_or_else(config::Config::path),
+    )?);
 
-    loop {
-      match self.run_connection(&password).await {
-        Ok(()) => {}
-        Err(e) => {
-          ::log::error!("connection error: {e}");
-          tokio::time::sleep(RECONNECT_DELAY).await;
+    let db = Database::new(self.db.clone().unwrap_or_else(db_path));
+
+    if let Some(paste) = &config.chat.paste {
+      let paster = Paster {
+        db: db.clone(),
+        config: paste.clone(),
+      };
+
+      tokio::spawn(async move {
+        if let Err(e) = paster.serve().await {
+          ::log::error!("paste server failed: {e}");
+        }
+      });
+    }
+
+    tokio::spawn({
+      let config = config.clone();
+      let db = db.clone();
+      async move {
+        loop {
+          tokio::time::sleep(NOTIFY_FLUSH_INTERVAL).await;
+
+          let result = tokio::task::spawn_blocking({
+            let db = db.clone();
+            let config = config.clone();
+            let runtime = tokio::runtime::Handle::current();
+            move || runtime.block_on(notify::flush_pending(&db, &config))
+          })
+          .await;
+
+          match result {
+            Ok(Ok(0)) => {}
+            Ok(Ok(delivered)) => ::log::info!("delivered {delivered} pending notification(s)"),
+            Ok(Err(e)) => ::log::error!("failed to flush pending notifications: {e}"),
+            Err(e) => ::log::error!("flushing pending notifications panicked: {e}"),
+          }
         }
       }
+    });
+
+    let relay = relay::Relay::default();
+
+    if let Some(path) = config.notify.relay.clone() {
+      let relay = relay.clone();
+      let target = config.notify.target.clone();
+      tokio::spawn(async move {
+        if let Err(e) = relay.serve(&path, &target).await {
+          ::log::error!("notification relay failed: {e}");
+        }
+      });
     }
-  }
 
-  async fn run_connection(&self, password: &str) -> Result {
-    let config = Config {
-      server: Some(SERVER.to_string()),
-      port: Some(PORT),
-      nickname: Some(NICK.to_string()),
-      use_tls: Some(true),
-      ping_time: Some(30),
-      ping_timeout: Some(20),
-      ..Config::default()
-    };
+    let paster = config.chat.paste.clone().map(|config| Paster {
+      db: db.clone(),
+      config,
+    });
+
+    let outbox = irc::Outbox::detached(NICK);
+
+    let away = Away::new(db.clone(), outbox.clone());
+
+    let history = History::load(&db)?;
+
+    let mut queues = Queues::new(Duration::from_millis(config.chat.quiet_window), {
+      let db = db.clone();
+      let claude = self.claude.clone();
+      let outbox = outbox.clone();
+      let fetch = config.chat.fetch.clone();
+      let progress = config.chat.progress.clone();
+      let away = away.clone();
+      let history = history.clone();
+      move |name: &str, request: Request| {
+        let result = lookup_session(&db, name).and_then(|(session, resume)| {
+          let _turn = away.turn();
+          let progress =
+            Progress::start(&progress, outbox.clone(), request.target.clone(), &session);
+          RUNNING.lock().unwrap().insert(name.into(), session.clone());
+          let response =
+            Self::handle_message(&db, &claude, &fetch, name, &session, resume, &request);
+          RUNNING.lock().unwrap().remove(name);
+          progress.finish();
+          response
+        });
+
+        match result {
+          Ok(response) => {
+            if let Err(e) = Self::send_response(
+              &outbox,
+              &request.target,
+              &response,
+              request.format,
+              request.msgid.as_deref(),
+              paster.as_ref(),
+            ) {
+              ::log::error!("failed to send response: {e}");
+            }
+          }
+          Err(Error::AgentFailed { status, .. }) if status.signal().is_some() => {
+            ::log::info!("agent for `{name}` was cancelled");
+          }
+          Err(e) => {
+            ::log::error!("failed to handle message: {e}");
+            let msg = format!("error: {e}").replace('\n', " | ");
+            outbox
+              .reply(&request.target, &msg, request.msgid.as_deref())
+              .ok();
+          }
+        }
+
+        for (timestamp, msgid) in &request.received {
+          if let Err(e) = history.record(&request.target, *timestamp, msgid.as_deref()) {
+            ::log::error!("failed to record chat history: {e}");
+          }
+        }
+      }
+    });
+
+    let health = irc::Health::default();
+    let mut backoff = irc::Backoff::new();
+
+    loop {
+      let start = Instant::now();
+
+      away.reset();
+
+      let result = Self::run_connection(
+        &config,
+        &db,
+        &health,
+        &relay,
+        &outbox,
+        &history,
+        &mut queues,
+      )
+      .await;
+
+      relay.disconnected();
+      outbox.detach();
+
+      if let Err(e) = &result {
+        ::log::error!("connection error: {e}");
+      }
+
+      health.disconnected(result.as_ref().err());
+
+      if start.elapsed() >= STABLE_CONNECTION {
+        backoff.reset();
+      }
+
+      let delay = backoff.next();
 
-    let mut client = Client::from_config(config).await.context(error::Irc)?;
-    let mut stream = client.stream().context(error::Irc)?;
+      ::log::info!("reconnecting in {delay:?} ({health})");
 
-    Self::sasl_auth(&client, &mut stream, password).await?;
+      tokio::time::sleep(delay).await;
+    }
+  }
 
-    client.identify().context(error::Irc)?;
+  async fn run_connection(
+    config: &config::Config,
+    db: &Database,
+    health: &irc::Health,
+    relay: &relay::Relay,
+    outbox: &irc::Outbox,
+    history: &History,
+    queues: &mut Queues<Request>,
+  ) -> Result {
+    let chat = &config.chat;
+
+    let irc::Connection {
+      capabilities,
+      client,
+      mut stream,
+    } = irc::Connection::open(
+      &config.irc,
+      NICK,
+      vec![
+        Capability::AccountTag,
+        Capability::Custom("message-tags"),
+        Capability::ServerTime,
+        Capability::Batch,
+        Capability::Custom("draft/chathistory"),
+      ],
+      chat
+        .channels
+        .iter()
+        .map(|channel| channel.name.clone())
+        .collect(),
+    )
+    .await?;
+
+    health.connected();
+
+    if !capabilities
+      .iter()
+      .any(|capability| capability == "account-tag")
+    {
+      ::log::warn!("server did not acknowledge account-tag, all senders will be refused");
+    }
+
+    outbox.attach(
+      client.sender(),
+      capabilities
+        .iter()
+        .any(|capability| capability == "message-tags"),
+    );
+
+    let mut backlogs = HashMap::<String, VecDeque<String>>::new();
+    let mut continuations = HashSet::<(String, String)>::new();
 
     while let Some(message) = stream.next().await.transpose().context(error::Irc)? {
-      if let IrcCommand::PRIVMSG(ref target, ref text) = message.command {
-        if !target.eq_ignore_ascii_case(NICK) {
-          continue;
+      outbox.observe(&message);
+      relay.observe(&message);
+
+      if let IrcCommand::Response(Response::RPL_ENDOFMOTD | Response::ERR_NOMOTD, _) =
+        message.command
+      {
+        relay.connected(outbox.clone());
+
+        if let Some(command) = history.targets() {
+          outbox.send(command)?;
         }
+      }
+
+      if let Some(command) = history.after(&message) {
+        outbox.send(command)?;
+      }
 
+      if let IrcCommand::PRIVMSG(ref target, ref text) = message.command {
         let sender = match message.source_nickname() {
           Some(nick) => nick.to_string(),
           None => continue,
         };
 
-        if sender != ALLOWED_SENDER {
+        let conversation = if target.eq_ignore_ascii_case(NICK) {
+          &sender
+        } else {
+          target
+        };
+
+        let msgid = message
+          .tags
+          .iter()
+          .flatten()
+          .find(|tag| tag.0 == "msgid")
+          .and_then(|tag| tag.1.clone());
+
+        let timestamp = History::timestamp(&message);
+
+        if let Some(timestamp) = timestamp
+          && history.handled(conversation, timestamp, msgid.as_deref())
+        {
+          continue;
+        }
+
+        let received = timestamp
+          .map(|timestamp| (timestamp, msgid.clone()))
+          .into_iter()
+          .collect::<Vec<(jiff::Timestamp, Option<String>)>>();
+
+        let account = accounts::account(&message);
+
+        let user = account
+          .as_deref()
+          .and_then(|account| chat.users.iter().find(|user| user.account == account));
+
+        if target.eq_ignore_ascii_case(NICK) {
+          let Some(user) = user else {
+            ::log::warn!(
+              "ignoring message from unauthorized sender {sender} (account {})",
+              account.as_deref().unwrap_or("none"),
+            );
+            continue;
+          };
+
+          let mut system_prompt = format!("You are chatting over IRC with {sender}.");
+
+          if let Some(prompt) = &user.system_prompt {
+            system_prompt.push_str("\n\n");
+            system_prompt.push_str(prompt);
+          }
+
+          let queued = Self::dispatch(
+            db,
+            health,
+            queues,
+            outbox,
+            &format!("chat:{}", user.namespace()),
+            text,
+            Request {
+              target: sender.clone(),
+              context: String::new(),
+              prompt: text.clone(),
+              system_prompt,
+              fast: user.fast,
+              format: user.format,
+              msgid: msgid.clone(),
+              received,
+            },
+          );
+
+          if queued {
+            history.dispatched(msgid.as_deref());
+          } else if let Some(timestamp) = timestamp
+            && let Err(e) = history.record(&sender, timestamp, msgid.as_deref())
+          {
+            ::log::error!("failed to record chat history: {e}");
+          }
+
           continue;
         }
 
-        let text = text.clone();
-        let db = self.db.clone().unwrap_or_else(db_path);
-        let claude = self.claude.clone();
-        let irc_sender = client.sender();
+        let Some(channel) = chat
+          .channels
+          .iter()
+          .find(|channel| channel.name.eq_ignore_ascii_case(target))
+        else {
+          continue;
+        };
+
+        let backlog = backlogs.entry(channel.name.to_lowercase()).or_default();
+
+        let continuation = (channel.name.to_lowercase(), sender.to_lowercase());
+
+        let addressed = if continuations.remove(&continuation) {
+          Some(text.as_str())
+        } else {
+          addressed(text, NICK)
+        };
 
-        tokio::task::spawn_blocking(move || {
-          match Self::handle_message(&db, &claude, &sender, &text) {
-            Ok(response) => {
-              if let Err(e) = Self::send_response(&irc_sender, &sender, &response) {
-                ::log::error!("failed to send response: {e}");
+        if let Some(addressed) = addressed {
+          if let Some(account) = account
+            .as_ref()
+            .filter(|account| channel.allow.contains(account))
+          {
+            let mut context = String::new();
+
+            if !backlog.is_empty() {
+              context.push_str(&format!("Recent messages in {}:\n\n", channel.name));
+              for line in backlog.iter() {
+                context.push_str(line);
+                context.push('\n');
               }
+              context.push('\n');
             }
-            Err(e) => {
-              ::log::error!("failed to handle message: {e}");
-              let msg = format!("error: {e}").replace('\n', " | ");
-              let _ = irc_sender.send_privmsg(&sender, &msg);
+
+            if addressed.ends_with('\\') {
+              continuations.insert(continuation);
+            }
+
+            let queued = Self::dispatch(
+              db,
+              health,
+              queues,
+              outbox,
+              &format!("chat:{}", channel.name),
+              addressed,
+              Request {
+                target: channel.name.clone(),
+                context,
+                prompt: format!("<{sender}> {addressed}"),
+                system_prompt: format!(
+                  "You are participating in the IRC channel {} as {NICK}. \
+                   The latest message is from {sender} (account {account}), who addressed you.",
+                  channel.name,
+                ),
+                fast: user.is_none_or(|user| user.fast),
+                format: user.map(|user| user.format).unwrap_or_default(),
+                msgid: msgid.clone(),
+                received,
+              },
+            );
+
+            if queued {
+              history.dispatched(msgid.as_deref());
+            } else if let Some(timestamp) = timestamp
+              && let Err(e) = history.record(&channel.name, timestamp, msgid.as_deref())
+            {
+              ::log::error!("failed to record chat history: {e}");
             }
+          } else {
+            ::log::warn!(
+              "ignoring mention in {} from unauthorized sender {sender} (account {})",
+              channel.name,
+              account.as_deref().unwrap_or("none"),
+            );
           }
-        })
-        .await
-        .context(error::TokioJoin)?;
+        }
+
+        backlog.push_back(format!("<{sender}> {text}"));
+
+        while backlog.len() > channel.backlog {
+          backlog.pop_front();
+        }
       }
     }
 
     Ok(())
   }
 
-  async fn sasl_auth(client: &Client, stream: &mut ClientStream, password: &str) -> Result {
-    client
-      .send_cap_req(&[Capability::Sasl])
-      .context(error::Irc)?;
-
-    while let Some(message) = stream.next().await.transpose().context(error::Irc)? {
-      if let IrcCommand::CAP(_, CapSubCommand::ACK, _, _) = &message.command {
-        break;
+  fn dispatch(
+    db: &Database,
+    health: &irc::Health,
+    queues: &mut Queues<Request>,
+    outbox: &irc::Outbox,
+    name: &str,
+    text: &str,
+    mut request: Request,
+  ) -> bool {
+    if let Some(command) = BangCommand::parse(text) {
+      let response = match command {
+        Some(command) => Self::bang(db, health, queues, name, request.fast, command)
+          .unwrap_or_else(|e| format!("error: {e}").replace('\n', " | ")),
+        None => BangCommand::USAGE.into(),
+      };
+
+      if let Err(e) = Self::send_response(
+        outbox,
+        &request.target,
+        &response,
+        Format::Plain,
+        request.msgid.as_deref(),
+        None,
+      ) {
+        ::log::error!("failed to send response: {e}");
       }
+
+      return false;
     }
 
-    client.send_sasl_plain().context(error::Irc)?;
+    match Self::fast_mode(db, name) {
+      Ok(fast) => request.fast = fast.unwrap_or(request.fast),
+      Err(e) => ::log::error!("failed to read fast mode for `{name}`: {e}"),
+    }
 
-    while let Some(message) = stream.next().await.transpose().context(error::Irc)? {
-      if let IrcCommand::AUTHENTICATE(ref param) = message.command
-        && param == "+"
-      {
-        break;
-      }
+    if let Some(msgid) = &request.msgid
+      && let Err(e) = outbox.react(&request.target, msgid, REACTION)
+    {
+      ::log::error!("failed to send reaction: {e}");
     }
 
-    let credentials = format!("\0{NICK}\0{password}");
-    let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
-    client.send_sasl(&encoded).context(error::Irc)?;
+    queues.push(name, request);
 
-    while let Some(message) = stream.next().await.transpose().context(error::Irc)? {
-      if let IrcCommand::Response(ref response, _) = message.command {
-        if *response == Response::RPL_SASLSUCCESS {
-          return Ok(());
+    true
+  }
+
+  fn bang(
+    db: &Database,
+    health: &irc::Health,
+    queues: &Queues<Request>,
+    name: &str,
+    fast: bool,
+    command: BangCommand,
+  ) -> Result<String> {
+    match command {
+      BangCommand::Cancel => {
+        let pending = queues.cancel(name);
+
+        let running = RUNNING.lock().unwrap().get(name).cloned();
+
+        let queued = if running.is_some() {
+          pending.saturating_sub(1)
+        } else {
+          pending
+        };
+
+        let killed = match running {
+          Some(session) => cancel_agent(&session)?,
+          None => false,
+        };
+
+        Ok(match (killed, queued) {
+          (false, 0) => format!("nothing to cancel for {name}"),
+          (true, queued) => {
+            format!("stopped the running agent and cancelled {queued} queued message(s) for {name}")
+          }
+          (false, queued) => format!("cancelled {queued} queued message(s) for {name}"),
+        })
+      }
+      BangCommand::Fast(fast) => {
+        Self::set_fast_mode(db, name, fast)?;
+
+        Ok(format!(
+          "fast mode {} for {name}",
+          if fast { "on" } else { "off" }
+        ))
+      }
+      BangCommand::Reset => {
+        reset_session(db, name)?;
+        Ok(format!("reset {name}"))
+      }
+      BangCommand::Resume(other) => {
+        if !owns(name, &other) {
+          return Ok(format!("no session named {other}"));
         }
-        if *response == Response::ERR_SASLFAIL {
-          return Err(Error::IrcProtocol {
-            message: "SASL authentication failed".to_string(),
-          });
+
+        let (session, resume) = lookup_session(db, &other)?;
+
+        if !resume {
+          return Ok(format!("no session named {other}"));
+        }
+
+        save_session(db, name, &session)?;
+
+        Ok(format!("{name} now resumes {other} ({session})"))
+      }
+      BangCommand::Sessions => {
+        let mut sessions = list_sessions(db)?;
+
+        sessions.retain(|other, _| owns(name, other));
+
+        if sessions.is_empty() {
+          return Ok("no sessions".into());
         }
+
+        Ok(
+          sessions
+            .iter()
+            .map(|(name, session)| format!("{name} {session}"))
+            .collect::<Vec<String>>()
+            .join("\n"),
+        )
+      }
+      BangCommand::Status => {
+        let (session, resume) = lookup_session(db, name)?;
+
+        let fast = Self::fast_mode(db, name)?.unwrap_or(fast);
+
+        Ok(format!(
+          "{name}: {}, fast mode {}, {} pending\nirc: {health}",
+          if resume {
+            session.as_str()
+          } else {
+            "no session"
+          },
+          if fast { "on" } else { "off" },
+          queues.pending(name),
+        ))
       }
     }
+  }
+
+  fn set_fast_mode(db: &Database, name: &str, fast: bool) -> Result {
+    let db = db.open()?;
 
-    Err(Error::IrcProtocol {
-      message: "connection closed during SASL authentication".to_string(),
-    })
+    let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
+    {
+      let mut table = write_txn
+        .open_table(FAST_MODE)
+        .context(error::DatabaseTable)?;
+      table.insert(name, fast).context(error::DatabaseStorage)?;
+    }
+    write_txn.commit().context(error::DatabaseCommit)?;
+
+    Ok(())
   }
 
-  fn handle_message(db: &Path, claude: &Path, sender: &str, text: &str) -> Result<String> {
-    let name = format!("chat:{sender}");
-    let (session, resume) = lookup_session(db, &name)?;
+  fn fast_mode(db: &Database, name: &str) -> Result<Option<bool>> {
+    let db = db.open()?;
+
+    let read_txn = db.begin_read().context(error::DatabaseTransaction)?;
+
+    let table = match read_txn.open_table(FAST_MODE) {
+      Ok(table) => table,
+      Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+      Err(e) => return Err(e).context(error::DatabaseTable),
+    };
+
+    Ok(
+      table
+        .get(name)
+        .context(error::DatabaseStorage)?
+        .map(|value| value.value()),
+    )
+  }
+
+  fn handle_message(
+    db: &Database,
+    claude: &Path,
+    fetch: &config::Fetch,
+    name: &str,
+    session: &str,
+    resume: bool,
+    request: &Request,
+  ) -> Result<String> {
+    let attachments = tokio::runtime::Handle::current().block_on(fetch::attach(
+      fetch,
+      &Path::new(SESSION_DIR).join(session),
+      &request.prompt,
+    ));
 
     let response = invoke_agent(
       claude,
       Path::new(SESSION_DIR),
-      &session,
+      session,
       resume,
-      text,
-      Some(&format!("You are chatting over IRC with {sender}.")),
-      true,
+      &format!("{}{}{attachments}", request.context, request.prompt),
+      Some(&request.system_prompt),
+      request.fast,
     )?;
 
     if !resume {
-      save_session(db, &name, &session)?;
+      save_session(db, name, session)?;
     }
 
     Ok(response)
   }
 
-  fn send_response(sender: &Sender, target: &str, response: &str) -> Result {
-    let response = markdown_to_plaintext(response);
+  fn send_response(
+    outbox: &irc::Outbox,
+    target: &str,
+    response: &str,
+    format: Format,
+    msgid: Option<&str>,
+    paster: Option<&Paster>,
+  ) -> Result {
+    let text = render_markdown(response, format);
+
+    if let Some(paster) = paster
+      && paster.should_paste(&text)
+    {
+      match paster.paste(response) {
+        Ok(url) => {
+          for line in Paster::summary(&text, &url) {
+            outbox.reply(target, &line, msgid)?;
+          }
+          return Ok(());
+        }
+        Err(e) => ::log::error!("failed to paste response: {e}"),
+      }
+    }
 
-    for line in response.lines() {
+    for line in text.lines() {
       let line = line.trim();
 
       if line.is_empty() {
         continue;
       }
 
-      for chunk in split_utf8(line, 400) {
-        sender.send_privmsg(target, chunk).context(error::Irc)?;
-      }
+      outbox.reply(target, line, msgid)?;
     }
     Ok(())
   }
 }
 
+fn owns(name: &str, other: &str) -> bool {
+  other
+    .strip_prefix(name)
+    .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+}
+
+fn addressed<'a>(text: &'a str, nick: &str) -> Option<&'a str> {
+  if let Some(prefix) = text.get(..nick.len())
+    && prefix.eq_ignore_ascii_case(nick)
+    && let Some(rest) = text[nick.len()..]
+      .strip_prefix(':')
+      .or_else(|| text[nick.len()..].strip_prefix(','))
+  {
+    return Some(rest.trim_start());
+  }
+
+  text
+    .split(|c: char| !c.is_alphanumeric() && c != '_' && c != '-')
+    .any(|word| word.eq_ignore_ascii_case(nick))
+    .then_some(text)
+}
+
 enum ListKind {
   Ordered(u64),
   Unordered,
@@ -204,7 +771,7 @@ struct TableState {
   current_cell: String,
 }
 
-fn markdown_to_plaintext(markdown: &str) -> String {
+fn render_markdown(markdown: &str, format: Format) -> String {
   use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
 
   let mut options = Options::empty();
@@ -230,7 +797,15 @@ fn markdown_to_plaintext(markdown: &str) -> String {
   let mut table_state: Option<TableState> = None;
   let mut at_line_start = true;
 
-  let blockquote_prefix = |depth: usize| -> String { "> ".repeat(depth) };
+  let irc = format == Format::Irc;
+
+  let blockquote_prefix = |depth: usize| -> String {
+    if irc && depth > 0 {
+      format!("{QUOTE_COLOR}{}", "> ".repeat(depth))
+    } else {
+      "> ".repeat(depth)
+    }
+  };
 
   for event in parser {
     match event {
@@ -243,11 +818,16 @@ fn markdown_to_plaintext(markdown: &str) -> String {
         if !output.is_empty() && !output.ends_with('\n') {
           output.push('\n');
         }
-        output.push_str(&heading_text.to_uppercase());
+        if irc {
+          output.push_str(&format!("{BOLD}{heading_text}{BOLD}"));
+        } else {
+          output.push_str(&heading_text.to_uppercase());
+        }
         output.push('\n');
         at_line_start = true;
       }
       Event::Start(Tag::Paragraph) => {}
+      #[allow(clippy::collapsible_match)]
       Event::End(TagEnd::Paragraph) => {
         if table_state.is_none() {
           if !output.is_empty() && !output.ends_with('\n') {
@@ -314,21 +894,10 @@ fn markdown_to_plaintext(markdown: &str) -> String {
       }
       Event::End(TagEnd::Link) => {
         in_link = false;
-        if in_heading {
-          heading_text.push_str(&link_text);
-          if link_url != link_text {
-            heading_text.push_str(&format!(" ({link_url})"));
-          }
-        } else if let Some(ref mut ts) = table_state {
-          ts.current_cell.push_str(&link_text);
-          if link_url != link_text {
-            ts.current_cell.push_str(&format!(" ({link_url})"));
-          }
-        } else {
-          output.push_str(&link_text);
-          if link_url != link_text {
-            output.push_str(&format!(" ({link_url})"));
-          }
+        let target = inline_target(in_heading, &mut heading_text, &mut table_state, &mut output);
+        target.push_str(&link_text);
+        if link_url != link_text {
+          target.push_str(&format!(" ({link_url})"));
         }
       }
       Event::Start(Tag::Image { dest_url, .. }) => {
@@ -343,36 +912,28 @@ fn markdown_to_plaintext(markdown: &str) -> String {
         } else {
           format!("{image_text} ({image_url})")
         };
-        if in_heading {
-          heading_text.push_str(&rendered);
-        } else if let Some(ref mut ts) = table_state {
-          ts.current_cell.push_str(&rendered);
-        } else {
-          output.push_str(&rendered);
-        }
+        inline_target(in_heading, &mut heading_text, &mut table_state, &mut output)
+          .push_str(&rendered);
+      }
+      Event::Start(Tag::Emphasis) | Event::End(TagEnd::Emphasis) if irc => {
+        inline_target(in_heading, &mut heading_text, &mut table_state, &mut output)
+          .push_str(ITALICS);
+      }
+      Event::Start(Tag::Strong) | Event::End(TagEnd::Strong) if irc => {
+        inline_target(in_heading, &mut heading_text, &mut table_state, &mut output).push_str(BOLD);
+      }
+      Event::Start(Tag::Strikethrough) | Event::End(TagEnd::Strikethrough) if irc => {
+        inline_target(in_heading, &mut heading_text, &mut table_state, &mut output)
+          .push_str(STRIKETHROUGH);
       }
       Event::Start(Tag::Emphasis | Tag::Strong | Tag::Strikethrough) => {}
       Event::End(TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough) => {}
       Event::Start(Tag::Superscript) => {
-        let target = if in_heading {
-          &mut heading_text
-        } else if let Some(ref mut ts) = table_state {
-          &mut ts.current_cell
-        } else {
-          &mut output
-        };
-        target.push('^');
+        inline_target(in_heading, &mut heading_text, &mut table_state, &mut output).push('^');
       }
       Event::End(TagEnd::Superscript) => {}
       Event::Start(Tag::Subscript) => {
-        let target = if in_heading {
-          &mut heading_text
-        } else if let Some(ref mut ts) = table_state {
-          &mut ts.current_cell
-        } else {
-          &mut output
-        };
-        target.push('_');
+        inline_target(in_heading, &mut heading_text, &mut table_state, &mut output).push('_');
       }
       Event::End(TagEnd::Subscript) => {}
       Event::Start(Tag::Table(_)) => {
@@ -429,12 +990,18 @@ fn markdown_to_plaintext(markdown: &str) -> String {
         } else if in_heading {
           heading_text.push_str(&text);
         } else if in_code_block {
-          if blockquote_depth > 0 {
+          if blockquote_depth > 0 || irc {
             for (i, line) in text.lines().enumerate() {
               if i > 0 || at_line_start {
                 output.push_str(&blockquote_prefix(blockquote_depth));
               }
+              if irc {
+                output.push_str(CODE_BLOCK);
+              }
               output.push_str(line);
+              if irc {
+                output.push_str(RESET);
+              }
               output.push('\n');
             }
           } else {
@@ -452,6 +1019,12 @@ fn markdown_to_plaintext(markdown: &str) -> String {
         }
       }
       Event::Code(text) => {
+        let text = if irc {
+          format!("{MONOSPACE}{text}{MONOSPACE}")
+        } else {
+          text.to_string()
+        };
+
         if in_link {
           link_text.push_str(&text);
         } else if in_image {
@@ -493,52 +1066,73 @@ fn markdown_to_plaintext(markdown: &str) -> String {
     output.pop();
   }
 
+  if irc {
+    output = carry_formatting(&output);
+  }
+
   output
 }
 
-pub(crate) fn split_utf8(s: &str, max_bytes: usize) -> Vec<&str> {
-  let mut chunks = Vec::new();
-  let mut start = 0;
-
-  while start < s.len() {
-    let remaining = &s[start..];
-
-    if remaining.len() <= max_bytes {
-      chunks.push(remaining);
-      break;
-    }
-
-    let mut end = start + max_bytes;
-
-    while !s.is_char_boundary(end) {
-      end -= 1;
-    }
+fn inline_target<'a>(
+  in_heading: bool,
+  heading_text: &'a mut String,
+  table_state: &'a mut Option<TableState>,
+  output: &'a mut String,
+) -> &'a mut String {
+  if in_heading {
+    heading_text
+  } else if let Some(ts) = table_state {
+    &mut ts.current_cell
+  } else {
+    output
+  }
+}
 
-    let at_boundary = end >= s.len() || s.as_bytes()[end] == b' ';
+fn carry_formatting(text: &str) -> String {
+  let mut active = Vec::<char>::new();
+  let mut output = String::with_capacity(text.len());
 
-    if !at_boundary && let Some(space) = s[start..end].rfind(' ') {
-      end = start + space;
+  for (i, line) in text.split('\n').enumerate() {
+    if i > 0 {
+      output.push('\n');
     }
 
-    chunks.push(&s[start..end]);
+    output.extend(&active);
+    output.push_str(line);
 
-    start = end;
-    if start < s.len() && s.as_bytes()[start] == b' ' {
-      start += 1;
+    for c in line.chars() {
+      match c {
+        '\x02' | '\x1d' | '\x11' | '\x1e' => match active.iter().position(|a| *a == c) {
+          Some(position) => {
+            active.remove(position);
+          }
+          None => active.push(c),
+        },
+        '\x0f' => active.clear(),
+        _ => {}
+      }
     }
   }
 
-  chunks
+  output
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
 
+  fn markdown_to_plaintext(markdown: &str) -> String {
+    render_markdown(markdown, Format::Plain)
+  }
+
+  fn markdown_to_irc(markdown: &str) -> String {
+    render_markdown(markdown, Format::Irc)
+  }
+
   #[test]
   fn session_resolution() {
     let dir = tempfile::TempDir::new().unwrap();
-    let db = dir.path().join("foo.redb");
+    let db = Database::new(dir.path().join("foo.redb"));
 
     let (session1, resume1) = lookup_session(&db, "chat:foo").unwrap();
     assert!(!resume1);
@@ -554,49 +1148,109 @@ mod tests {
   }
 
   #[test]
-  fn split_utf8_short() {
-    assert_eq!(split_utf8("foo", 400), vec!["foo"]);
-  }
+  fn bang_commands() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+    let queues = Queues::new(Duration::ZERO, |_: &str, _: Request| {});
 
-  #[test]
-  fn split_utf8_exact() {
-    let s = "a".repeat(400);
-    assert_eq!(split_utf8(&s, 400), vec![s.as_str()]);
-  }
+    let health = irc::Health::default();
 
-  #[test]
-  fn split_utf8_long() {
-    let s = "a".repeat(801);
-    let chunks = split_utf8(&s, 400);
-    assert_eq!(chunks.len(), 3);
-    assert_eq!(chunks[0].len(), 400);
-    assert_eq!(chunks[1].len(), 400);
-    assert_eq!(chunks[2].len(), 1);
+    let bang = |command| Chat::bang(&db, &health, &queues, "chat:foo", true, command).unwrap();
+
+    assert_eq!(
+      bang(BangCommand::Status),
+      "chat:foo: no session, fast mode on, 0 pending\n\
+       irc: disconnected after 0 failed attempt(s)"
+    );
+
+    assert_eq!(bang(BangCommand::Fast(false)), "fast mode off for chat:foo");
+
+    assert_eq!(
+      bang(BangCommand::Status),
+      "chat:foo: no session, fast mode off, 0 pending\n\
+       irc: disconnected after 0 failed attempt(s)"
+    );
+
+    assert_eq!(
+      bang(BangCommand::Resume("chat:foo:bar".into())),
+      "no session named chat:foo:bar"
+    );
+
+    save_session(&db, "chat:foo:bar", "baz").unwrap();
+
+    assert_eq!(
+      bang(BangCommand::Resume("chat:foo:bar".into())),
+      "chat:foo now resumes chat:foo:bar (baz)"
+    );
+
+    assert_eq!(
+      bang(BangCommand::Sessions),
+      "chat:foo baz\nchat:foo:bar baz"
+    );
+
+    assert_eq!(bang(BangCommand::Cancel), "nothing to cancel for chat:foo");
+
+    assert_eq!(bang(BangCommand::Reset), "reset chat:foo");
+
+    assert_eq!(
+      bang(BangCommand::Status),
+      "chat:foo: no session, fast mode off, 0 pending\n\
+       irc: disconnected after 0 failed attempt(s)"
+    );
   }
 
   #[test]
-  fn split_utf8_multibyte() {
-    let s = "\u{1F600}".repeat(101);
-    let chunks = split_utf8(&s, 400);
-    assert_eq!(chunks[0].len(), 400);
-    for chunk in &chunks {
-      assert!(chunk.len() <= 400);
+  fn bang_commands_are_scoped_to_sender() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+    let queues = Queues::new(Duration::ZERO, |_: &str, _: Request| {});
+
+    let health = irc::Health::default();
+
+    save_session(&db, "chat:foo", "a").unwrap();
+    save_session(&db, "chat:foobar", "b").unwrap();
+    save_session(&db, "chat:bar", "c").unwrap();
+    save_session(&db, "task:baz", "d").unwrap();
+
+    let bang = |command| Chat::bang(&db, &health, &queues, "chat:foo", true, command).unwrap();
+
+    assert_eq!(bang(BangCommand::Sessions), "chat:foo a");
+
+    for other in ["chat:foobar", "chat:bar", "task:baz"] {
+      assert_eq!(
+        bang(BangCommand::Resume(other.into())),
+        format!("no session named {other}"),
+      );
     }
-    let reassembled = chunks.join("");
-    assert_eq!(reassembled, s);
-  }
 
-  #[test]
-  fn split_utf8_word_boundary() {
-    let chunks = split_utf8("foo bar baz", 7);
-    assert_eq!(chunks, vec!["foo bar", "baz"]);
+    assert_eq!(lookup_session(&db, "chat:foo").unwrap().0, "a");
   }
 
   #[test]
-  fn split_utf8_no_space_fallback() {
-    let s = "a".repeat(10);
-    let chunks = split_utf8(&s, 4);
-    assert_eq!(chunks, vec!["aaaa", "aaaa", "aa"]);
+  fn cancel_running_turn() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+    let queues = Queues::new(Duration::ZERO, |_: &str, _: Request| {});
+
+    let health = irc::Health::default();
+
+    let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+
+    AGENTS.lock().unwrap().insert("qux".into(), child.id());
+    RUNNING
+      .lock()
+      .unwrap()
+      .insert("chat:baz".into(), "qux".into());
+
+    assert_eq!(
+      Chat::bang(&db, &health, &queues, "chat:baz", true, BangCommand::Cancel).unwrap(),
+      "stopped the running agent and cancelled 0 queued message(s) for chat:baz",
+    );
+
+    assert!(child.wait().unwrap().signal().is_some());
+
+    AGENTS.lock().unwrap().remove("qux");
+    RUNNING.lock().unwrap().remove("chat:baz");
   }
 
   #[test]
@@ -703,4 +1357,156 @@ mod tests {
       "restarted ~1 day ago"
     );
   }
+
+  #[test]
+  fn irc_inline_formatting() {
+    assert_eq!(markdown_to_irc("**foo**"), "\x02foo\x02");
+    assert_eq!(markdown_to_irc("*foo*"), "\x1dfoo\x1d");
+    assert_eq!(markdown_to_irc("`foo`"), "\x11foo\x11");
+    assert_eq!(markdown_to_irc("~~foo~~"), "\x1efoo\x1e");
+  }
+
+  #[test]
+  fn irc_headings() {
+    assert_eq!(markdown_to_irc("# foo"), "\x02foo\x02");
+    assert_eq!(markdown_to_irc("## foo `bar`"), "\x02foo \x11bar\x11\x02");
+  }
+
+  #[test]
+  fn irc_unordered_list() {
+    assert_eq!(
+      markdown_to_irc("- foo\n- *bar*\n- baz"),
+      "- foo\n- \x1dbar\x1d\n- baz"
+    );
+  }
+
+  #[test]
+  fn irc_ordered_list() {
+    assert_eq!(
+      markdown_to_irc("1. foo\n2. bar\n3. baz"),
+      "1. foo\n2. bar\n3. baz"
+    );
+  }
+
+  #[test]
+  fn irc_blockquote() {
+    assert_eq!(markdown_to_irc("> foo"), "\x0303,99> foo");
+  }
+
+  #[test]
+  fn irc_nested_blockquote() {
+    assert_eq!(markdown_to_irc("> > foo"), "\x0303,99> > foo");
+  }
+
+  #[test]
+  fn irc_link() {
+    assert_eq!(markdown_to_irc("[foo](http://bar)"), "foo (http://bar)");
+  }
+
+  #[test]
+  fn irc_link_same_text_and_url() {
+    assert_eq!(markdown_to_irc("<http://foo>"), "http://foo");
+  }
+
+  #[test]
+  fn irc_image() {
+    assert_eq!(markdown_to_irc("![foo](http://bar)"), "foo (http://bar)");
+  }
+
+  #[test]
+  fn irc_code_block() {
+    assert_eq!(
+      markdown_to_irc("```\nfoo\nbar\n```"),
+      "\x11\x0310,99foo\x0f\n\x11\x0310,99bar\x0f"
+    );
+    assert_eq!(
+      markdown_to_irc("```\n,12 foo\n```"),
+      "\x11\x0310,99,12 foo\x0f"
+    );
+  }
+
+  #[test]
+  fn irc_formatting_spans_lines() {
+    assert_eq!(
+      markdown_to_irc("**foo\nbar** baz\n*qux*"),
+      "\x02foo\n\x02bar\x02 baz\n\x1dqux\x1d"
+    );
+    assert_eq!(
+      markdown_to_irc("*foo **bar\nbaz** qux*"),
+      "\x1dfoo \x02bar\n\x1d\x02baz\x02 qux\x1d"
+    );
+    assert_eq!(
+      markdown_to_irc("**foo**\n\n```\nbar\n```\n\nbaz"),
+      "\x02foo\x02\n\x11\x0310,99bar\x0f\nbaz"
+    );
+  }
+
+  #[test]
+  fn irc_quoted_code_block() {
+    assert_eq!(
+      markdown_to_irc("> ```\n> foo\n> ```"),
+      "\x0303,99> \x11\x0310,99foo\x0f"
+    );
+  }
+
+  #[test]
+  fn irc_table() {
+    assert_eq!(
+      markdown_to_irc("| a | **bb** |\n|---|----|\n| c | d  |"),
+      "a | \x02bb\x02\nc | d"
+    );
+  }
+
+  #[test]
+  fn irc_horizontal_rule() {
+    assert_eq!(markdown_to_irc("foo\n\n---\n\nbar"), "foo\n---\nbar");
+  }
+
+  #[test]
+  fn irc_mixed_content() {
+    assert_eq!(
+      markdown_to_irc("# foo\n\nbar **baz**"),
+      "\x02foo\x02\nbar \x02baz\x02"
+    );
+  }
+
+  #[test]
+  fn irc_superscript() {
+    assert_eq!(markdown_to_irc("foo ^bar^"), "foo ^bar");
+  }
+
+  #[test]
+  fn irc_subscript() {
+    assert_eq!(markdown_to_irc("foo ~bar~"), "foo _bar");
+  }
+
+  #[test]
+  fn irc_tilde_preserved() {
+    assert_eq!(
+      markdown_to_irc("restarted ~1 day ago"),
+      "restarted ~1 day ago"
+    );
+  }
+
+  #[test]
+  fn addressed_prefix() {
+    assert_eq!(addressed("root: hello", "root"), Some("hello"));
+    assert_eq!(addressed("Root, hello", "root"), Some("hello"));
+  }
+
+  #[test]
+  fn addressed_mention() {
+    assert_eq!(
+      addressed("ask root about it", "root"),
+      Some("ask root about it")
+    );
+    assert_eq!(addressed("hey @root!", "root"), Some("hey @root!"));
+  }
+
+  #[test]
+  fn addressed_not_mentioned() {
+    assert_eq!(addressed("hello", "root"), None);
+    assert_eq!(addressed("rooted in history", "root"), None);
+    assert_eq!(addressed("chroot: nope", "root"), None);
+  }
 }
@@ -0,0 +1,28 @@
+use super::*;
+
+pub(super) fn account(message: &IrcMessage) -> Option<String> {
+  message
+    .tags
+    .iter()
+    .flatten()
+    .find(|tag| tag.0 == "account")
+    .and_then(|tag| tag.1.clone())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn message(line: &str) -> IrcMessage {
+    line.parse().unwrap()
+  }
+
+  #[test]
+  fn tag() {
+    assert_eq!(
+      account(&message("@account=foo :bar!u@h PRIVMSG root :hi")),
+      Some("foo".into()),
+    );
+    assert_eq!(account(&message(":bar!u@h PRIVMSG root :hi")), None);
+  }
+}
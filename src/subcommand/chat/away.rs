@@ -0,0 +1,131 @@
+use {
+  super::*,
+  std::sync::{
+    Weak,
+    atomic::{AtomicUsize, Ordering},
+  },
+};
+
+const BUSY: &str = "busy";
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+pub(super) struct Away(Arc<Inner>);
+
+struct Inner {
+  active: AtomicUsize,
+  current: Mutex<Option<String>>,
+  db: Database,
+  outbox: irc::Outbox,
+}
+
+pub(super) struct Turn(Away);
+
+impl Away {
+  pub(super) fn new(db: Database, outbox: irc::Outbox) -> Self {
+    let away = Self(Arc::new(Inner {
+      active: AtomicUsize::new(0),
+      current: Mutex::new(None),
+      db,
+      outbox,
+    }));
+
+    tokio::spawn(Self::poll(Arc::downgrade(&away.0)));
+
+    away
+  }
+
+  async fn poll(inner: Weak<Inner>) {
+    let mut interval =
+      tokio::time::interval_at(tokio::time::Instant::now() + POLL_INTERVAL, POLL_INTERVAL);
+
+    loop {
+      interval.tick().await;
+
+      let Some(inner) = inner.upgrade() else {
+        break;
+      };
+
+      if tokio::task::spawn_blocking(move || Self(inner).refresh())
+        .await
+        .is_err()
+      {
+        ::log::error!("away status refresh panicked");
+      }
+    }
+  }
+
+  pub(super) fn reset(&self) {
+    *self.0.current.lock().unwrap() = None;
+  }
+
+  pub(super) fn turn(&self) -> Turn {
+    self.0.active.fetch_add(1, Ordering::SeqCst);
+    self.refresh();
+    Turn(self.clone())
+  }
+
+  fn refresh(&self) {
+    let mut current = self.0.current.lock().unwrap();
+
+    let status = if self.0.active.load(Ordering::SeqCst) > 0 {
+      Some(BUSY.into())
+    } else {
+      match mood::load(&self.0.db) {
+        Ok(mood) => mood,
+        Err(err) => {
+          ::log::error!("failed to load mood: {err}");
+          return;
+        }
+      }
+    };
+
+    if *current == status {
+      return;
+    }
+
+    match self.0.outbox.send(IrcCommand::AWAY(status.clone())) {
+      Ok(()) => *current = status,
+      Err(err) => ::log::error!("failed to set away status: {err}"),
+    }
+  }
+}
+
+impl Drop for Turn {
+  fn drop(&mut self) {
+    self.0.0.active.fetch_sub(1, Ordering::SeqCst);
+    self.0.refresh();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn away() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let (outbox, mut receiver) = irc::Outbox::test(false);
+
+    let away = Away::new(db.clone(), outbox);
+
+    away.refresh();
+    assert!(receiver.is_empty());
+
+    mood::save(&db, "😀").unwrap();
+    away.refresh();
+    assert_eq!(receiver.try_recv().unwrap().to_string(), "AWAY 😀\r\n");
+
+    let turn = away.turn();
+    assert_eq!(receiver.try_recv().unwrap().to_string(), "AWAY busy\r\n");
+
+    let nested = away.turn();
+    drop(turn);
+    assert!(receiver.is_empty());
+
+    drop(nested);
+    assert_eq!(receiver.try_recv().unwrap().to_string(), "AWAY 😀\r\n");
+  }
+}
@@ -0,0 +1,83 @@
+#[derive(Debug, PartialEq)]
+pub(super) enum BangCommand {
+  Cancel,
+  Fast(bool),
+  Reset,
+  Resume(String),
+  Sessions,
+  Status,
+}
+
+impl BangCommand {
+  pub(super) const USAGE: &str =
+    "commands: !reset, !status, !sessions, !cancel, !fast on|off, !resume <name>";
+
+  pub(super) fn parse(text: &str) -> Option<Option<Self>> {
+    let text = text.trim().strip_prefix('!')?;
+
+    let mut words = text.split_whitespace();
+
+    let command = words.next().unwrap_or_default();
+    let argument = words.next();
+
+    if words.next().is_some() {
+      return Some(None);
+    }
+
+    Some(match (command, argument) {
+      ("cancel", None) => Some(Self::Cancel),
+      ("fast", Some("on")) => Some(Self::Fast(true)),
+      ("fast", Some("off")) => Some(Self::Fast(false)),
+      ("reset", None) => Some(Self::Reset),
+      ("resume", Some(name)) => Some(Self::Resume(name.into())),
+      ("sessions", None) => Some(Self::Sessions),
+      ("status", None) => Some(Self::Status),
+      _ => None,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse() {
+    assert_eq!(BangCommand::parse("hello"), None);
+    assert_eq!(BangCommand::parse("!reset"), Some(Some(BangCommand::Reset)));
+    assert_eq!(
+      BangCommand::parse(" !status "),
+      Some(Some(BangCommand::Status))
+    );
+    assert_eq!(
+      BangCommand::parse("!sessions"),
+      Some(Some(BangCommand::Sessions))
+    );
+    assert_eq!(
+      BangCommand::parse("!cancel"),
+      Some(Some(BangCommand::Cancel))
+    );
+    assert_eq!(
+      BangCommand::parse("!fast on"),
+      Some(Some(BangCommand::Fast(true)))
+    );
+    assert_eq!(
+      BangCommand::parse("!fast off"),
+      Some(Some(BangCommand::Fast(false)))
+    );
+    assert_eq!(
+      BangCommand::parse("!resume task:foo"),
+      Some(Some(BangCommand::Resume("task:foo".into())))
+    );
+  }
+
+  #[test]
+  fn parse_invalid() {
+    assert_eq!(BangCommand::parse("!"), Some(None));
+    assert_eq!(BangCommand::parse("!fast"), Some(None));
+    assert_eq!(BangCommand::parse("!fast maybe"), Some(None));
+    assert_eq!(BangCommand::parse("!resume"), Some(None));
+    assert_eq!(BangCommand::parse("!reset now please"), Some(None));
+    assert_eq!(BangCommand::parse("!foo"), Some(None));
+  }
+}
@@ -0,0 +1,501 @@
+use {
+  super::*,
+  reqwest::{
+    Url,
+    header::{CONTENT_TYPE, LOCATION},
+    redirect,
+  },
+};
+
+const ATTACHMENTS: &str = "attachments";
+const MAX_REDIRECTS: usize = 5;
+
+pub(super) struct Attachment {
+  path: PathBuf,
+  content_type: String,
+  size: usize,
+}
+
+pub(super) fn urls(text: &str) -> Vec<&str> {
+  let mut urls = Vec::new();
+
+  for word in text.split_whitespace() {
+    let word = word.trim_start_matches(['<', '(', '[', '"', '\'']);
+
+    if !word.starts_with("http://") && !word.starts_with("https://") {
+      continue;
+    }
+
+    let url = word.trim_end_matches(['.', ',', ';', ':', '!', '?', ')', ']', '>', '"', '\'']);
+
+    if !urls.contains(&url) {
+      urls.push(url);
+    }
+  }
+
+  urls
+}
+
+pub(super) async fn attach(config: &config::Fetch, session_dir: &Path, text: &str) -> String {
+  let urls = urls(text);
+
+  if !config.enabled || urls.is_empty() {
+    return String::new();
+  }
+
+  let mut note =
+    String::from("\n\nLinks in this message were fetched into your working directory:\n");
+
+  for url in urls.into_iter().take(config.max_urls) {
+    match fetch(config, session_dir, url).await {
+      Ok(attachment) => note.push_str(&format!(
+        "- {url} saved to {} ({}, {} bytes)\n",
+        attachment.path.display(),
+        attachment.content_type,
+        attachment.size,
+      )),
+      Err(err) => {
+        ::log::warn!("{err}");
+        note.push_str(&format!("- {url} was not fetched: {err}\n"));
+      }
+    }
+  }
+
+  note
+}
+
+async fn fetch(config: &config::Fetch, session_dir: &Path, url: &str) -> Result<Attachment> {
+  let mut response = get(config, url)
+    .await?
+    .error_for_status()
+    .context(error::Fetch { url })?;
+
+  let content_type = response
+    .headers()
+    .get(CONTENT_TYPE)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.split(';').next())
+    .map(|value| value.trim().to_ascii_lowercase())
+    .unwrap_or_default();
+
+  if !config
+    .types
+    .iter()
+    .any(|prefix| content_type.starts_with(prefix.as_str()))
+  {
+    return Err(Error::FetchRejected {
+      url: url.into(),
+      reason: format!("unsupported content type `{content_type}`"),
+    });
+  }
+
+  let too_large = || Error::FetchRejected {
+    url: url.into(),
+    reason: format!("larger than {} bytes", config.max_bytes),
+  };
+
+  if response
+    .content_length()
+    .is_some_and(|length| length > config.max_bytes as u64)
+  {
+    return Err(too_large());
+  }
+
+  let mut body = Vec::new();
+
+  while let Some(chunk) = response.chunk().await.context(error::Fetch { url })? {
+    body.extend_from_slice(&chunk);
+
+    if body.len() > config.max_bytes {
+      return Err(too_large());
+    }
+  }
+
+  let dir = session_dir.join(ATTACHMENTS);
+
+  fs::create_dir_all(&dir).context(error::FilesystemIo { path: &dir })?;
+
+  let count = fs::read_dir(&dir)
+    .context(error::FilesystemIo { path: &dir })?
+    .count();
+
+  let path = Path::new(ATTACHMENTS).join(format!("{}-{}", count + 1, filename(url)));
+
+  let destination = session_dir.join(&path);
+
+  fs::write(&destination, &body).context(error::FilesystemIo { path: &destination })?;
+
+  Ok(Attachment {
+    path,
+    content_type,
+    size: body.len(),
+  })
+}
+
+async fn get(config: &config::Fetch, url: &str) -> Result<reqwest::Response> {
+  let rejected = |reason: String| Error::FetchRejected {
+    url: url.into(),
+    reason,
+  };
+
+  let mut next = Url::parse(url).map_err(|err| rejected(format!("invalid URL: {err}")))?;
+
+  for _ in 0..=MAX_REDIRECTS {
+    let response = client(config, &next)
+      .await
+      .map_err(rejected)?
+      .get(next.clone())
+      .timeout(Duration::from_secs(config.timeout))
+      .send()
+      .await
+      .context(error::Fetch { url })?;
+
+    if !response.status().is_redirection() {
+      return Ok(response);
+    }
+
+    let Some(location) = response
+      .headers()
+      .get(LOCATION)
+      .and_then(|location| location.to_str().ok())
+    else {
+      return Ok(response);
+    };
+
+    next = next
+      .join(location)
+      .map_err(|err| rejected(format!("invalid redirect: {err}")))?;
+  }
+
+  Err(rejected(format!("more than {MAX_REDIRECTS} redirects")))
+}
+
+async fn client(config: &config::Fetch, url: &Url) -> Result<reqwest::Client, String> {
+  if !matches!(url.scheme(), "http" | "https") {
+    return Err(format!("unsupported scheme `{}`", url.scheme()));
+  }
+
+  let port = url.port_or_known_default().unwrap_or(80);
+
+  let host = url.host_str().ok_or("missing host")?;
+
+  let (domain, addresses) = match host
+    .trim_start_matches('[')
+    .trim_end_matches(']')
+    .parse::<IpAddr>()
+  {
+    Ok(ip) => (None, vec![SocketAddr::new(ip, port)]),
+    Err(_) => (
+      Some(host),
+      tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|err| format!("failed to resolve `{host}`: {err}"))?
+        .collect::<Vec<SocketAddr>>(),
+    ),
+  };
+
+  if addresses.is_empty() {
+    return Err("host has no addresses".into());
+  }
+
+  for address in &addresses {
+    if !public(address.ip()) && !config.allow.contains(&address.ip()) {
+      return Err(format!("non-public address {}", address.ip()));
+    }
+  }
+
+  let mut builder = reqwest::Client::builder().redirect(redirect::Policy::none());
+
+  if let Some(domain) = domain {
+    builder = builder.resolve_to_addrs(domain, &addresses);
+  }
+
+  builder.build().map_err(|err| err.to_string())
+}
+
+fn public(ip: IpAddr) -> bool {
+  match ip {
+    IpAddr::V4(ip) => {
+      let [a, b, ..] = ip.octets();
+      !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || a == 0
+        || (a == 100 && (64..128).contains(&b)))
+    }
+    IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+      Some(ip) => public(ip.into()),
+      None => {
+        !(ip.is_loopback()
+          || ip.is_unspecified()
+          || ip.is_multicast()
+          || ip.is_unique_local()
+          || ip.is_unicast_link_local())
+      }
+    },
+  }
+}
+
+fn filename(url: &str) -> String {
+  let path = url
+    .split_once("://")
+    .map_or(url, |(_, rest)| rest)
+    .split(['?', '#'])
+    .next()
+    .unwrap_or_default();
+
+  let name = path
+    .split_once('/')
+    .map_or("", |(_, path)| path)
+    .rsplit('/')
+    .next()
+    .unwrap_or_default()
+    .chars()
+    .map(|c| {
+      if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+        c
+      } else {
+        '_'
+      }
+    })
+    .collect::<String>();
+
+  let name = name.trim_start_matches('.');
+
+  if name.is_empty() {
+    "index".into()
+  } else {
+    name.into()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    axum::{Router, http::header, response::Redirect, routing::get},
+  };
+
+  fn config() -> config::Fetch {
+    config::Fetch {
+      allow: vec![IpAddr::from([127, 0, 0, 1])],
+      max_bytes: 100,
+      ..config::Fetch::default()
+    }
+  }
+
+  async fn server() -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+    let address = listener.local_addr().unwrap();
+
+    let router = Router::new()
+      .route(
+        "/foo.png",
+        get(|| async { ([(header::CONTENT_TYPE, "image/png")], "png") }),
+      )
+      .route(
+        "/bar",
+        get(|| async { ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], "bar") }),
+      )
+      .route(
+        "/big.txt",
+        get(|| async { ([(header::CONTENT_TYPE, "text/plain")], "a".repeat(101)) }),
+      )
+      .route(
+        "/foo.bin",
+        get(|| async { ([(header::CONTENT_TYPE, "application/octet-stream")], "bin") }),
+      )
+      .route("/redirect", get(|| async { Redirect::temporary("/bar") }))
+      .route(
+        "/metadata",
+        get(|| async { Redirect::temporary("http://169.254.169.254/latest/meta-data") }),
+      );
+
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    format!("http://{address}")
+  }
+
+  #[test]
+  fn urls() {
+    assert_eq!(
+      super::urls("see https://foo.com/a, (http://bar.com/b) and <https://baz.com>."),
+      ["https://foo.com/a", "http://bar.com/b", "https://baz.com"],
+    );
+    assert_eq!(
+      super::urls("https://foo.com https://foo.com ftp://bar.com"),
+      ["https://foo.com"]
+    );
+    assert!(super::urls("foo bar").is_empty());
+  }
+
+  #[test]
+  fn filename() {
+    assert_eq!(super::filename("https://foo.com/a/b.png?c=d#e"), "b.png");
+    assert_eq!(super::filename("https://foo.com"), "index");
+    assert_eq!(super::filename("https://foo.com/"), "index");
+    assert_eq!(super::filename("https://foo.com/a b/.."), "index");
+    assert_eq!(super::filename("https://foo.com/a%20b.pdf"), "a_20b.pdf");
+  }
+
+  #[tokio::test]
+  async fn attach() {
+    let server = server().await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+
+    let note = super::attach(
+      &config(),
+      dir.path(),
+      &format!("{server}/foo.png {server}/bar {server}/big.txt {server}/foo.bin {server}/baz"),
+    )
+    .await;
+
+    assert_eq!(
+      note,
+      format!(
+        "\n\nLinks in this message were fetched into your working directory:\n\
+         - {server}/foo.png saved to attachments/1-foo.png (image/png, 3 bytes)\n\
+         - {server}/bar saved to attachments/2-bar (text/plain, 3 bytes)\n\
+         - {server}/big.txt was not fetched: refusing to fetch `{server}/big.txt`: \
+         larger than 100 bytes\n"
+      ),
+    );
+
+    assert_eq!(
+      fs::read_to_string(dir.path().join("attachments/1-foo.png")).unwrap(),
+      "png"
+    );
+    assert_eq!(
+      fs::read_to_string(dir.path().join("attachments/2-bar")).unwrap(),
+      "bar"
+    );
+  }
+
+  #[tokio::test]
+  async fn attach_rejected() {
+    let server = server().await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+
+    let note = super::attach(
+      &config(),
+      dir.path(),
+      &format!("{server}/foo.bin {server}/baz"),
+    )
+    .await;
+
+    assert_eq!(
+      note,
+      format!(
+        "\n\nLinks in this message were fetched into your working directory:\n\
+         - {server}/foo.bin was not fetched: refusing to fetch `{server}/foo.bin`: \
+         unsupported content type `application/octet-stream`\n\
+         - {server}/baz was not fetched: failed to fetch `{server}/baz`\n"
+      ),
+    );
+
+    assert!(!dir.path().join("attachments").exists());
+  }
+
+  #[test]
+  fn public() {
+    for ip in [
+      "127.0.0.1",
+      "10.0.0.1",
+      "172.16.0.1",
+      "192.168.1.1",
+      "169.254.169.254",
+      "100.64.0.1",
+      "0.0.0.0",
+      "::1",
+      "::",
+      "fe80::1",
+      "fd00::1",
+      "::ffff:127.0.0.1",
+    ] {
+      assert!(!super::public(ip.parse().unwrap()), "{ip}");
+    }
+
+    for ip in ["1.1.1.1", "93.184.216.34", "2606:4700::1111"] {
+      assert!(super::public(ip.parse().unwrap()), "{ip}");
+    }
+  }
+
+  #[tokio::test]
+  async fn loopback_refused() {
+    let server = server().await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+
+    let note = super::attach(
+      &config::Fetch {
+        allow: Vec::new(),
+        ..config()
+      },
+      dir.path(),
+      &format!("{server}/bar http://169.254.169.254/ http://[::1]/"),
+    )
+    .await;
+
+    assert_eq!(
+      note,
+      format!(
+        "\n\nLinks in this message were fetched into your working directory:\n\
+         - {server}/bar was not fetched: refusing to fetch `{server}/bar`: \
+         non-public address 127.0.0.1\n\
+         - http://169.254.169.254/ was not fetched: refusing to fetch \
+         `http://169.254.169.254/`: non-public address 169.254.169.254\n\
+         - http://[::1]/ was not fetched: refusing to fetch `http://[::1]/`: \
+         non-public address ::1\n"
+      ),
+    );
+
+    assert!(!dir.path().join("attachments").exists());
+  }
+
+  #[tokio::test]
+  async fn redirects() {
+    let server = server().await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+
+    let note = super::attach(
+      &config(),
+      dir.path(),
+      &format!("{server}/redirect {server}/metadata"),
+    )
+    .await;
+
+    assert_eq!(
+      note,
+      format!(
+        "\n\nLinks in this message were fetched into your working directory:\n\
+         - {server}/redirect saved to attachments/1-redirect (text/plain, 3 bytes)\n\
+         - {server}/metadata was not fetched: refusing to fetch `{server}/metadata`: \
+         non-public address 169.254.169.254\n"
+      ),
+    );
+  }
+
+  #[tokio::test]
+  async fn attach_disabled() {
+    let dir = tempfile::TempDir::new().unwrap();
+
+    let disabled = config::Fetch {
+      enabled: false,
+      ..config()
+    };
+
+    assert_eq!(
+      super::attach(&disabled, dir.path(), "https://foo.com").await,
+      ""
+    );
+    assert_eq!(super::attach(&config(), dir.path(), "foo").await, "");
+  }
+}
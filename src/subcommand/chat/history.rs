@@ -0,0 +1,349 @@
+use {super::*, redb::ReadableTable, std::iter};
+
+const HISTORY: redb::TableDefinition<&str, &str> = redb::TableDefinition::new("chat_history");
+const TARGETS_LIMIT: usize = 50;
+const AFTER_LIMIT: usize = 100;
+
+#[derive(Clone)]
+struct Cursor {
+  timestamp: jiff::Timestamp,
+  msgids: Vec<String>,
+}
+
+impl Cursor {
+  fn parse(value: &str) -> Option<Self> {
+    let mut fields = value.split_whitespace();
+
+    Some(Self {
+      timestamp: fields.next()?.parse().ok()?,
+      msgids: fields.map(str::to_string).collect(),
+    })
+  }
+
+  fn covers(&self, timestamp: jiff::Timestamp, msgid: Option<&str>) -> bool {
+    timestamp < self.timestamp
+      || (timestamp == self.timestamp
+        && msgid.is_none_or(|msgid| self.msgids.iter().any(|seen| seen == msgid)))
+  }
+}
+
+#[derive(Clone)]
+pub(super) struct History(Arc<Mutex<State>>);
+
+struct State {
+  db: Database,
+  dispatched: HashSet<String>,
+  last: HashMap<String, Cursor>,
+}
+
+impl History {
+  pub(super) fn load(db: &Database) -> Result<Self> {
+    let handle = db.open()?;
+
+    let read_txn = handle.begin_read().context(error::DatabaseTransaction)?;
+
+    let mut last = HashMap::new();
+
+    match read_txn.open_table(HISTORY) {
+      Ok(table) => {
+        for entry in table.iter().context(error::DatabaseStorage)? {
+          let (target, cursor) = entry.context(error::DatabaseStorage)?;
+
+          if let Some(cursor) = Cursor::parse(cursor.value()) {
+            last.insert(target.value().to_string(), cursor);
+          }
+        }
+      }
+      Err(redb::TableError::TableDoesNotExist(_)) => {}
+      Err(e) => return Err(e).context(error::DatabaseTable),
+    }
+
+    Ok(Self(Arc::new(Mutex::new(State {
+      db: db.clone(),
+      dispatched: HashSet::new(),
+      last,
+    }))))
+  }
+
+  pub(super) fn timestamp(message: &IrcMessage) -> Option<jiff::Timestamp> {
+    message
+      .tags
+      .iter()
+      .flatten()
+      .find(|tag| tag.0 == "time")
+      .and_then(|tag| tag.1.as_deref())
+      .and_then(|time| time.parse().ok())
+  }
+
+  pub(super) fn handled(
+    &self,
+    target: &str,
+    timestamp: jiff::Timestamp,
+    msgid: Option<&str>,
+  ) -> bool {
+    let state = self.0.lock().unwrap();
+
+    msgid.is_some_and(|msgid| state.dispatched.contains(msgid))
+      || state
+        .last
+        .get(&target.to_lowercase())
+        .is_some_and(|cursor| cursor.covers(timestamp, msgid))
+  }
+
+  pub(super) fn dispatched(&self, msgid: Option<&str>) {
+    if let Some(msgid) = msgid {
+      self.0.lock().unwrap().dispatched.insert(msgid.into());
+    }
+  }
+
+  pub(super) fn record(
+    &self,
+    target: &str,
+    timestamp: jiff::Timestamp,
+    msgid: Option<&str>,
+  ) -> Result {
+    let target = target.to_lowercase();
+
+    let mut state = self.0.lock().unwrap();
+
+    if let Some(msgid) = msgid {
+      state.dispatched.remove(msgid);
+    }
+
+    let cursor = match state.last.get(&target) {
+      Some(cursor) if cursor.covers(timestamp, msgid) => return Ok(()),
+      Some(cursor) if cursor.timestamp == timestamp => {
+        let mut cursor = cursor.clone();
+        cursor.msgids.extend(msgid.map(str::to_string));
+        cursor
+      }
+      _ => Cursor {
+        timestamp,
+        msgids: msgid.map(str::to_string).into_iter().collect(),
+      },
+    };
+
+    let value = iter::once(cursor.timestamp.to_string())
+      .chain(cursor.msgids.iter().cloned())
+      .collect::<Vec<String>>()
+      .join(" ");
+
+    let db = state.db.open()?;
+
+    let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
+    {
+      let mut table = write_txn
+        .open_table(HISTORY)
+        .context(error::DatabaseTable)?;
+      table
+        .insert(target.as_str(), value.as_str())
+        .context(error::DatabaseStorage)?;
+    }
+    write_txn.commit().context(error::DatabaseCommit)?;
+
+    drop(db);
+
+    state.last.insert(target, cursor);
+
+    Ok(())
+  }
+
+  pub(super) fn targets(&self) -> Option<IrcCommand> {
+    let since = self
+      .0
+      .lock()
+      .unwrap()
+      .last
+      .values()
+      .map(|cursor| cursor.timestamp)
+      .max()?;
+
+    Some(IrcCommand::Raw(
+      "CHATHISTORY".into(),
+      vec![
+        "TARGETS".into(),
+        format!("timestamp={since}"),
+        format!("timestamp={}", jiff::Timestamp::now()),
+        TARGETS_LIMIT.to_string(),
+      ],
+    ))
+  }
+
+  pub(super) fn after(&self, message: &IrcMessage) -> Option<IrcCommand> {
+    let IrcCommand::Raw(command, args) = &message.command else {
+      return None;
+    };
+
+    if command != "CHATHISTORY" || args.first().map(String::as_str) != Some("TARGETS") {
+      return None;
+    }
+
+    let target = args.get(1)?;
+
+    let state = self.0.lock().unwrap();
+
+    let since = state
+      .last
+      .get(&target.to_lowercase())
+      .map(|cursor| cursor.timestamp)
+      .or_else(|| state.last.values().map(|cursor| cursor.timestamp).max())?;
+
+    Some(IrcCommand::Raw(
+      "CHATHISTORY".into(),
+      vec![
+        "AFTER".into(),
+        target.clone(),
+        format!("timestamp={since}"),
+        AFTER_LIMIT.to_string(),
+      ],
+    ))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn message(line: &str) -> IrcMessage {
+    line.parse().unwrap()
+  }
+
+  #[test]
+  fn timestamp() {
+    assert_eq!(
+      History::timestamp(&message(
+        "@time=2026-01-02T03:04:05.678Z :foo!u@h PRIVMSG root :hi"
+      )),
+      Some("2026-01-02T03:04:05.678Z".parse().unwrap()),
+    );
+    assert_eq!(
+      History::timestamp(&message(":foo!u@h PRIVMSG root :hi")),
+      None
+    );
+  }
+
+  #[test]
+  fn record() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let history = History::load(&db).unwrap();
+    assert!(history.targets().is_none());
+
+    let a = "2026-01-02T03:04:05Z".parse().unwrap();
+    let b = "2026-01-02T03:04:06Z".parse().unwrap();
+
+    assert!(!history.handled("foo", a, None));
+    history.record("foo", a, None).unwrap();
+    assert!(history.handled("foo", a, None));
+    assert!(!history.handled("foo", b, None));
+    history.record("Foo", b, None).unwrap();
+    assert!(history.handled("foo", a, None));
+    assert!(history.handled("FOO", b, None));
+
+    let history = History::load(&db).unwrap();
+    assert!(history.handled("foo", b, None));
+  }
+
+  #[test]
+  fn msgids() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let history = History::load(&db).unwrap();
+
+    let a = "2026-01-02T03:04:05Z".parse().unwrap();
+
+    history.dispatched(Some("x"));
+    assert!(history.handled("foo", a, Some("x")));
+    assert!(!history.handled("foo", a, Some("y")));
+
+    history.record("foo", a, Some("x")).unwrap();
+    assert!(history.handled("foo", a, Some("x")));
+    assert!(!history.handled("foo", a, Some("y")));
+
+    history.record("foo", a, Some("y")).unwrap();
+
+    let history = History::load(&db).unwrap();
+    assert!(history.handled("foo", a, Some("x")));
+    assert!(history.handled("foo", a, Some("y")));
+    assert!(!history.handled("foo", a, Some("z")));
+    assert!(history.handled("foo", a, None));
+  }
+
+  #[test]
+  fn targets_are_independent() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let history = History::load(&db).unwrap();
+
+    let a = "2026-01-02T03:04:05Z".parse().unwrap();
+    let b = "2026-01-02T03:04:06Z".parse().unwrap();
+
+    history.record("#foo", b, None).unwrap();
+
+    assert!(!history.handled("bar", a, None));
+    history.record("bar", a, None).unwrap();
+
+    assert!(history.handled("bar", a, None));
+    assert!(!history.handled("#foo", "2026-01-02T03:04:07Z".parse().unwrap(), None));
+
+    let history = History::load(&db).unwrap();
+    assert!(history.handled("bar", a, None));
+    assert!(history.handled("#foo", b, None));
+  }
+
+  #[test]
+  fn catch_up() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let history = History::load(&db).unwrap();
+
+    history
+      .record("foo", "2026-01-02T03:04:05Z".parse().unwrap(), None)
+      .unwrap();
+    history
+      .record("bar", "2026-01-02T03:04:07Z".parse().unwrap(), None)
+      .unwrap();
+
+    let IrcCommand::Raw(command, args) = history.targets().unwrap() else {
+      panic!();
+    };
+    assert_eq!(command, "CHATHISTORY");
+    assert_eq!(args[..2], ["TARGETS", "timestamp=2026-01-02T03:04:07Z"]);
+
+    assert_eq!(
+      history.after(&message(
+        ":server CHATHISTORY TARGETS foo 2026-01-02T03:05:00Z"
+      )),
+      Some(IrcCommand::Raw(
+        "CHATHISTORY".into(),
+        vec![
+          "AFTER".into(),
+          "foo".into(),
+          "timestamp=2026-01-02T03:04:05Z".into(),
+          "100".into(),
+        ],
+      )),
+    );
+
+    assert_eq!(
+      history.after(&message(
+        ":server CHATHISTORY TARGETS baz 2026-01-02T03:05:00Z"
+      )),
+      Some(IrcCommand::Raw(
+        "CHATHISTORY".into(),
+        vec![
+          "AFTER".into(),
+          "baz".into(),
+          "timestamp=2026-01-02T03:04:07Z".into(),
+          "100".into(),
+        ],
+      )),
+    );
+
+    assert_eq!(history.after(&message(":foo!u@h PRIVMSG root :hi")), None);
+  }
+}
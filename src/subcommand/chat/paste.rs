@@ -0,0 +1,248 @@
+use {
+  super::*,
+  axum::{
+    Router,
+    extract::{Path as UrlPath, State},
+    http::StatusCode,
+    response::{Html, IntoResponse},
+    routing::get,
+  },
+};
+
+const PASTES: redb::TableDefinition<&str, &str> = redb::TableDefinition::new("pastes");
+
+const STYLE: &str = "\
+body { font-family: sans-serif; max-width: 50em; margin: 2em auto; padding: 0 1em; }
+pre { overflow-x: auto; background: #f4f4f4; padding: 0.5em; }
+table { border-collapse: collapse; }
+td, th { border: 1px solid #ccc; padding: 0.25em 0.5em; }";
+
+const SUMMARY_LINES: usize = 3;
+
+pub(super) struct Paster {
+  pub(super) db: Database,
+  pub(super) config: config::Paste,
+}
+
+impl Paster {
+  pub(super) fn should_paste(&self, plaintext: &str) -> bool {
+    plaintext.len() > self.config.max_bytes
+      || plaintext
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .count()
+        > self.config.max_lines
+  }
+
+  pub(super) fn paste(&self, markdown: &str) -> Result<String> {
+    let id = uuid::Uuid::now_v7().simple().to_string();
+
+    let now = now();
+
+    let db = self.db.open()?;
+    let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
+    {
+      let mut table = write_txn.open_table(PASTES).context(error::DatabaseTable)?;
+      table
+        .retain(|id, _| !self.expired(id, now))
+        .context(error::DatabaseStorage)?;
+      table
+        .insert(id.as_str(), markdown)
+        .context(error::DatabaseStorage)?;
+    }
+    write_txn.commit().context(error::DatabaseCommit)?;
+
+    Ok(format!("{}/{id}", self.config.url.trim_end_matches('/')))
+  }
+
+  fn expired(&self, id: &str, now: u64) -> bool {
+    uuid::Uuid::try_parse(id)
+      .ok()
+      .and_then(|id| id.get_timestamp())
+      .is_none_or(|timestamp| timestamp.to_unix().0.saturating_add(self.config.ttl) < now)
+  }
+
+  pub(super) fn summary(plaintext: &str, url: &str) -> Vec<String> {
+    let mut lines = plaintext
+      .lines()
+      .map(str::trim)
+      .filter(|line| !line.is_empty())
+      .take(SUMMARY_LINES)
+      .map(str::to_string)
+      .collect::<Vec<String>>();
+
+    lines.push(format!("full response: {url}"));
+
+    lines
+  }
+
+  pub(super) async fn serve(self) -> Result {
+    let listener = tokio::net::TcpListener::bind(self.config.listen)
+      .await
+      .context(error::PasteServer)?;
+
+    let router = Router::new()
+      .route("/{id}", get(Self::get))
+      .with_state(Arc::new(self));
+
+    axum::serve(listener, router)
+      .await
+      .context(error::PasteServer)
+  }
+
+  async fn get(State(paster): State<Arc<Self>>, UrlPath(id): UrlPath<String>) -> impl IntoResponse {
+    let result = tokio::task::spawn_blocking({
+      let id = id.clone();
+      move || paster.render(&id)
+    })
+    .await;
+
+    match result {
+      Ok(Ok(Some(html))) => (StatusCode::OK, Html(html)),
+      Ok(Ok(None)) => (StatusCode::NOT_FOUND, Html("not found".into())),
+      Ok(Err(err)) => {
+        ::log::error!("failed to render paste `{id}`: {err}");
+        (
+          StatusCode::INTERNAL_SERVER_ERROR,
+          Html("internal error".into()),
+        )
+      }
+      Err(err) => {
+        ::log::error!("rendering paste `{id}` panicked: {err}");
+        (
+          StatusCode::INTERNAL_SERVER_ERROR,
+          Html("internal error".into()),
+        )
+      }
+    }
+  }
+
+  fn render(&self, id: &str) -> Result<Option<String>> {
+    if self.expired(id, now()) {
+      return Ok(None);
+    }
+
+    let db = self.db.open()?;
+
+    let read_txn = db.begin_read().context(error::DatabaseTransaction)?;
+
+    let table = match read_txn.open_table(PASTES) {
+      Ok(table) => table,
+      Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+      Err(e) => return Err(e).context(error::DatabaseTable),
+    };
+
+    let Some(markdown) = table.get(id).context(error::DatabaseStorage)? else {
+      return Ok(None);
+    };
+
+    Ok(Some(format!(
+      "<!DOCTYPE html>\n\
+       <html lang=\"en\">\n\
+       <head>\n\
+       <meta charset=\"utf-8\">\n\
+       <title>paste {id}</title>\n\
+       <style>\n{STYLE}\n</style>\n\
+       </head>\n\
+       <body>\n{}</body>\n\
+       </html>\n",
+      mail::Mail::markdown_to_escaped_html(markdown.value()),
+    )))
+  }
+}
+
+fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+  use {super::*, redb::ReadableTableMetadata};
+
+  fn paster(db: Database) -> Paster {
+    Paster {
+      db,
+      config: config::Paste {
+        listen: "127.0.0.1:0".parse().unwrap(),
+        url: "https://paste.example/".into(),
+        max_lines: 2,
+        max_bytes: 20,
+        ttl: 60,
+      },
+    }
+  }
+
+  #[test]
+  fn should_paste() {
+    let paster = paster(Database::new(PathBuf::new()));
+    assert!(!paster.should_paste("foo\n\nbar"));
+    assert!(paster.should_paste("foo\nbar\nbaz"));
+    assert!(paster.should_paste(&"a".repeat(21)));
+  }
+
+  #[test]
+  fn summary() {
+    assert_eq!(
+      Paster::summary("foo\n\n  bar\nbaz\nqux", "https://paste.example/a"),
+      [
+        "foo",
+        "bar",
+        "baz",
+        "full response: https://paste.example/a"
+      ],
+    );
+  }
+
+  #[test]
+  fn paste_and_render() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let paster = paster(Database::new(dir.path().join("foo.redb")));
+
+    assert_eq!(paster.render("foo").unwrap(), None);
+
+    let url = paster
+      .paste("# Foo\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n<script>bar</script>")
+      .unwrap();
+
+    let id = url.strip_prefix("https://paste.example/").unwrap();
+
+    let html = paster.render(id).unwrap().unwrap();
+    assert!(html.contains("<h1>Foo</h1>"));
+    assert!(html.contains("<table>"));
+    assert!(html.contains("&lt;script&gt;bar&lt;/script&gt;"));
+    assert!(!html.contains("<script>"));
+  }
+
+  #[test]
+  fn expired_pastes_are_pruned() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let paster = paster(Database::new(dir.path().join("foo.redb")));
+
+    let old = uuid::Uuid::new_v7(uuid::Timestamp::from_unix(uuid::NoContext, now() - 61, 0))
+      .simple()
+      .to_string();
+
+    {
+      let db = paster.db.open().unwrap();
+      let write_txn = db.begin_write().unwrap();
+      write_txn
+        .open_table(PASTES)
+        .unwrap()
+        .insert(old.as_str(), "foo")
+        .unwrap();
+      write_txn.commit().unwrap();
+    }
+
+    assert_eq!(paster.render(&old).unwrap(), None);
+
+    paster.paste("bar").unwrap();
+
+    let db = paster.db.open().unwrap();
+    let read_txn = db.begin_read().unwrap();
+    let table = read_txn.open_table(PASTES).unwrap();
+    assert!(table.get(old.as_str()).unwrap().is_none());
+    assert_eq!(table.len().unwrap(), 1);
+  }
+}
@@ -0,0 +1,170 @@
+use {
+  super::*,
+  std::{sync::mpsc, thread, time::Instant},
+};
+
+const TICK: Duration = Duration::from_secs(1);
+const TYPING_INTERVAL: Duration = Duration::from_secs(3);
+
+pub(super) struct Progress {
+  done: mpsc::Sender<()>,
+  thread: thread::JoinHandle<()>,
+}
+
+impl Progress {
+  pub(super) fn start(
+    config: &config::Progress,
+    outbox: irc::Outbox,
+    target: String,
+    session: &str,
+  ) -> Self {
+    let (done, receiver) = mpsc::channel();
+
+    let path = if config.tool_use {
+      match Self::create(session) {
+        Ok(path) => Some(path),
+        Err(err) => {
+          ::log::error!("failed to create progress file: {err}");
+          None
+        }
+      }
+    } else {
+      None
+    };
+
+    let config = config.clone();
+
+    let thread = thread::spawn(move || {
+      let start = Instant::now();
+      let mut typing = None::<Instant>;
+      let mut noticed = false;
+      let mut offset = 0;
+
+      loop {
+        if config.typing && typing.is_none_or(|typing| typing.elapsed() >= TYPING_INTERVAL) {
+          Self::typing(&outbox, &target, "active");
+          typing = Some(Instant::now());
+        }
+
+        if !noticed
+          && let Some(delay) = config.working_notice_delay
+          && start.elapsed() >= Duration::from_secs(delay)
+        {
+          outbox.notice(&target, "working…").ok();
+          noticed = true;
+        }
+
+        if let Some(path) = &path {
+          for line in read_lines(path, &mut offset) {
+            if let Some(chunk) = irc::split_utf8(&line, outbox.budget("NOTICE", &target)).first() {
+              outbox.notice(&target, chunk).ok();
+            }
+          }
+        }
+
+        match receiver.recv_timeout(TICK) {
+          Err(mpsc::RecvTimeoutError::Timeout) => {}
+          Ok(()) | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+      }
+
+      if config.typing {
+        Self::typing(&outbox, &target, "done");
+      }
+
+      if let Some(path) = path {
+        fs::remove_file(path).ok();
+      }
+    });
+
+    Self { done, thread }
+  }
+
+  fn create(session: &str) -> Result<PathBuf> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let path = progress_path(session)?;
+
+    fs::OpenOptions::new()
+      .write(true)
+      .create(true)
+      .truncate(true)
+      .mode(0o600)
+      .custom_flags(libc::O_NOFOLLOW)
+      .open(&path)
+      .context(error::FilesystemIo { path: &path })?;
+
+    Ok(path)
+  }
+
+  pub(super) fn finish(self) {
+    self.done.send(()).ok();
+
+    if self.thread.join().is_err() {
+      ::log::error!("progress thread panicked");
+    }
+  }
+
+  fn typing(outbox: &irc::Outbox, target: &str, state: &str) {
+    let message = IrcMessage::with_tags(
+      Some(vec![Tag("+typing".into(), Some(state.into()))]),
+      None,
+      "TAGMSG",
+      vec![target],
+    );
+
+    match message {
+      Ok(message) => {
+        outbox.send(message).ok();
+      }
+      Err(err) => ::log::error!("failed to build typing message: {err}"),
+    }
+  }
+}
+
+fn read_lines(path: &Path, offset: &mut usize) -> Vec<String> {
+  let Ok(content) = fs::read_to_string(path) else {
+    return Vec::new();
+  };
+
+  let Some(end) = content.rfind('\n').map(|end| end + 1) else {
+    return Vec::new();
+  };
+
+  if end <= *offset {
+    return Vec::new();
+  }
+
+  let lines = content[*offset..end]
+    .lines()
+    .filter(|line| !line.trim().is_empty())
+    .map(str::to_string)
+    .collect();
+
+  *offset = end;
+
+  lines
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn read_lines() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("progress");
+
+    let mut offset = 0;
+
+    assert!(super::read_lines(&path, &mut offset).is_empty());
+
+    fs::write(&path, "foo\nbar").unwrap();
+    assert_eq!(super::read_lines(&path, &mut offset), ["foo"]);
+
+    fs::write(&path, "foo\nbar\nbaz\n").unwrap();
+    assert_eq!(super::read_lines(&path, &mut offset), ["bar", "baz"]);
+
+    assert!(super::read_lines(&path, &mut offset).is_empty());
+  }
+}
@@ -0,0 +1,296 @@
+use super::*;
+
+use {
+  std::sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+  tokio::sync::mpsc,
+};
+
+const CONTINUATION_TIMEOUT: Duration = Duration::from_secs(120);
+
+type Handler<T> = Arc<dyn Fn(&str, T) + Send + Sync>;
+
+pub(super) trait Coalesce {
+  fn continued(&self) -> bool;
+
+  fn merge(&mut self, other: Self);
+}
+
+struct Worker<T> {
+  sender: mpsc::UnboundedSender<(u64, T)>,
+  generation: Arc<AtomicU64>,
+  pending: Arc<AtomicUsize>,
+}
+
+pub(super) struct Queues<T> {
+  handler: Handler<T>,
+  quiet: Duration,
+  workers: HashMap<String, Worker<T>>,
+}
+
+impl<T: Coalesce + Send + 'static> Queues<T> {
+  pub(super) fn new(quiet: Duration, handler: impl Fn(&str, T) + Send + Sync + 'static) -> Self {
+    Self {
+      handler: Arc::new(handler),
+      quiet,
+      workers: HashMap::new(),
+    }
+  }
+
+  pub(super) fn pending(&self, key: &str) -> usize {
+    self
+      .workers
+      .get(key)
+      .map_or(0, |worker| worker.pending.load(Ordering::SeqCst))
+  }
+
+  pub(super) fn cancel(&self, key: &str) -> usize {
+    match self.workers.get(key) {
+      Some(worker) => {
+        worker.generation.fetch_add(1, Ordering::SeqCst);
+        worker.pending.load(Ordering::SeqCst)
+      }
+      None => 0,
+    }
+  }
+
+  pub(super) fn push(&mut self, key: &str, item: T) {
+    let item = match self.workers.get(key) {
+      Some(worker) => {
+        worker.pending.fetch_add(1, Ordering::SeqCst);
+        match worker
+          .sender
+          .send((worker.generation.load(Ordering::SeqCst), item))
+        {
+          Ok(()) => return,
+          Err(mpsc::error::SendError((_, item))) => item,
+        }
+      }
+      None => item,
+    };
+
+    let (sender, mut receiver) = mpsc::unbounded_channel::<(u64, T)>();
+
+    sender.send((0, item)).ok();
+
+    let generation = Arc::new(AtomicU64::new(0));
+    let pending = Arc::new(AtomicUsize::new(1));
+
+    let handler = self.handler.clone();
+    let quiet = self.quiet;
+    let worker = key.to_string();
+
+    tokio::spawn({
+      let generation = generation.clone();
+      let pending = pending.clone();
+      async move {
+        while let Some((mut item_generation, mut item)) = receiver.recv().await {
+          let mut received = 1;
+
+          loop {
+            let timeout = if item.continued() {
+              CONTINUATION_TIMEOUT
+            } else {
+              quiet
+            };
+
+            if timeout.is_zero() {
+              break;
+            }
+
+            match tokio::time::timeout(timeout, receiver.recv()).await {
+              Ok(Some((next_generation, next))) => {
+                received += 1;
+
+                if next_generation == item_generation {
+                  item.merge(next);
+                } else {
+                  item_generation = next_generation;
+                  item = next;
+                }
+              }
+              Ok(None) | Err(_) => break,
+            }
+          }
+
+          if item_generation == generation.load(Ordering::SeqCst) {
+            let handler = handler.clone();
+            let key = worker.clone();
+
+            if let Err(err) = tokio::task::spawn_blocking(move || handler(&key, item)).await {
+              ::log::error!("worker for `{worker}` panicked: {err}");
+            }
+          }
+
+          pending.fetch_sub(received, Ordering::SeqCst);
+        }
+      }
+    });
+
+    self.workers.insert(
+      key.to_string(),
+      Worker {
+        sender,
+        generation,
+        pending,
+      },
+    );
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {super::*, std::sync::Mutex};
+
+  impl Coalesce for u64 {
+    fn continued(&self) -> bool {
+      false
+    }
+
+    fn merge(&mut self, _: Self) {
+      unreachable!("items are never coalesced without a quiet window");
+    }
+  }
+
+  impl Coalesce for String {
+    fn continued(&self) -> bool {
+      self.ends_with('\\')
+    }
+
+    fn merge(&mut self, other: Self) {
+      if self.continued() {
+        self.pop();
+      }
+      self.push('\n');
+      self.push_str(&other);
+    }
+  }
+
+  #[tokio::test]
+  async fn senders_run_concurrently_in_order() {
+    let done = Arc::new(Mutex::new(Vec::new()));
+
+    let mut queues = Queues::new(Duration::ZERO, {
+      let done = done.clone();
+      move |key: &str, item: u64| {
+        if key == "a" {
+          std::thread::sleep(Duration::from_millis(100));
+        }
+        done.lock().unwrap().push(format!("{key}{item}"));
+      }
+    });
+
+    queues.push("a", 1);
+    queues.push("a", 2);
+    queues.push("b", 1);
+
+    while done.lock().unwrap().len() < 3 {
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert_eq!(*done.lock().unwrap(), ["b1", "a1", "a2"]);
+  }
+
+  #[tokio::test]
+  async fn cancel_drops_pending_items() {
+    let done = Arc::new(Mutex::new(Vec::new()));
+
+    let mut queues = Queues::new(Duration::ZERO, {
+      let done = done.clone();
+      move |_: &str, item: u64| {
+        std::thread::sleep(Duration::from_millis(100));
+        done.lock().unwrap().push(item);
+      }
+    });
+
+    queues.push("a", 1);
+
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    queues.push("a", 2);
+    queues.push("a", 3);
+
+    assert_eq!(queues.pending("a"), 3);
+    assert_eq!(queues.cancel("a"), 3);
+
+    queues.push("a", 4);
+
+    while queues.pending("a") > 0 {
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert_eq!(*done.lock().unwrap(), [1, 4]);
+  }
+
+  #[tokio::test]
+  async fn coalesce_within_quiet_window() {
+    let done = Arc::new(Mutex::new(Vec::new()));
+
+    let mut queues = Queues::new(Duration::from_millis(50), {
+      let done = done.clone();
+      move |_: &str, item: String| {
+        done.lock().unwrap().push(item);
+      }
+    });
+
+    queues.push("a", "foo\\".into());
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    queues.push("a", "bar".into());
+    queues.push("a", "baz".into());
+
+    while queues.pending("a") > 0 {
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    queues.push("a", "qux".into());
+
+    while queues.pending("a") > 0 {
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    assert_eq!(*done.lock().unwrap(), ["foo\nbar\nbaz", "qux"]);
+  }
+
+  #[tokio::test]
+  async fn coalescing_loses_nothing() {
+    let done = Arc::new(Mutex::new(Vec::new()));
+
+    let mut queues = Queues::new(Duration::from_millis(50), {
+      let done = done.clone();
+      move |key: &str, item: String| {
+        std::thread::sleep(Duration::from_millis(20));
+        done.lock().unwrap().push(format!("{key}:{item}"));
+      }
+    });
+
+    for i in 0..20 {
+      queues.push("a", i.to_string());
+      queues.push("b", i.to_string());
+
+      if i % 5 == 0 {
+        tokio::time::sleep(Duration::from_millis(60)).await;
+      }
+    }
+
+    while queues.pending("a") + queues.pending("b") > 0 {
+      tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    for key in ["a", "b"] {
+      let received = done
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|item| item.strip_prefix(&format!("{key}:")))
+        .flat_map(|item| item.lines())
+        .map(str::to_string)
+        .collect::<Vec<String>>();
+
+      assert_eq!(
+        received,
+        (0..20).map(|i| i.to_string()).collect::<Vec<String>>()
+      );
+    }
+  }
+}
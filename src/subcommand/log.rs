@@ -119,6 +119,13 @@ impl Log {
       None => input.trim().to_owned(),
     };
 
+    if let Some(object) = object
+      && object.get("hook_event_name").and_then(|v| v.as_str()) == Some("PreToolUse")
+      && let Some(session) = object.get("session_id").and_then(|v| v.as_str())
+    {
+      Self::relay_progress(session, &message)?;
+    }
+
     let kvs = Fields(fields);
 
     #[cfg(target_os = "linux")]
@@ -146,11 +153,29 @@ impl Log {
 
     Ok(())
   }
+
+  fn relay_progress(session: &str, message: &str) -> Result {
+    use {io::Write, std::os::unix::fs::OpenOptionsExt};
+
+    let path = progress_path(session)?;
+
+    let mut file = match fs::OpenOptions::new()
+      .append(true)
+      .custom_flags(libc::O_NOFOLLOW)
+      .open(&path)
+    {
+      Ok(file) => file,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+      Err(err) => return Err(err).context(error::FilesystemIo { path }),
+    };
+
+    writeln!(file, "{}", message.replace('\n', " ")).context(error::FilesystemIo { path })
+  }
 }
 
 #[cfg(test)]
 mod tests {
-  use super::*;
+  use {super::*, std::os::unix::fs::PermissionsExt};
 
   fn parse(json: &str) -> serde_json::Map<String, serde_json::Value> {
     serde_json::from_str::<serde_json::Value>(json)
@@ -286,4 +311,41 @@ mod tests {
     );
     assert_eq!(build_message(&obj), "PreToolUse Read: /root/foo.rs");
   }
+
+  #[test]
+  fn relay_progress() {
+    let session = uuid::Uuid::now_v7().to_string();
+    let path = progress_path(&session).unwrap();
+
+    Log::relay_progress(&session, "PreToolUse Bash: ls").unwrap();
+    assert!(!path.exists());
+
+    fs::write(&path, "").unwrap();
+    Log::relay_progress(&session, "PreToolUse Bash: ls\n-la").unwrap();
+    assert_eq!(
+      fs::read_to_string(&path).unwrap(),
+      "PreToolUse Bash: ls -la\n"
+    );
+
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+      fs::metadata(path.parent().unwrap())
+        .unwrap()
+        .permissions()
+        .mode()
+        & 0o777,
+      0o700,
+    );
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let target = dir.path().join("target");
+    fs::write(&target, "").unwrap();
+    std::os::unix::fs::symlink(&target, &path).unwrap();
+
+    assert!(Log::relay_progress(&session, "foo").is_err());
+    assert_eq!(fs::read_to_string(&target).unwrap(), "");
+
+    fs::remove_file(path).unwrap();
+  }
 }
@@ -1,12 +1,34 @@
 use super::*;
 
-pub(super) const THREADS: redb::TableDefinition<&str, &str> = redb::TableDefinition::new("threads");
-const LOCAL_ADDRESS: &str = "root@tulip.farm";
+use {
+  mail_builder::{headers::content_type::ContentType, mime::MimePart},
+  redb::ReadableTable,
+};
+
+mod archive;
+mod index;
+mod search;
+mod send;
+
+const DELIVERY_FAILURES: redb::TableDefinition<&str, &str> =
+  redb::TableDefinition::new("delivery_failures");
+const THREADS: redb::TableDefinition<&str, &str> = redb::TableDefinition::new("threads");
+pub(super) const LOCAL_ADDRESS: &str = "root@tulip.farm";
+
+#[derive(clap::Subcommand)]
+enum Subcommand {
+  Archive(archive::Archive),
+  Search(search::Search),
+  Send(send::Send),
+}
 
 #[derive(clap::Args)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub(crate) struct Mail {
-  #[arg(long)]
-  dir: PathBuf,
+  #[command(subcommand)]
+  subcommand: Option<Subcommand>,
+  #[arg(long, required = true)]
+  dir: Option<PathBuf>,
   #[arg(long, default_value = "/run/wrappers/bin/sendmail")]
   sendmail: PathBuf,
   #[arg(long)]
@@ -15,14 +37,24 @@ pub(crate) struct Mail {
   claude: PathBuf,
   #[arg(long, default_value = SESSION_DIR)]
   session_dir: PathBuf,
+  #[arg(long)]
+  config: Option<PathBuf>,
 }
 
 impl Mail {
   pub(crate) fn run(self) -> Result {
+    if let Some(subcommand) = self.subcommand {
+      return match subcommand {
+        Subcommand::Archive(archive) => archive.run(),
+        Subcommand::Search(search) => search.run(),
+        Subcommand::Send(send) => send.run(),
+      };
+    }
+
     let mut raw = Vec::new();
     io::stdin().read_to_end(&mut raw).context(error::Stdin)?;
 
-    Self::save_to_maildir(&self.dir, &raw)?;
+    Self::save_to_maildir(self.dir(), &raw)?;
 
     let message = Message::parse(&raw)?;
 
@@ -30,12 +62,16 @@ impl Mail {
       return Ok(());
     }
 
+    if let Some(report) = &message.delivery_report {
+      return self.record_delivery_report(report);
+    }
+
     self.reply(&message)?;
 
     Ok(())
   }
 
-  pub(super) fn save_to_maildir(maildir: &Path, data: &[u8]) -> Result {
+  pub(super) fn save_to_maildir(maildir: &Path, data: &[u8]) -> Result<PathBuf> {
     for dir in ["cur", "new", "tmp"] {
       let path = maildir.join(dir);
       fs::create_dir_all(&path).context(error::FilesystemIo { path })?;
@@ -52,18 +88,21 @@ impl Mail {
 
     fs::write(&tmp, data).context(error::FilesystemIo { path: tmp.clone() })?;
 
-    fs::rename(&tmp, &new).context(error::FilesystemIo { path: new })?;
+    fs::rename(&tmp, &new).context(error::FilesystemIo { path: &new })?;
 
-    Ok(())
+    Ok(new)
+  }
+
+  fn dir(&self) -> &Path {
+    self.dir.as_deref().unwrap()
   }
 
-  fn db(&self) -> PathBuf {
-    self.db.clone().unwrap_or_else(db_path)
+  fn db(&self) -> Database {
+    Database::new(self.db.clone().unwrap_or_else(db_path))
   }
 
   fn resolve_session(&self, message: &Message) -> Result<(String, bool)> {
-    let db_path = self.db();
-    let db = redb::Database::create(&db_path).context(error::DatabaseOpen { path: db_path })?;
+    let db = self.db().open()?;
 
     let read_txn = db.begin_read().context(error::DatabaseTransaction)?;
     let table = read_txn.open_table(THREADS);
@@ -113,7 +152,128 @@ impl Mail {
     Ok((session, resume))
   }
 
+  fn record_delivery_report(&self, report: &DeliveryReport) -> Result {
+    let failures = report
+      .failures
+      .iter()
+      .map(|failure| match &failure.diagnostic {
+        Some(diagnostic) => format!("{} {} {diagnostic}", failure.recipient, failure.status),
+        None => format!("{} {}", failure.recipient, failure.status),
+      })
+      .collect::<Vec<String>>();
+
+    if failures.is_empty() {
+      return Ok(());
+    }
+
+    let mut session = None;
+
+    if let Some(original) = &report.original_message_id {
+      let db = self.db().open()?;
+
+      let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
+      {
+        let threads = write_txn
+          .open_table(THREADS)
+          .context(error::DatabaseTable)?;
+        session = threads
+          .get(original.as_str())
+          .context(error::DatabaseStorage)?
+          .map(|value| value.value().to_string());
+
+        let mut table = write_txn
+          .open_table(DELIVERY_FAILURES)
+          .context(error::DatabaseTable)?;
+        table
+          .insert(original.as_str(), failures.join("\n").as_str())
+          .context(error::DatabaseStorage)?;
+      }
+      write_txn.commit().context(error::DatabaseCommit)?;
+    }
+
+    let mut notification = format!(
+      "delivery failed: {}",
+      report
+        .original_message_id
+        .as_deref()
+        .unwrap_or("unknown message"),
+    );
+
+    if let Some(session) = session {
+      notification.push_str(&format!(" (session {session})"));
+    }
+
+    for failure in &failures {
+      notification.push('\n');
+      notification.push_str(failure);
+    }
+
+    let config =
+      config::Config::load_or_default(&self.config.clone().unwrap_or_else(config::Config::path));
+
+    if let Err(err) = notify::send(&config, &self.db(), "mail", &notification) {
+      ::log::error!("failed to send delivery failure notification: {err}");
+    }
+
+    Ok(())
+  }
+
   pub(super) fn markdown_to_html(markdown: &str) -> String {
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, Self::markdown_parser(markdown));
+    html
+  }
+
+  pub(super) fn markdown_to_escaped_html(markdown: &str) -> String {
+    use pulldown_cmark::{CowStr, Event, Tag};
+
+    fn safe(url: CowStr) -> CowStr {
+      let scheme = url
+        .split_once(':')
+        .map(|(scheme, _)| scheme.trim().to_ascii_lowercase());
+
+      match scheme.as_deref() {
+        Some("javascript" | "vbscript" | "data") => CowStr::Borrowed(""),
+        _ => url,
+      }
+    }
+
+    let mut html = String::new();
+
+    pulldown_cmark::html::push_html(
+      &mut html,
+      Self::markdown_parser(markdown).map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        Event::Start(Tag::Link {
+          link_type,
+          dest_url,
+          title,
+          id,
+        }) => Event::Start(Tag::Link {
+          link_type,
+          dest_url: safe(dest_url),
+          title,
+          id,
+        }),
+        Event::Start(Tag::Image {
+          link_type,
+          dest_url,
+          title,
+          id,
+        }) => Event::Start(Tag::Image {
+          link_type,
+          dest_url: safe(dest_url),
+          title,
+          id,
+        }),
+        event => event,
+      }),
+    );
+
+    html
+  }
+
+  fn markdown_parser(markdown: &str) -> pulldown_cmark::Parser<'_> {
     let options = pulldown_cmark::Options::ENABLE_TABLES
       | pulldown_cmark::Options::ENABLE_FOOTNOTES
       | pulldown_cmark::Options::ENABLE_STRIKETHROUGH
@@ -122,16 +282,26 @@ impl Mail {
       | pulldown_cmark::Options::ENABLE_DEFINITION_LIST
       | pulldown_cmark::Options::ENABLE_SUPERSCRIPT
       | pulldown_cmark::Options::ENABLE_SUBSCRIPT;
-    let parser = pulldown_cmark::Parser::new_ext(markdown, options);
-    let mut html = String::new();
-    pulldown_cmark::html::push_html(&mut html, parser);
-    html
+    pulldown_cmark::Parser::new_ext(markdown, options)
   }
 
   fn reply(&self, message: &Message) -> Result {
     let (session, resume) = self.resolve_session(message)?;
 
-    let body = strip_quoted_reply(&message.body, LOCAL_ADDRESS);
+    let mut body = strip_quoted_reply(&message.body, LOCAL_ADDRESS);
+
+    let invite = message
+      .invites
+      .iter()
+      .find(|invite| invite.method.as_deref() == Some("REQUEST"));
+
+    for invite in &message.invites {
+      body.push_str(&format!("\n\n{invite}"));
+    }
+
+    if invite.is_some() {
+      body.push_str(&format!("\n{}", calendar::RSVP_INSTRUCTIONS));
+    }
 
     let response = invoke_agent(
       &self.claude,
@@ -143,24 +313,18 @@ impl Mail {
       false,
     )?;
 
+    let (response, rsvp) = match invite {
+      Some(_) => Rsvp::extract(&response),
+      None => (response, None),
+    };
+
     let html = Self::markdown_to_html(&response);
 
     let reply_id = format!("{}@tulip.farm", uuid::Uuid::now_v7());
 
-    let db_path = self.db();
-    let db = redb::Database::create(&db_path).context(error::DatabaseOpen { path: db_path })?;
-    let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
-    {
-      let mut table = write_txn
-        .open_table(THREADS)
-        .context(error::DatabaseTable)?;
-      table
-        .insert(reply_id.as_str(), session.as_str())
-        .context(error::DatabaseStorage)?;
-    }
-    write_txn.commit().context(error::DatabaseCommit)?;
+    Self::register_thread(&self.db(), &reply_id, &session)?;
 
-    let reply = mail_builder::MessageBuilder::new()
+    let builder = mail_builder::MessageBuilder::new()
       .from(("Root", "root@tulip.farm"))
       .to(message.sender.as_str())
       .subject(&message.subject)
@@ -172,23 +336,58 @@ impl Mail {
           .iter()
           .map(|r| r.as_str())
           .collect::<Vec<&str>>(),
-      )
-      .text_body(&response)
-      .html_body(&html)
-      .write_to_vec()
-      .expect("writing to Vec failed");
+      );
+
+    let builder = match invite.zip(rsvp) {
+      Some((invite, rsvp)) => builder.body(MimePart::new(
+        "multipart/alternative",
+        vec![
+          MimePart::new("text/plain", response.as_str()),
+          MimePart::new("text/html", html.as_str()),
+          MimePart::new(
+            ContentType::new("text/calendar")
+              .attribute("method", "REPLY")
+              .attribute("charset", "utf-8"),
+            invite.reply(LOCAL_ADDRESS, rsvp),
+          ),
+        ],
+      )),
+      None => builder.text_body(&response).html_body(&html),
+    };
+
+    let reply = builder.write_to_vec().expect("writing to Vec failed");
+
+    Self::save_to_maildir(self.dir(), &reply)?;
 
-    Self::save_to_maildir(&self.dir, &reply)?;
+    Self::deliver(&self.sendmail, &message.sender, &reply)
+  }
+
+  pub(super) fn register_thread(db: &Database, message_id: &str, session: &str) -> Result {
+    let db = db.open()?;
+    let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
+    {
+      let mut table = write_txn
+        .open_table(THREADS)
+        .context(error::DatabaseTable)?;
+      table
+        .insert(message_id, session)
+        .context(error::DatabaseStorage)?;
+    }
+    write_txn.commit().context(error::DatabaseCommit)?;
+
+    Ok(())
+  }
 
+  pub(super) fn deliver(sendmail: &Path, recipient: &str, email: &[u8]) -> Result {
     let envelope = lettre::address::Envelope::new(
-      Some("root@tulip.farm".parse().unwrap()),
-      vec![message.sender.parse().context(error::Address)?],
+      Some(LOCAL_ADDRESS.parse().unwrap()),
+      vec![recipient.parse().context(error::Address)?],
     )
     .unwrap();
 
-    let transport = lettre::SendmailTransport::new_with_command(&self.sendmail);
+    let transport = lettre::SendmailTransport::new_with_command(sendmail);
 
-    lettre::Transport::send_raw(&transport, &envelope, &reply).context(error::Send)?;
+    lettre::Transport::send_raw(&transport, &envelope, email).context(error::Send)?;
 
     Ok(())
   }
@@ -0,0 +1,518 @@
+use super::*;
+
+const PAGES: redb::TableDefinition<&str, &str> = redb::TableDefinition::new("archive_pages");
+
+const STYLE: &str = "\
+body { font-family: sans-serif; max-width: 50em; margin: 2em auto; padding: 0 1em; }
+article { border-left: 2px solid #ccc; margin: 1em 0; padding-left: 1em; }
+pre { white-space: pre-wrap; }
+.meta { color: #666; font-size: 0.9em; }";
+
+#[derive(clap::Args)]
+pub(super) struct Archive {
+  #[arg(long)]
+  out: PathBuf,
+  #[arg(long, default_value = "/root/mail")]
+  dir: PathBuf,
+  #[arg(long)]
+  db: Option<PathBuf>,
+}
+
+struct Thread {
+  id: String,
+  session: Option<String>,
+  entries: Vec<(usize, index::Entry)>,
+}
+
+impl Thread {
+  fn subject(&self) -> &str {
+    self
+      .entries
+      .first()
+      .map_or("", |(_, entry)| entry.subject.as_str())
+  }
+
+  fn latest(&self) -> Option<i64> {
+    self
+      .entries
+      .iter()
+      .filter_map(|(_, entry)| entry.date)
+      .max()
+  }
+
+  fn fingerprint(&self) -> String {
+    let mut fingerprint = self.session.clone().unwrap_or_default();
+    for (_, entry) in &self.entries {
+      fingerprint.push(' ');
+      fingerprint.push_str(&entry.message_id);
+    }
+    fingerprint
+  }
+}
+
+impl Archive {
+  pub(super) fn run(self) -> Result {
+    let db = Database::new(self.db.clone().unwrap_or_else(db_path));
+
+    index::update(&db, &self.dir)?;
+
+    let threads = Self::threads(&db)?;
+
+    let mut pages = Vec::new();
+
+    for thread in &threads {
+      pages.push((
+        Path::new("threads").join(format!("{}.html", filename(&thread.id))),
+        thread.fingerprint(),
+        thread,
+      ));
+    }
+
+    let mut sessions = BTreeMap::<&str, Vec<&Thread>>::new();
+
+    for thread in &threads {
+      if let Some(session) = &thread.session {
+        sessions.entry(session).or_default().push(thread);
+      }
+    }
+
+    let fingerprints = Self::fingerprints(&db)?;
+    let mut updated = BTreeMap::new();
+    let mut current = BTreeSet::new();
+
+    for (path, fingerprint, thread) in pages {
+      current.insert(path.clone());
+
+      let key = self.out.join(&path).to_string_lossy().into_owned();
+
+      if fingerprints.get(&key) == Some(&fingerprint) && self.out.join(&path).is_file() {
+        continue;
+      }
+
+      self.write(&path, &self.render_thread(thread)?)?;
+      updated.insert(key, fingerprint);
+    }
+
+    for (session, threads) in &sessions {
+      let path = Path::new("sessions").join(format!("{session}.html"));
+      let key = self.out.join(&path).to_string_lossy().into_owned();
+
+      current.insert(path.clone());
+
+      let fingerprint = threads
+        .iter()
+        .map(|thread| thread.fingerprint())
+        .collect::<Vec<String>>()
+        .join("\n");
+
+      if fingerprints.get(&key) == Some(&fingerprint) && self.out.join(&path).is_file() {
+        continue;
+      }
+
+      self.write(&path, &Self::render_session(session, threads))?;
+      updated.insert(key, fingerprint);
+    }
+
+    self.write(Path::new("index.html"), &Self::render_index(&threads))?;
+
+    let pruned = self.prune(&current)?;
+
+    let db = db.open()?;
+    let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
+    {
+      let mut table = write_txn.open_table(PAGES).context(error::DatabaseTable)?;
+      for (key, fingerprint) in &updated {
+        table
+          .insert(key.as_str(), fingerprint.as_str())
+          .context(error::DatabaseStorage)?;
+      }
+      for key in &pruned {
+        table.remove(key.as_str()).context(error::DatabaseStorage)?;
+      }
+    }
+    write_txn.commit().context(error::DatabaseCommit)?;
+
+    Ok(())
+  }
+
+  fn prune(&self, current: &BTreeSet<PathBuf>) -> Result<Vec<String>> {
+    let mut pruned = Vec::new();
+
+    for dir in ["threads", "sessions"] {
+      let path = self.out.join(dir);
+
+      let entries = match fs::read_dir(&path) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+        Err(err) => return Err(err).context(error::FilesystemIo { path }),
+      };
+
+      for entry in entries {
+        let entry = entry.context(error::FilesystemIo { path: &path })?;
+
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+          continue;
+        };
+
+        let relative = Path::new(dir).join(&name);
+
+        if !name.ends_with(".html") || current.contains(&relative) {
+          continue;
+        }
+
+        let path = entry.path();
+
+        fs::remove_file(&path).context(error::FilesystemIo { path: &path })?;
+
+        pruned.push(path.to_string_lossy().into_owned());
+      }
+    }
+
+    Ok(pruned)
+  }
+
+  fn fingerprints(db: &Database) -> Result<BTreeMap<String, String>> {
+    let db = db.open()?;
+
+    let read_txn = db.begin_read().context(error::DatabaseTransaction)?;
+
+    let table = match read_txn.open_table(PAGES) {
+      Ok(table) => table,
+      Err(redb::TableError::TableDoesNotExist(_)) => return Ok(BTreeMap::new()),
+      Err(e) => return Err(e).context(error::DatabaseTable),
+    };
+
+    let mut fingerprints = BTreeMap::new();
+
+    for entry in table.iter().context(error::DatabaseStorage)? {
+      let (key, value) = entry.context(error::DatabaseStorage)?;
+      fingerprints.insert(key.value().to_string(), value.value().to_string());
+    }
+
+    Ok(fingerprints)
+  }
+
+  fn threads(db: &Database) -> Result<Vec<Thread>> {
+    let mut threads = BTreeMap::<String, Vec<index::Entry>>::new();
+
+    for entry in index::search(db, "")? {
+      threads.entry(entry.thread.clone()).or_default().push(entry);
+    }
+
+    let message_ids = threads
+      .values()
+      .flatten()
+      .map(|entry| entry.message_id.as_str())
+      .collect::<Vec<&str>>();
+
+    let sessions = index::sessions(db, &message_ids)?;
+
+    let mut threads = threads
+      .into_iter()
+      .map(|(id, entries)| {
+        let session = entries
+          .iter()
+          .find_map(|entry| sessions.get(&entry.message_id).cloned());
+
+        Thread {
+          id,
+          session,
+          entries: tree(entries),
+        }
+      })
+      .collect::<Vec<Thread>>();
+
+    threads.sort_by_key(|thread| std::cmp::Reverse(thread.latest()));
+
+    Ok(threads)
+  }
+
+  fn write(&self, path: &Path, html: &str) -> Result {
+    let path = self.out.join(path);
+
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent).context(error::FilesystemIo { path: parent })?;
+    }
+
+    fs::write(&path, html).context(error::FilesystemIo { path })
+  }
+
+  fn render_thread(&self, thread: &Thread) -> Result<String> {
+    let mut body = format!("<h1>{}</h1>\n", escape(thread.subject()));
+
+    body.push_str("<p class=meta><a href=../index.html>index</a>");
+
+    if let Some(session) = &thread.session {
+      body.push_str(&format!(
+        " · session <a href=../sessions/{session}.html>{session}</a>"
+      ));
+    }
+
+    body.push_str("</p>\n");
+
+    for (depth, entry) in &thread.entries {
+      let path = self.dir.join(&entry.path);
+      let raw = fs::read(&path).context(error::FilesystemIo { path })?;
+      let parsed = mailparse::parse_mail(&raw).context(error::MailParse)?;
+      let text = Message::extract_body(&parsed).unwrap_or_default();
+
+      let content = if entry.from.contains(LOCAL_ADDRESS) {
+        Mail::markdown_to_escaped_html(&text)
+      } else {
+        format!("<pre>{}</pre>\n", escape(text.trim_end()))
+      };
+
+      body.push_str(&format!(
+        "<article id=\"{}\" style=\"margin-left: {}em\">\n\
+         <p class=meta>{} · {}</p>\n\
+         {content}</article>\n",
+        escape(&entry.message_id),
+        depth * 2,
+        escape(&entry.from),
+        entry.date.map(search::format_date).unwrap_or_default(),
+      ));
+    }
+
+    Ok(page(thread.subject(), &body))
+  }
+
+  fn render_session(session: &str, threads: &[&Thread]) -> String {
+    let mut body = format!(
+      "<h1>Session {session}</h1>\n\
+       <p class=meta><a href=../index.html>index</a> · <code>lab resume {session}</code></p>\n\
+       <ul>\n"
+    );
+
+    for thread in threads {
+      body.push_str(&format!(
+        "<li><a href=../threads/{}.html>{}</a></li>\n",
+        filename(&thread.id),
+        escape(thread.subject()),
+      ));
+    }
+
+    body.push_str("</ul>\n");
+
+    page(&format!("Session {session}"), &body)
+  }
+
+  fn render_index(threads: &[Thread]) -> String {
+    let mut body = String::from("<h1>Archive</h1>\n<ul>\n");
+
+    for thread in threads {
+      body.push_str(&format!(
+        "<li><a href=threads/{}.html>{}</a> <span class=meta>{} · {} messages",
+        filename(&thread.id),
+        escape(thread.subject()),
+        thread.latest().map(search::format_date).unwrap_or_default(),
+        thread.entries.len(),
+      ));
+
+      if let Some(session) = &thread.session {
+        body.push_str(&format!(" · <a href=sessions/{session}.html>{session}</a>"));
+      }
+
+      body.push_str("</span></li>\n");
+    }
+
+    body.push_str("</ul>\n");
+
+    page("Archive", &body)
+  }
+}
+
+fn tree(mut entries: Vec<index::Entry>) -> Vec<(usize, index::Entry)> {
+  struct Tree<'a> {
+    children: BTreeMap<&'a str, Vec<usize>>,
+    entries: &'a [index::Entry],
+    output: Vec<(usize, index::Entry)>,
+    visited: Vec<bool>,
+  }
+
+  impl Tree<'_> {
+    fn visit(&mut self, i: usize, depth: usize) {
+      if self.visited[i] {
+        return;
+      }
+
+      self.visited[i] = true;
+      self.output.push((depth, self.entries[i].clone()));
+
+      let children = self
+        .children
+        .get(self.entries[i].message_id.as_str())
+        .cloned()
+        .unwrap_or_default();
+
+      for child in children {
+        self.visit(child, depth + 1);
+      }
+    }
+  }
+
+  entries.sort_by_key(|entry| entry.date);
+
+  let ids = entries
+    .iter()
+    .map(|entry| entry.message_id.as_str())
+    .collect::<BTreeSet<&str>>();
+
+  let mut roots = Vec::new();
+  let mut children = BTreeMap::<&str, Vec<usize>>::new();
+
+  for (i, entry) in entries.iter().enumerate() {
+    match entry.in_reply_to.as_deref() {
+      Some(parent) if ids.contains(parent) && parent != entry.message_id => {
+        children.entry(parent).or_default().push(i);
+      }
+      _ => roots.push(i),
+    }
+  }
+
+  let mut tree = Tree {
+    children,
+    entries: &entries,
+    output: Vec::new(),
+    visited: vec![false; entries.len()],
+  };
+
+  for root in roots {
+    tree.visit(root, 0);
+  }
+
+  // entries whose in_reply_to chain loops back on itself have no root
+  for i in 0..entries.len() {
+    tree.visit(i, 0);
+  }
+
+  tree.output
+}
+
+fn filename(id: &str) -> String {
+  let mut filename = String::with_capacity(id.len());
+
+  for byte in id.bytes() {
+    if byte.is_ascii_alphanumeric() || b"@.-".contains(&byte) {
+      filename.push(byte.into());
+    } else {
+      filename.push_str(&format!("_{byte:02X}"));
+    }
+  }
+
+  filename
+}
+
+fn escape(text: &str) -> String {
+  let mut escaped = String::with_capacity(text.len());
+
+  for c in text.chars() {
+    match c {
+      '&' => escaped.push_str("&amp;"),
+      '<' => escaped.push_str("&lt;"),
+      '>' => escaped.push_str("&gt;"),
+      '"' => escaped.push_str("&quot;"),
+      _ => escaped.push(c),
+    }
+  }
+
+  escaped
+}
+
+fn page(title: &str, body: &str) -> String {
+  format!(
+    "<!DOCTYPE html>\n\
+     <html lang=\"en\">\n\
+     <head>\n\
+     <meta charset=\"utf-8\">\n\
+     <title>{}</title>\n\
+     <style>\n{STYLE}\n</style>\n\
+     </head>\n\
+     <body>\n{body}</body>\n\
+     </html>\n",
+    escape(title),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn entry(message_id: &str, in_reply_to: Option<&str>, date: i64) -> index::Entry {
+    index::Entry {
+      path: PathBuf::new(),
+      message_id: message_id.into(),
+      thread: "a".into(),
+      in_reply_to: in_reply_to.map(Into::into),
+      from: String::new(),
+      to: String::new(),
+      subject: String::new(),
+      date: Some(date),
+    }
+  }
+
+  #[test]
+  fn tree() {
+    let entries = super::tree(vec![
+      entry("c", Some("a"), 2),
+      entry("a", None, 0),
+      entry("d", Some("b"), 3),
+      entry("b", Some("a"), 1),
+      entry("e", Some("x"), 4),
+    ]);
+
+    assert_eq!(
+      entries
+        .iter()
+        .map(|(depth, entry)| (*depth, entry.message_id.as_str()))
+        .collect::<Vec<(usize, &str)>>(),
+      [(0, "a"), (1, "b"), (2, "d"), (1, "c"), (0, "e")],
+    );
+  }
+
+  #[test]
+  fn tree_cycle() {
+    let entries = super::tree(vec![
+      entry("a", None, 0),
+      entry("b", Some("c"), 1),
+      entry("c", Some("b"), 2),
+      entry("d", Some("c"), 3),
+    ]);
+
+    assert_eq!(
+      entries
+        .iter()
+        .map(|(depth, entry)| (*depth, entry.message_id.as_str()))
+        .collect::<Vec<(usize, &str)>>(),
+      [(0, "a"), (0, "b"), (1, "c"), (2, "d")],
+    );
+  }
+
+  #[test]
+  fn markdown() {
+    assert_eq!(
+      Mail::markdown_to_escaped_html("# foo\n\n<script>bar</script>\n\nbaz <b>qux</b>"),
+      "<h1>foo</h1>\n&lt;script&gt;bar&lt;/script&gt;\n<p>baz &lt;b&gt;qux&lt;/b&gt;</p>\n",
+    );
+    assert_eq!(
+      Mail::markdown_to_escaped_html("[foo](javascript:bar) [baz](https://qux)"),
+      "<p><a href=\"\">foo</a> <a href=\"https://qux\">baz</a></p>\n",
+    );
+  }
+
+  #[test]
+  fn escape() {
+    assert_eq!(
+      super::escape("<a href=\"&\">"),
+      "&lt;a href=&quot;&amp;&quot;&gt;"
+    );
+  }
+
+  #[test]
+  fn filename() {
+    assert_eq!(super::filename("foo@bar.com"), "foo@bar.com");
+    assert_eq!(super::filename("foo/bar$baz"), "foo_2Fbar_24baz");
+    assert_eq!(super::filename("a_b"), "a_5Fb");
+    assert_ne!(super::filename("a/b"), super::filename("a$b"));
+    assert_ne!(super::filename("a/b"), super::filename("a_2Fb"));
+  }
+}
@@ -0,0 +1,400 @@
+use {super::*, redb::ReadableMultimapTable};
+
+const MESSAGES: redb::TableDefinition<&str, &str> = redb::TableDefinition::new("mail_messages");
+const TERMS: redb::MultimapTableDefinition<&str, &str> =
+  redb::MultimapTableDefinition::new("mail_terms");
+
+#[derive(Clone, Deserialize, Serialize)]
+pub(super) struct Entry {
+  pub(super) path: PathBuf,
+  pub(super) message_id: String,
+  pub(super) thread: String,
+  pub(super) in_reply_to: Option<String>,
+  pub(super) from: String,
+  pub(super) to: String,
+  pub(super) subject: String,
+  pub(super) date: Option<i64>,
+}
+
+impl Entry {
+  fn parse(path: PathBuf, raw: &[u8]) -> Result<(Self, BTreeSet<String>)> {
+    let parsed = mailparse::parse_mail(raw).context(error::MailParse)?;
+    let headers = parsed.get_headers();
+
+    let id = |value: &str| {
+      value
+        .trim()
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+        .to_string()
+    };
+
+    let message_id = headers
+      .get_first_value("Message-ID")
+      .map(|value| id(&value))
+      .ok_or(Error::MissingMessageId)?;
+
+    let in_reply_to = headers
+      .get_first_value("In-Reply-To")
+      .map(|value| id(&value));
+
+    let thread = headers
+      .get_first_value("References")
+      .and_then(|references| references.split_whitespace().next().map(id))
+      .or_else(|| in_reply_to.clone())
+      .unwrap_or_else(|| message_id.clone());
+
+    let from = headers.get_first_value("From").unwrap_or_default();
+    let to = headers.get_first_value("To").unwrap_or_default();
+    let subject = headers.get_first_value("Subject").unwrap_or_default();
+
+    let date = headers
+      .get_first_value("Date")
+      .and_then(|date| mailparse::dateparse(&date).ok());
+
+    let body = Message::extract_body(&parsed).unwrap_or_default();
+
+    let terms = [&body, &from, &to, &subject]
+      .into_iter()
+      .flat_map(|text| terms(text))
+      .collect();
+
+    Ok((
+      Self {
+        path,
+        message_id,
+        thread,
+        in_reply_to,
+        from,
+        to,
+        subject,
+        date,
+      },
+      terms,
+    ))
+  }
+}
+
+pub(super) fn terms(text: &str) -> BTreeSet<String> {
+  text
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|term| !term.is_empty())
+    .map(str::to_lowercase)
+    .collect()
+}
+
+pub(super) fn update(db: &Database, maildir: &Path) -> Result {
+  let mut files = Vec::new();
+
+  for dir in ["cur", "new"] {
+    let path = maildir.join(dir);
+
+    let entries = match fs::read_dir(&path) {
+      Ok(entries) => entries,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+      Err(err) => return Err(err).context(error::FilesystemIo { path }),
+    };
+
+    for entry in entries {
+      let entry = entry.context(error::FilesystemIo { path: &path })?;
+      let filename = entry.file_name().to_string_lossy().into_owned();
+      let key = filename
+        .split_once(':')
+        .map_or(filename.as_str(), |(key, _)| key)
+        .to_string();
+      files.push((key, Path::new(dir).join(filename)));
+    }
+  }
+
+  let db = db.open()?;
+
+  let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
+  {
+    let mut messages = write_txn
+      .open_table(MESSAGES)
+      .context(error::DatabaseTable)?;
+    let mut postings = write_txn
+      .open_multimap_table(TERMS)
+      .context(error::DatabaseTable)?;
+
+    let keys = files
+      .iter()
+      .map(|(key, _)| key.as_str())
+      .collect::<BTreeSet<&str>>();
+
+    let mut stale = BTreeSet::new();
+
+    for entry in messages.iter().context(error::DatabaseStorage)? {
+      let (key, _) = entry.context(error::DatabaseStorage)?;
+      if !keys.contains(key.value()) {
+        stale.insert(key.value().to_string());
+      }
+    }
+
+    if !stale.is_empty() {
+      for key in &stale {
+        messages
+          .remove(key.as_str())
+          .context(error::DatabaseStorage)?;
+      }
+
+      let mut orphans = Vec::new();
+
+      for entry in postings.iter().context(error::DatabaseStorage)? {
+        let (term, keys) = entry.context(error::DatabaseStorage)?;
+        for key in keys {
+          let key = key.context(error::DatabaseStorage)?;
+          if stale.contains(key.value()) {
+            orphans.push((term.value().to_string(), key.value().to_string()));
+          }
+        }
+      }
+
+      for (term, key) in orphans {
+        postings
+          .remove(term.as_str(), key.as_str())
+          .context(error::DatabaseStorage)?;
+      }
+    }
+
+    for (key, path) in files {
+      let existing = messages
+        .get(key.as_str())
+        .context(error::DatabaseStorage)?
+        .map(|value| value.value().to_string());
+
+      if let Some(existing) = existing {
+        let mut entry = serde_json::from_str::<Entry>(&existing).context(error::JsonParse)?;
+
+        if entry.path != path {
+          entry.path = path;
+          let json = serde_json::to_string(&entry).context(error::JsonParse)?;
+          messages
+            .insert(key.as_str(), json.as_str())
+            .context(error::DatabaseStorage)?;
+        }
+
+        continue;
+      }
+
+      let full = maildir.join(&path);
+      let raw = fs::read(&full).context(error::FilesystemIo { path: full })?;
+
+      let (entry, terms) = match Entry::parse(path, &raw) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+          ::log::warn!("skipping unparseable message `{key}`: {err}");
+          continue;
+        }
+      };
+
+      let json = serde_json::to_string(&entry).context(error::JsonParse)?;
+
+      messages
+        .insert(key.as_str(), json.as_str())
+        .context(error::DatabaseStorage)?;
+
+      for term in terms {
+        postings
+          .insert(term.as_str(), key.as_str())
+          .context(error::DatabaseStorage)?;
+      }
+    }
+  }
+  write_txn.commit().context(error::DatabaseCommit)?;
+
+  Ok(())
+}
+
+pub(super) fn search(db: &Database, query: &str) -> Result<Vec<Entry>> {
+  let db = db.open()?;
+
+  let read_txn = db.begin_read().context(error::DatabaseTransaction)?;
+
+  let messages = match read_txn.open_table(MESSAGES) {
+    Ok(table) => table,
+    Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+    Err(e) => return Err(e).context(error::DatabaseTable),
+  };
+
+  let postings = read_txn
+    .open_multimap_table(TERMS)
+    .context(error::DatabaseTable)?;
+
+  let mut keys: Option<BTreeSet<String>> = None;
+
+  for term in terms(query) {
+    let mut matches = BTreeSet::new();
+
+    for key in postings
+      .get(term.as_str())
+      .context(error::DatabaseStorage)?
+    {
+      let key = key.context(error::DatabaseStorage)?;
+      matches.insert(key.value().to_string());
+    }
+
+    keys = Some(match keys {
+      Some(keys) => keys.intersection(&matches).cloned().collect(),
+      None => matches,
+    });
+  }
+
+  let mut entries = Vec::new();
+
+  match keys {
+    Some(keys) => {
+      for key in keys {
+        if let Some(value) = messages.get(key.as_str()).context(error::DatabaseStorage)? {
+          entries.push(serde_json::from_str(value.value()).context(error::JsonParse)?);
+        }
+      }
+    }
+    None => {
+      for entry in messages.iter().context(error::DatabaseStorage)? {
+        let (_, value) = entry.context(error::DatabaseStorage)?;
+        entries.push(serde_json::from_str(value.value()).context(error::JsonParse)?);
+      }
+    }
+  }
+
+  Ok(entries)
+}
+
+pub(super) fn sessions(db: &Database, message_ids: &[&str]) -> Result<BTreeMap<String, String>> {
+  let db = db.open()?;
+
+  let read_txn = db.begin_read().context(error::DatabaseTransaction)?;
+
+  let threads = match read_txn.open_table(THREADS) {
+    Ok(table) => table,
+    Err(redb::TableError::TableDoesNotExist(_)) => return Ok(BTreeMap::new()),
+    Err(e) => return Err(e).context(error::DatabaseTable),
+  };
+
+  let mut sessions = BTreeMap::new();
+
+  for message_id in message_ids {
+    if let Some(session) = threads.get(*message_id).context(error::DatabaseStorage)? {
+      sessions.insert(message_id.to_string(), session.value().to_string());
+    }
+  }
+
+  Ok(sessions)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn terms() {
+    assert_eq!(
+      super::terms("Foo, bar@baz.com: FOO!"),
+      ["bar", "baz", "com", "foo"]
+        .into_iter()
+        .map(String::from)
+        .collect(),
+    );
+  }
+
+  #[test]
+  fn incremental_update() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("db.redb"));
+    let maildir = dir.path().join("mail");
+
+    Mail::save_to_maildir(
+      &maildir,
+      b"From: foo@bar.com\r\nMessage-ID: <foo@bar>\r\nSubject: hello\r\n\r\nbaz qux",
+    )
+    .unwrap();
+
+    update(&db, &maildir).unwrap();
+
+    assert_eq!(search(&db, "baz").unwrap().len(), 1);
+    assert_eq!(search(&db, "hello qux").unwrap().len(), 1);
+    assert_eq!(search(&db, "baz nope").unwrap().len(), 0);
+
+    Mail::save_to_maildir(
+      &maildir,
+      b"From: root@tulip.farm\r\nMessage-ID: <bar@tulip.farm>\r\n\
+        In-Reply-To: <foo@bar>\r\nReferences: <foo@bar>\r\n\r\nbaz",
+    )
+    .unwrap();
+
+    update(&db, &maildir).unwrap();
+
+    let entries = search(&db, "baz").unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(entries.iter().all(|entry| entry.thread == "foo@bar"));
+  }
+
+  #[test]
+  fn moved_message() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("db.redb"));
+    let maildir = dir.path().join("mail");
+
+    Mail::save_to_maildir(
+      &maildir,
+      b"From: foo@bar.com\r\nMessage-ID: <foo@bar>\r\n\r\nbaz",
+    )
+    .unwrap();
+
+    update(&db, &maildir).unwrap();
+
+    let new = fs::read_dir(maildir.join("new"))
+      .unwrap()
+      .next()
+      .unwrap()
+      .unwrap();
+
+    let cur = Path::new("cur").join(format!("{}:2,S", new.file_name().to_str().unwrap()));
+
+    fs::rename(new.path(), maildir.join(&cur)).unwrap();
+
+    update(&db, &maildir).unwrap();
+
+    let entries = search(&db, "baz").unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].path, cur);
+  }
+
+  #[test]
+  fn deleted_message() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("db.redb"));
+    let maildir = dir.path().join("mail");
+
+    let foo = Mail::save_to_maildir(
+      &maildir,
+      b"From: foo@bar.com\r\nMessage-ID: <foo@bar>\r\n\r\nbaz qux",
+    )
+    .unwrap();
+
+    Mail::save_to_maildir(
+      &maildir,
+      b"From: foo@bar.com\r\nMessage-ID: <bar@bar>\r\n\r\nbaz",
+    )
+    .unwrap();
+
+    update(&db, &maildir).unwrap();
+
+    assert_eq!(search(&db, "baz").unwrap().len(), 2);
+
+    fs::remove_file(foo).unwrap();
+
+    update(&db, &maildir).unwrap();
+
+    let entries = search(&db, "baz").unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].message_id, "bar@bar");
+    assert!(search(&db, "qux").unwrap().is_empty());
+    assert_eq!(search(&db, "").unwrap().len(), 1);
+
+    let read_txn = db.open().unwrap().begin_read().unwrap();
+    let postings = read_txn.open_multimap_table(TERMS).unwrap();
+    assert!(postings.get("qux").unwrap().is_empty());
+  }
+}
@@ -0,0 +1,174 @@
+use super::*;
+
+#[derive(clap::Args)]
+pub(super) struct Search {
+  query: String,
+  #[arg(long, default_value = "/root/mail")]
+  dir: PathBuf,
+  #[arg(long)]
+  db: Option<PathBuf>,
+  #[arg(long, default_value = "claude")]
+  claude: PathBuf,
+  #[arg(long)]
+  from: Option<String>,
+  #[arg(long)]
+  since: Option<jiff::civil::Date>,
+  #[arg(long)]
+  until: Option<jiff::civil::Date>,
+  #[arg(long)]
+  thread: Option<String>,
+  #[arg(long)]
+  resume: bool,
+}
+
+struct Thread {
+  id: String,
+  session: Option<String>,
+  entries: Vec<index::Entry>,
+}
+
+impl Thread {
+  fn latest(&self) -> Option<i64> {
+    self.entries.iter().filter_map(|entry| entry.date).max()
+  }
+}
+
+impl Search {
+  pub(super) fn run(self) -> Result {
+    let db = Database::new(self.db.clone().unwrap_or_else(db_path));
+
+    index::update(&db, &self.dir)?;
+
+    let threads = self.threads(&db)?;
+
+    if self.resume {
+      let session = threads
+        .iter()
+        .find_map(|thread| thread.session.clone())
+        .ok_or_else(|| Error::SessionNotFound {
+          name: self.query.clone(),
+        })?;
+
+      return Err(resume::exec(&self.claude, &session));
+    }
+
+    for thread in threads {
+      println!("{} {}", thread.session.as_deref().unwrap_or("-"), thread.id);
+
+      for entry in thread.entries {
+        println!(
+          "  {} {} {}",
+          entry.date.map(format_date).unwrap_or_default(),
+          entry.from,
+          entry.subject,
+        );
+      }
+    }
+
+    Ok(())
+  }
+
+  fn threads(&self, db: &Database) -> Result<Vec<Thread>> {
+    let since = self.since.map(start_of_day).transpose()?;
+
+    let until = self
+      .until
+      .map(|date| start_of_day(date.tomorrow().unwrap_or(date)))
+      .transpose()?;
+
+    let mut entries = index::search(db, &self.query)?;
+
+    let thread = self.thread.as_deref().map(|thread| {
+      let thread = thread.trim_start_matches('<').trim_end_matches('>');
+      entries
+        .iter()
+        .find(|entry| entry.message_id == thread)
+        .map_or(thread.to_string(), |entry| entry.thread.clone())
+    });
+
+    entries.retain(|entry| {
+      if let Some(from) = &self.from
+        && !entry.from.to_lowercase().contains(&from.to_lowercase())
+      {
+        return false;
+      }
+
+      if let Some(since) = since
+        && entry.date.is_none_or(|date| date < since)
+      {
+        return false;
+      }
+
+      if let Some(until) = until
+        && entry.date.is_none_or(|date| date >= until)
+      {
+        return false;
+      }
+
+      if let Some(thread) = &thread
+        && entry.thread != *thread
+      {
+        return false;
+      }
+
+      true
+    });
+
+    let mut threads = BTreeMap::<String, Vec<index::Entry>>::new();
+
+    for entry in entries {
+      threads.entry(entry.thread.clone()).or_default().push(entry);
+    }
+
+    let message_ids = threads
+      .values()
+      .flatten()
+      .map(|entry| entry.message_id.as_str())
+      .chain(threads.keys().map(String::as_str))
+      .collect::<Vec<&str>>();
+
+    let sessions = index::sessions(db, &message_ids)?;
+
+    let mut threads = threads
+      .into_iter()
+      .map(|(id, mut entries)| {
+        entries.sort_by_key(|entry| entry.date);
+
+        let session = sessions.get(&id).cloned().or_else(|| {
+          entries
+            .iter()
+            .find_map(|entry| sessions.get(&entry.message_id).cloned())
+        });
+
+        Thread {
+          id,
+          session,
+          entries,
+        }
+      })
+      .collect::<Vec<Thread>>();
+
+    threads.sort_by_key(|thread| std::cmp::Reverse(thread.latest()));
+
+    Ok(threads)
+  }
+}
+
+fn start_of_day(date: jiff::civil::Date) -> Result<i64> {
+  let zoned = date
+    .to_zoned(jiff::tz::TimeZone::system())
+    .context(error::Date { date })?;
+
+  Ok(zoned.timestamp().as_second())
+}
+
+pub(super) fn format_date(timestamp: i64) -> String {
+  jiff::Timestamp::from_second(timestamp)
+    .map(|timestamp| {
+      timestamp
+        .to_zoned(jiff::tz::TimeZone::system())
+        .strftime("%Y-%m-%d %H:%M")
+        .to_string()
+    })
+    .unwrap_or_default()
+}
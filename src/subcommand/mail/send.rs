@@ -0,0 +1,78 @@
+use super::*;
+
+#[derive(clap::Args)]
+pub(super) struct Send {
+  #[arg(long)]
+  to: String,
+  #[arg(long)]
+  subject: String,
+  #[arg(long)]
+  prompt: PathBuf,
+  #[arg(long)]
+  draft: bool,
+  #[arg(long, default_value = "/root/mail")]
+  dir: PathBuf,
+  #[arg(long, default_value = "/run/wrappers/bin/sendmail")]
+  sendmail: PathBuf,
+  #[arg(long)]
+  db: Option<PathBuf>,
+  #[arg(long, default_value = "claude")]
+  claude: PathBuf,
+  #[arg(long, default_value = SESSION_DIR)]
+  session_dir: PathBuf,
+}
+
+impl Send {
+  pub(super) fn run(self) -> Result {
+    self.to.parse::<lettre::Address>().context(error::Address)?;
+
+    let prompt = if self.prompt == Path::new("-") {
+      io::read_to_string(io::stdin()).context(error::Stdin)?
+    } else {
+      fs::read_to_string(&self.prompt).context(error::FilesystemIo { path: &self.prompt })?
+    };
+
+    let session = uuid::Uuid::now_v7().to_string();
+
+    let response = invoke_agent(
+      &self.claude,
+      &self.session_dir,
+      &session,
+      false,
+      &prompt,
+      Some(&format!(
+        "You are writing an email to {} with the subject \"{}\". \
+         Your response will be sent as the body of the email.",
+        self.to, self.subject,
+      )),
+      false,
+    )?;
+
+    let message_id = format!("{}@tulip.farm", uuid::Uuid::now_v7());
+
+    let email = mail_builder::MessageBuilder::new()
+      .from(("Root", LOCAL_ADDRESS))
+      .to(self.to.as_str())
+      .subject(&self.subject)
+      .message_id(message_id.as_str())
+      .text_body(&response)
+      .html_body(Mail::markdown_to_html(&response))
+      .write_to_vec()
+      .expect("writing to Vec failed");
+
+    let db = Database::new(self.db.clone().unwrap_or_else(db_path));
+
+    if self.draft {
+      let path = Mail::save_to_maildir(&self.dir.join("drafts"), &email)?;
+      Mail::register_thread(&db, &message_id, &session)?;
+      println!("{}", path.display());
+      return Ok(());
+    }
+
+    Mail::save_to_maildir(&self.dir, &email)?;
+
+    Mail::deliver(&self.sendmail, &self.to, &email)?;
+
+    Mail::register_thread(&db, &message_id, &session)
+  }
+}
@@ -3,10 +3,17 @@ use super::*;
 #[derive(clap::Args)]
 pub(crate) struct Mood {
   emoji: String,
+  #[arg(long)]
+  db: Option<PathBuf>,
 }
 
 impl Mood {
   pub(crate) fn run(self) -> Result {
+    let saved = save(
+      &Database::new(self.db.clone().unwrap_or_else(db_path)),
+      &self.emoji,
+    );
+
     #[cfg(target_os = "linux")]
     {
       let logger = systemd_journal_logger::JournalLog::empty()
@@ -28,6 +35,53 @@ impl Mood {
       ::log::info!("Mood: {}", self.emoji);
     }
 
-    Ok(())
+    saved
+  }
+}
+
+fn path(db: &Database) -> PathBuf {
+  db.path().with_extension("mood")
+}
+
+pub(crate) fn load(db: &Database) -> Result<Option<String>> {
+  let path = path(db);
+
+  match fs::read_to_string(&path) {
+    Ok(mood) => Ok(Some(mood.trim().to_string()).filter(|mood| !mood.is_empty())),
+    Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+    Err(err) => Err(err).context(error::FilesystemIo { path }),
+  }
+}
+
+pub(crate) fn save(db: &Database, mood: &str) -> Result {
+  let path = path(db);
+
+  let staging = path.with_extension("mood.tmp");
+
+  fs::write(&staging, mood).context(error::FilesystemIo { path: &staging })?;
+
+  fs::rename(&staging, &path).context(error::FilesystemIo { path })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn save_and_load() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    assert_eq!(load(&db).unwrap(), None);
+
+    save(&db, "😀").unwrap();
+    assert_eq!(load(&db).unwrap().as_deref(), Some("😀"));
+
+    save(&db, "😴").unwrap();
+    assert_eq!(load(&db).unwrap().as_deref(), Some("😴"));
+
+    assert!(!dir.path().join("foo.redb").exists());
+
+    assert!(save(&Database::new(dir.path().join("bar/foo.redb")), "😀").is_err());
   }
 }
@@ -28,7 +28,12 @@ impl Note {
 
       let subject = String::from_utf8_lossy(&output.stdout).trim().to_string();
 
-      notify::send(&format!("note: {subject}"))?;
+      notify::send(
+        &config::Config::load_or_default(&config::Config::path()),
+        &Database::new(db_path()),
+        "note",
+        &format!("note: {subject}"),
+      )?;
 
       let payload = format!("{oldrev} {newrev}");
       let socket = UnixDatagram::unbound().context(error::SocketSend)?;
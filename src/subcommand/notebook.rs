@@ -43,7 +43,7 @@ impl Notebook {
   }
 
   fn handle_message(&self, oldrev: &str, newrev: &str) -> Result {
-    let db = self.db.clone().unwrap_or_else(db_path);
+    let db = Database::new(self.db.clone().unwrap_or_else(db_path));
     let (session, resume) = lookup_session(&db, SESSION_NAME)?;
 
     let session_dir = Path::new(SESSION_DIR).join(&session);
@@ -68,7 +68,12 @@ impl Notebook {
 
     let response = response.trim();
 
-    notify::send(&format!("note complete: {subject} {response}"))
+    notify::send(
+      &config::Config::load_or_default(&config::Config::path()),
+      &db,
+      "notebook",
+      &format!("note complete: {subject} {response}"),
+    )
   }
 
   fn clone_or_pull(session_dir: &Path) -> Result {
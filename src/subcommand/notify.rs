@@ -1,177 +1,849 @@
 use super::*;
 
 use {
-  base64::Engine,
-  irc::client::{
-    Client, ClientStream,
-    data::Config,
-    prelude::{Capability, Response},
-  },
-  irc::proto::{CapSubCommand, Command as IrcCommand},
+  ::irc::proto::{Command as IrcCommand, Response},
   tokio_stream::StreamExt,
 };
 
-const SERVER: &str = "tulip.farm";
-const PORT: u16 = 6697;
+use self::notifier::Notifier;
+
+mod notifier;
+mod pending;
+mod receipt;
+mod throttle;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_SOURCE: &str = "default";
 const NICK: &str = "system";
-const PASSWORD_FILE: &str = "/root/secrets/ergo-password";
-const TARGET: &str = "rodarmor";
+const PRESENCE_TIMEOUT: Duration = Duration::from_secs(10);
 
-const PUSHOVER_TOKEN_FILE: &str = "/root/secrets/pushover-token";
-const PUSHOVER_USER_FILE: &str = "/root/secrets/pushover-user";
+#[derive(
+  Clone,
+  Copy,
+  Debug,
+  Default,
+  Deserialize,
+  Eq,
+  Ord,
+  PartialEq,
+  PartialOrd,
+  Serialize,
+  clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Priority {
+  Lowest,
+  Low,
+  #[default]
+  Normal,
+  High,
+  Emergency,
+}
 
-pub(crate) fn send(message: &str) -> Result {
-  let rt = tokio::runtime::Runtime::new().context(error::TokioRuntime)?;
-  rt.block_on(send_async(message))
+impl Priority {
+  fn pushover(self) -> i8 {
+    match self {
+      Self::Lowest => -2,
+      Self::Low => -1,
+      Self::Normal => 0,
+      Self::High => 1,
+      Self::Emergency => 2,
+    }
+  }
+
+  fn irc_prefix(self) -> Option<&'static str> {
+    match self {
+      Self::Lowest | Self::Low | Self::Normal => None,
+      Self::High => Some("\x02\x0307HIGH\x0f "),
+      Self::Emergency => Some("\x02\x0304EMERGENCY\x0f "),
+    }
+  }
 }
 
-async fn send_async(message: &str) -> Result {
-  let (irc_result, pushover_result) = tokio::join!(irc(message), pushover(message));
+#[derive(Clone, Deserialize, Serialize)]
+pub(crate) struct Notification {
+  pub(crate) message: String,
+  pub(crate) title: Option<String>,
+  pub(crate) priority: Priority,
+  pub(crate) url: Option<String>,
+  pub(crate) sound: Option<String>,
+  pub(crate) retry: u64,
+  pub(crate) expire: u64,
+  #[serde(default)]
+  pub(crate) source: Option<String>,
+}
 
-  if let Err(err) = &irc_result {
-    ::log::error!("IRC notification failed: {err}");
+impl Notification {
+  pub(crate) fn new(message: &str) -> Self {
+    Self {
+      message: message.into(),
+      title: None,
+      priority: Priority::Normal,
+      url: None,
+      sound: None,
+      retry: 60,
+      expire: 3600,
+      source: None,
+    }
   }
 
-  if let Err(err) = &pushover_result {
-    ::log::error!("Pushover notification failed: {err}");
+  fn source(&self) -> &str {
+    self.source.as_deref().unwrap_or(DEFAULT_SOURCE)
   }
 
-  irc_result.or(pushover_result)
+  fn irc_lines(&self) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    if let Some(title) = &self.title {
+      lines.push(format!("\x02{title}\x02"));
+    }
+
+    lines.extend(
+      self
+        .message
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string),
+    );
+
+    if let Some(url) = &self.url {
+      lines.push(url.clone());
+    }
+
+    if let Some(prefix) = self.priority.irc_prefix()
+      && let Some(first) = lines.first_mut()
+    {
+      first.insert_str(0, prefix);
+    }
+
+    lines
+  }
+
+  fn pushover_form(&self, token: &str, user: &str) -> Vec<(&'static str, String)> {
+    let mut form = vec![
+      ("token", token.into()),
+      ("user", user.into()),
+      ("message", self.message.clone()),
+    ];
+
+    if let Some(title) = &self.title {
+      form.push(("title", title.clone()));
+    }
+
+    if self.priority != Priority::Normal {
+      form.push(("priority", self.priority.pushover().to_string()));
+    }
+
+    if self.priority == Priority::Emergency {
+      form.push(("retry", self.retry.to_string()));
+      form.push(("expire", self.expire.to_string()));
+    }
+
+    if let Some(url) = &self.url {
+      form.push(("url", url.clone()));
+    }
+
+    if let Some(sound) = &self.sound {
+      form.push(("sound", sound.clone()));
+    }
+
+    form
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Presence {
+  Active,
+  Away,
+  Offline,
+  Unknown,
 }
 
-async fn pushover(message: &str) -> Result {
-  let token = fs::read_to_string(PUSHOVER_TOKEN_FILE)
-    .context(error::FilesystemIo {
-      path: Path::new(PUSHOVER_TOKEN_FILE),
-    })?
-    .trim()
-    .to_string();
+impl Presence {
+  fn from_away(away: Option<bool>) -> Self {
+    match away {
+      Some(true) => Self::Away,
+      Some(false) => Self::Active,
+      None => Self::Offline,
+    }
+  }
+
+  fn routes(self, routing: &config::Routing) -> &[config::Route] {
+    match self {
+      Self::Active => &routing.active,
+      Self::Away => &routing.away,
+      Self::Offline => &routing.offline,
+      Self::Unknown => &routing.unknown,
+    }
+  }
+}
 
-  let user = fs::read_to_string(PUSHOVER_USER_FILE)
-    .context(error::FilesystemIo {
-      path: Path::new(PUSHOVER_USER_FILE),
-    })?
-    .trim()
-    .to_string();
+impl Display for Presence {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    match self {
+      Self::Active => write!(f, "active"),
+      Self::Away => write!(f, "away"),
+      Self::Offline => write!(f, "offline"),
+      Self::Unknown => write!(f, "unknown"),
+    }
+  }
+}
 
-  let client = reqwest::Client::new();
+pub(crate) fn send(config: &config::Config, db: &Database, source: &str, message: &str) -> Result {
+  send_notification(
+    config,
+    db,
+    &Notification {
+      source: Some(source.into()),
+      ..Notification::new(message)
+    },
+  )
+}
 
-  client
-    .post("https://api.pushover.net/1/messages.json")
-    .form(&[
-      ("token", token.as_str()),
-      ("user", user.as_str()),
-      ("message", message),
-    ])
-    .send()
-    .await
-    .context(error::PushoverSend)?
-    .error_for_status()
-    .context(error::PushoverSend)?;
+fn send_notification(
+  config: &config::Config,
+  db: &Database,
+  notification: &Notification,
+) -> Result {
+  let rt = tokio::runtime::Runtime::new().context(error::TokioRuntime)?;
+  rt.block_on(send_async(config, db, notification))
+}
+
+async fn send_async(config: &config::Config, db: &Database, notification: &Notification) -> Result {
+  let now = jiff::Timestamp::now();
+
+  let notification = match throttle::dedup(db, &config.notify.throttle, notification, now) {
+    Ok(Some(notification)) => notification,
+    Ok(None) => {
+      ::log::info!("suppressed duplicate notification");
+      return Ok(());
+    }
+    Err(err) => {
+      ::log::error!("failed to check for duplicate notification: {err}");
+      notification.clone()
+    }
+  };
+
+  match throttle::gate(db, &config.notify.throttle, &notification, now) {
+    Ok(Some((until, reason))) => match pending::hold(db, &notification, until, &reason) {
+      Ok(()) => {
+        ::log::info!("notification deferred: {reason}");
+        return Ok(());
+      }
+      Err(err) => ::log::error!("failed to defer notification, delivering now: {err}"),
+    },
+    Ok(None) => {}
+    Err(err) => ::log::error!("failed to check notification throttle: {err}"),
+  }
+
+  match deliver(config, db, &notification).await {
+    Err(err) if retryable(&err) => match pending::push(db, &notification, &err) {
+      Ok(()) => {
+        ::log::warn!("notification queued for retry: {err}");
+        Ok(())
+      }
+      Err(push) => {
+        ::log::error!("failed to queue notification for retry: {push}");
+        Err(err)
+      }
+    },
+    result => result,
+  }
+}
+
+pub(crate) async fn flush_pending(db: &Database, config: &config::Config) -> Result<usize> {
+  summarize(db, config)?;
+
+  let delivered = pending::flush(db, config, false).await?;
+
+  receipt::check(db, config).await?;
+
+  Ok(delivered)
+}
+
+fn summarize(db: &Database, config: &config::Config) -> Result {
+  let now = jiff::Timestamp::now();
+
+  for summary in throttle::summaries(db, &config.notify.throttle, now)? {
+    let next_attempt = throttle::gate(db, &config.notify.throttle, &summary, now)?
+      .map_or(now.as_second(), |(until, _)| until);
+
+    pending::defer(db, &summary, next_attempt, "duplicate summary")?;
+  }
 
   Ok(())
 }
 
-async fn irc(message: &str) -> Result {
-  let password = fs::read_to_string(PASSWORD_FILE)
-    .context(error::PasswordFile {
-      path: Path::new(PASSWORD_FILE),
-    })?
-    .trim()
-    .to_string();
-
-  let config = Config {
-    server: Some(SERVER.to_string()),
-    port: Some(PORT),
-    nickname: Some(NICK.to_string()),
-    use_tls: Some(true),
-    ..Config::default()
+fn retryable(err: &Error) -> bool {
+  !matches!(
+    err,
+    Error::NotifierUnconfigured { .. } | Error::PushoverReceipt { .. }
+  )
+}
+
+async fn deliver(config: &config::Config, db: &Database, notification: &Notification) -> Result {
+  let target = &config.notify.target;
+
+  let mut session = tokio::time::timeout(CONNECT_TIMEOUT, Session::open(config))
+    .await
+    .unwrap_or_else(|_| {
+      Err(Error::IrcProtocol {
+        message: "timed out connecting".into(),
+      })
+    });
+
+  let presence = match &mut session {
+    Ok(session) => match tokio::time::timeout(PRESENCE_TIMEOUT, session.presence()).await {
+      Ok(Ok(presence)) => presence,
+      Ok(Err(err)) => {
+        ::log::error!("failed to check presence of {target}: {err}");
+        Presence::Unknown
+      }
+      Err(_) => {
+        ::log::error!("timed out checking presence of {target}");
+        Presence::Unknown
+      }
+    },
+    Err(err) => {
+      ::log::error!("IRC connection failed: {err}");
+      Presence::Unknown
+    }
   };
 
-  let mut client = Client::from_config(config).await.context(error::Irc)?;
-  let mut stream = client.stream().context(error::Irc)?;
+  let routes = presence.routes(&config.notify.routing);
 
-  sasl_auth(&client, &mut stream, &password).await?;
+  ::log::info!(
+    "{target} is {presence}, routing notification to {}",
+    routes
+      .iter()
+      .map(ToString::to_string)
+      .collect::<Vec<String>>()
+      .join(", "),
+  );
 
-  client.identify().context(error::Irc)?;
+  let mut delivered = false;
+  let mut last_error = None;
 
-  while let Some(msg) = stream.next().await.transpose().context(error::Irc)? {
-    if let IrcCommand::Response(Response::RPL_WELCOME, _) = &msg.command {
-      break;
+  for route in routes {
+    match notify(config, db, &mut session, *route, notification).await {
+      Ok(()) => delivered = true,
+      Err(err) => {
+        ::log::error!("{route} notification failed: {err}");
+        if last_error.as_ref().is_none_or(|last| !retryable(last)) {
+          last_error = Some(err);
+        }
+      }
     }
   }
 
-  let sender = client.sender();
-  for line in message.lines() {
-    let line = line.trim();
-    if line.is_empty() {
-      continue;
-    }
-    for chunk in chat::split_utf8(line, 400) {
-      sender.send_privmsg(TARGET, chunk).context(error::Irc)?;
+  if !delivered {
+    for route in &config.notify.fallback {
+      match notify(config, db, &mut session, *route, notification).await {
+        Ok(()) => {
+          ::log::info!("notification delivered via fallback {route}");
+          delivered = true;
+          break;
+        }
+        Err(err) => {
+          ::log::error!("fallback {route} notification failed: {err}");
+          if last_error.as_ref().is_none_or(|last| !retryable(last)) {
+            last_error = Some(err);
+          }
+        }
+      }
     }
   }
-  sender.send_quit("").context(error::Irc)?;
 
-  while let Some(msg) = stream.next().await.transpose().context(error::Irc)? {
-    if let IrcCommand::ERROR(_) = &msg.command {
-      break;
+  if let Ok(session) = session
+    && let Err(err) = session.quit().await
+  {
+    ::log::error!("failed to disconnect from IRC: {err}");
+  }
+
+  match last_error {
+    Some(err) if !delivered => Err(err),
+    _ => Ok(()),
+  }
+}
+
+async fn notify(
+  config: &config::Config,
+  db: &Database,
+  session: &mut Result<Session>,
+  route: config::Route,
+  notification: &Notification,
+) -> Result {
+  let unconfigured = || Error::NotifierUnconfigured { route };
+
+  match route {
+    config::Route::Irc => match session {
+      Ok(session) => session.notify(notification).await,
+      Err(_) => std::mem::replace(session, Err(Error::IrcClosed)).map(|_| ()),
+    },
+    config::Route::Pushover => {
+      if let Some(receipt) = notifier::Pushover(&config.notify.pushover)
+        .send(notification)
+        .await?
+        && let Err(err) = receipt::track(db, &receipt, notification)
+      {
+        ::log::error!("failed to track emergency notification receipt {receipt}: {err}");
+      }
+
+      Ok(())
     }
+    config::Route::Webhook => {
+      notifier::Webhook(config.notify.webhook.as_ref().ok_or_else(unconfigured)?)
+        .notify(notification)
+        .await
+    }
+    config::Route::Ntfy => {
+      notifier::Ntfy(config.notify.ntfy.as_ref().ok_or_else(unconfigured)?)
+        .notify(notification)
+        .await
+    }
+    config::Route::Email => {
+      notifier::Email(config.notify.email.as_ref().ok_or_else(unconfigured)?)
+        .notify(notification)
+        .await
+    }
+    config::Route::Journal => notifier::Journal.notify(notification).await,
   }
+}
 
-  Ok(())
+enum Session {
+  Direct(Box<Direct>),
+  Relay {
+    client: relay::Client,
+    target: String,
+  },
 }
 
-async fn sasl_auth(client: &Client, stream: &mut ClientStream, password: &str) -> Result {
-  client
-    .send_cap_req(&[Capability::Sasl])
-    .context(error::Irc)?;
+impl Session {
+  async fn open(config: &config::Config) -> Result<Self> {
+    let target = &config.notify.target;
 
-  while let Some(message) = stream.next().await.transpose().context(error::Irc)? {
-    if let IrcCommand::CAP(_, CapSubCommand::ACK, _, _) = &message.command {
-      break;
+    if let Some(path) = &config.notify.relay {
+      match relay::Client::connect(path).await {
+        Ok(client) => {
+          return Ok(Self::Relay {
+            client,
+            target: target.clone(),
+          });
+        }
+        Err(err) => ::log::info!("notification relay unavailable, connecting directly: {err}"),
+      }
     }
+
+    Ok(Self::Direct(Box::new(
+      Direct::open(&config.irc, target).await?,
+    )))
   }
 
-  client.send_sasl_plain().context(error::Irc)?;
+  async fn presence(&mut self) -> Result<Presence> {
+    match self {
+      Self::Direct(direct) => direct.presence().await,
+      Self::Relay { client, target } => Ok(Presence::from_away(client.presence(target).await?)),
+    }
+  }
 
-  while let Some(message) = stream.next().await.transpose().context(error::Irc)? {
-    if let IrcCommand::AUTHENTICATE(ref param) = message.command
-      && param == "+"
-    {
-      break;
+  async fn quit(self) -> Result {
+    match self {
+      Self::Direct(direct) => direct.quit().await,
+      Self::Relay { .. } => Ok(()),
     }
   }
+}
 
-  let credentials = format!("\0{NICK}\0{password}");
-  let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
-  client.send_sasl(&encoded).context(error::Irc)?;
+impl Notifier for Session {
+  async fn notify(&self, notification: &Notification) -> Result {
+    match self {
+      Self::Direct(direct) => direct.notify(notification).await,
+      Self::Relay { client, target } => client.privmsg(target, notification.irc_lines()).await,
+    }
+  }
+}
 
-  while let Some(message) = stream.next().await.transpose().context(error::Irc)? {
-    if let IrcCommand::Response(ref response, _) = message.command {
-      if *response == Response::RPL_SASLSUCCESS {
-        return Ok(());
+struct Direct {
+  _client: ::irc::client::Client,
+  outbox: irc::Outbox,
+  stream: ::irc::client::ClientStream,
+  target: String,
+}
+
+impl Direct {
+  async fn open(config: &config::Irc, target: &str) -> Result<Self> {
+    let irc::Connection {
+      client, mut stream, ..
+    } = irc::Connection::open(config, NICK, Vec::new(), Vec::new()).await?;
+
+    let outbox = irc::Outbox::new(client.sender(), NICK, false);
+
+    while let Some(msg) = stream.next().await.transpose().context(error::Irc)? {
+      outbox.observe(&msg);
+
+      if let IrcCommand::Response(Response::RPL_WELCOME, _) = &msg.command {
+        break;
       }
-      if *response == Response::ERR_SASLFAIL {
-        return Err(Error::IrcProtocol {
-          message: "SASL authentication failed".to_string(),
-        });
+    }
+
+    Ok(Self {
+      _client: client,
+      outbox,
+      stream,
+      target: target.into(),
+    })
+  }
+
+  async fn presence(&mut self) -> Result<Presence> {
+    let (mut query, command) = irc::PresenceQuery::new(&self.target);
+
+    self.outbox.send(command)?;
+
+    while let Some(msg) = self.stream.next().await.transpose().context(error::Irc)? {
+      match query.observe(&msg) {
+        irc::PresenceStep::Pending => {}
+        irc::PresenceStep::Send(command) => self.outbox.send(command)?,
+        irc::PresenceStep::Done(away) => return Ok(Presence::from_away(away)),
       }
     }
+
+    Err(Error::IrcProtocol {
+      message: "connection closed during presence check".into(),
+    })
   }
 
-  Err(Error::IrcProtocol {
-    message: "connection closed during SASL authentication".to_string(),
-  })
+  async fn quit(mut self) -> Result {
+    self.outbox.send(IrcCommand::QUIT(None))?;
+
+    while let Some(msg) = self.stream.next().await.transpose().context(error::Irc)? {
+      if let IrcCommand::ERROR(_) = &msg.command {
+        break;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+impl Notifier for Direct {
+  async fn notify(&self, notification: &Notification) -> Result {
+    for line in notification.irc_lines() {
+      self.outbox.privmsg(&self.target, &line)?;
+    }
+
+    Ok(())
+  }
+}
+
+#[derive(clap::Subcommand)]
+enum Subcommand {
+  Flush(pending::Flush),
+  Pending(pending::Pending),
 }
 
 #[derive(clap::Args)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub(crate) struct Notify {
-  message: String,
+  #[command(subcommand)]
+  subcommand: Option<Subcommand>,
+  message: Option<String>,
+  #[arg(long)]
+  title: Option<String>,
+  #[arg(long, value_enum, default_value_t)]
+  priority: Priority,
+  #[arg(long)]
+  url: Option<String>,
+  #[arg(long)]
+  sound: Option<String>,
+  #[arg(long, default_value_t = 60)]
+  retry: u64,
+  #[arg(long, default_value_t = 3600)]
+  expire: u64,
+  #[arg(long)]
+  source: Option<String>,
 }
 
 impl Notify {
   pub(crate) fn run(self) -> Result {
-    send(&self.message)
+    if let Some(subcommand) = self.subcommand {
+      return match subcommand {
+        Subcommand::Flush(flush) => flush.run(),
+        Subcommand::Pending(pending) => pending.run(),
+      };
+    }
+
+    let message = match self.message {
+      Some(message) => message,
+      None => {
+        let mut message = String::new();
+        io::stdin()
+          .read_to_string(&mut message)
+          .context(error::Stdin)?;
+        message
+      }
+    };
+
+    send_notification(
+      &config::Config::load_or_default(&config::Config::path()),
+      &Database::new(db_path()),
+      &Notification {
+        message,
+        title: self.title,
+        priority: self.priority,
+        url: self.url,
+        sound: self.sound,
+        retry: self.retry,
+        expire: self.expire,
+        source: self.source,
+      },
+    )
+  }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+  use super::*;
+
+  #[test]
+  fn irc_lines() {
+    assert_eq!(
+      Notification::new("foo\n\n bar ").irc_lines(),
+      ["foo", "bar"]
+    );
+
+    assert_eq!(
+      Notification {
+        title: Some("foo".into()),
+        priority: Priority::Emergency,
+        url: Some("https://foo.com".into()),
+        ..Notification::new("bar")
+      }
+      .irc_lines(),
+      [
+        "\x02\x0304EMERGENCY\x0f \x02foo\x02",
+        "bar",
+        "https://foo.com"
+      ],
+    );
+
+    assert_eq!(
+      Notification {
+        priority: Priority::High,
+        ..Notification::new("foo")
+      }
+      .irc_lines(),
+      ["\x02\x0307HIGH\x0f foo"],
+    );
+
+    assert_eq!(
+      Notification {
+        priority: Priority::Low,
+        ..Notification::new("foo")
+      }
+      .irc_lines(),
+      ["foo"],
+    );
+  }
+
+  #[test]
+  fn pushover_form() {
+    assert_eq!(
+      Notification::new("foo").pushover_form("a", "b"),
+      [
+        ("token", "a".to_string()),
+        ("user", "b".into()),
+        ("message", "foo".into())
+      ],
+    );
+
+    assert_eq!(
+      Notification {
+        title: Some("bar".into()),
+        priority: Priority::Emergency,
+        url: Some("https://foo.com".into()),
+        sound: Some("siren".into()),
+        retry: 30,
+        expire: 600,
+        ..Notification::new("foo")
+      }
+      .pushover_form("a", "b"),
+      [
+        ("token", "a".to_string()),
+        ("user", "b".into()),
+        ("message", "foo".into()),
+        ("title", "bar".into()),
+        ("priority", "2".into()),
+        ("retry", "30".into()),
+        ("expire", "600".into()),
+        ("url", "https://foo.com".into()),
+        ("sound", "siren".into()),
+      ],
+    );
+
+    assert_eq!(
+      Notification {
+        priority: Priority::Lowest,
+        ..Notification::new("foo")
+      }
+      .pushover_form("a", "b")[3],
+      ("priority", "-2".into()),
+    );
+  }
+
+  #[test]
+  fn presence_from_away() {
+    assert_eq!(Presence::from_away(Some(false)), Presence::Active);
+    assert_eq!(Presence::from_away(Some(true)), Presence::Away);
+    assert_eq!(Presence::from_away(None), Presence::Offline);
+  }
+
+  #[test]
+  fn presence_routes() {
+    let routing = config::Routing {
+      active: vec![config::Route::Irc],
+      away: vec![config::Route::Pushover],
+      offline: vec![config::Route::Email],
+      unknown: vec![config::Route::Irc, config::Route::Journal],
+    };
+
+    assert_eq!(Presence::Active.routes(&routing), [config::Route::Irc]);
+    assert_eq!(Presence::Away.routes(&routing), [config::Route::Pushover]);
+    assert_eq!(Presence::Offline.routes(&routing), [config::Route::Email]);
+    assert_eq!(
+      Presence::Unknown.routes(&routing),
+      [config::Route::Irc, config::Route::Journal],
+    );
+  }
+
+  pub(super) fn offline_config(dir: &tempfile::TempDir) -> config::Config {
+    rustls::crypto::ring::default_provider()
+      .install_default()
+      .ok();
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+      .unwrap()
+      .local_addr()
+      .unwrap()
+      .port();
+
+    let sendmail = dir.path().join("sendmail");
+    fs::write(
+      &sendmail,
+      format!("#!/bin/sh\ncat >> {}\n", dir.path().join("mail").display()),
+    )
+    .unwrap();
+    fs::set_permissions(
+      &sendmail,
+      std::os::unix::fs::PermissionsExt::from_mode(0o755),
+    )
+    .unwrap();
+
+    let mut config = config::Config::default();
+    config.irc.server = "127.0.0.1".into();
+    config.irc.port = port;
+    config.notify.relay = None;
+    config.notify.email = Some(config::Email {
+      to: "foo@bar.com".into(),
+      sendmail,
+    });
+    config
+  }
+
+  fn mail(dir: &tempfile::TempDir) -> Option<String> {
+    fs::read_to_string(dir.path().join("mail")).ok()
+  }
+
+  #[tokio::test]
+  async fn deliver_routes_unknown_presence() {
+    let dir = tempfile::TempDir::new().unwrap();
+
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let mut config = offline_config(&dir);
+    config.notify.routing.unknown = vec![config::Route::Irc, config::Route::Email];
+
+    deliver(&config, &db, &Notification::new("foo"))
+      .await
+      .unwrap();
+
+    assert!(mail(&dir).unwrap().contains("Subject: foo"));
+  }
+
+  #[tokio::test]
+  async fn send_fails_open_when_database_is_locked() {
+    let dir = tempfile::TempDir::new().unwrap();
+
+    let path = dir.path().join("foo.redb");
+
+    let _locked = Database::new(path.clone()).open().unwrap();
+
+    let mut config = offline_config(&dir);
+    config.notify.routing.unknown = vec![config::Route::Email];
+
+    send_async(&config, &Database::new(path), &Notification::new("foo"))
+      .await
+      .unwrap();
+
+    assert!(mail(&dir).unwrap().contains("Subject: foo"));
+  }
+
+  #[tokio::test]
+  async fn deliver_falls_back() {
+    let dir = tempfile::TempDir::new().unwrap();
+
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let mut config = offline_config(&dir);
+    config.notify.routing.unknown = vec![config::Route::Irc, config::Route::Webhook];
+    config.notify.fallback = vec![
+      config::Route::Ntfy,
+      config::Route::Email,
+      config::Route::Email,
+    ];
+
+    deliver(&config, &db, &Notification::new("foo"))
+      .await
+      .unwrap();
+
+    assert_eq!(mail(&dir).unwrap().matches("Subject: foo").count(), 1);
+  }
+
+  #[tokio::test]
+  async fn deliver_skips_fallback_after_success() {
+    let dir = tempfile::TempDir::new().unwrap();
+
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let mut config = offline_config(&dir);
+    config.notify.routing.unknown = vec![config::Route::Email, config::Route::Webhook];
+    config.notify.fallback = vec![config::Route::Email];
+
+    deliver(&config, &db, &Notification::new("foo"))
+      .await
+      .unwrap();
+
+    assert_eq!(mail(&dir).unwrap().matches("Subject: foo").count(), 1);
+  }
+
+  #[tokio::test]
+  async fn deliver_fails_without_route() {
+    let dir = tempfile::TempDir::new().unwrap();
+
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let mut config = offline_config(&dir);
+    config.notify.routing.unknown = vec![config::Route::Webhook];
+    config.notify.fallback = vec![config::Route::Ntfy];
+
+    assert_eq!(
+      deliver(&config, &db, &Notification::new("foo"))
+        .await
+        .unwrap_err()
+        .to_string(),
+      "ntfy notifications are not configured",
+    );
+
+    assert_eq!(mail(&dir), None);
   }
 }
@@ -0,0 +1,534 @@
+use {
+  super::*,
+  reqwest::header::{AUTHORIZATION, CONTENT_TYPE},
+};
+
+pub(super) trait Notifier {
+  async fn notify(&self, notification: &Notification) -> Result;
+}
+
+#[derive(Deserialize)]
+struct PushoverResponse {
+  receipt: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub(super) struct Receipt {
+  #[serde(default)]
+  pub(super) acknowledged: u8,
+  #[serde(default)]
+  pub(super) acknowledged_by: String,
+  #[serde(default)]
+  pub(super) expired: u8,
+}
+
+fn read_secret(path: &Path) -> Result<String> {
+  Ok(
+    fs::read_to_string(path)
+      .context(error::FilesystemIo { path })?
+      .trim()
+      .to_string(),
+  )
+}
+
+pub(super) struct Pushover<'a>(pub(super) &'a config::Pushover);
+
+impl Pushover<'_> {
+  pub(super) async fn send(&self, notification: &Notification) -> Result<Option<String>> {
+    let token = read_secret(&self.0.token_file)?;
+    let user = read_secret(&self.0.user_file)?;
+
+    let response = reqwest::Client::new()
+      .post(format!("{}/messages.json", self.api()))
+      .form(&notification.pushover_form(&token, &user))
+      .send()
+      .await
+      .context(error::PushoverSend)?
+      .error_for_status()
+      .context(error::PushoverSend)?
+      .text()
+      .await
+      .context(error::PushoverSend)?;
+
+    if notification.priority != Priority::Emergency {
+      return Ok(None);
+    }
+
+    let response = serde_json::from_str::<PushoverResponse>(&response).context(error::JsonParse)?;
+
+    let Some(receipt) = response.receipt else {
+      return Err(Error::PushoverReceipt {
+        message: "no receipt returned for emergency notification".into(),
+      });
+    };
+
+    ::log::info!("emergency notification issued with receipt {receipt}");
+
+    Ok(Some(receipt))
+  }
+
+  pub(super) async fn receipt(&self, receipt: &str) -> Result<Receipt> {
+    let token = read_secret(&self.0.token_file)?;
+
+    let response = reqwest::Client::new()
+      .get(format!("{}/receipts/{receipt}.json", self.api()))
+      .query(&[("token", token.as_str())])
+      .send()
+      .await
+      .context(error::PushoverSend)?
+      .error_for_status()
+      .context(error::PushoverSend)?
+      .text()
+      .await
+      .context(error::PushoverSend)?;
+
+    serde_json::from_str(&response).context(error::JsonParse)
+  }
+
+  fn api(&self) -> &str {
+    self.0.api.trim_end_matches('/')
+  }
+}
+
+impl Notifier for Pushover<'_> {
+  async fn notify(&self, notification: &Notification) -> Result {
+    self.send(notification).await.map(|_| ())
+  }
+}
+
+pub(super) struct Webhook<'a>(pub(super) &'a config::Webhook);
+
+impl Notifier for Webhook<'_> {
+  async fn notify(&self, notification: &Notification) -> Result {
+    let body = serde_json::to_string(&serde_json::json!({
+      "message": notification.message,
+      "title": notification.title,
+      "priority": notification.priority,
+      "url": notification.url,
+      "sound": notification.sound,
+    }))
+    .context(error::JsonParse)?;
+
+    reqwest::Client::new()
+      .post(&self.0.url)
+      .header(CONTENT_TYPE, "application/json")
+      .body(body)
+      .send()
+      .await
+      .context(error::NotificationSend {
+        route: config::Route::Webhook,
+      })?
+      .error_for_status()
+      .context(error::NotificationSend {
+        route: config::Route::Webhook,
+      })?;
+
+    Ok(())
+  }
+}
+
+pub(super) struct Ntfy<'a>(pub(super) &'a config::Ntfy);
+
+impl Ntfy<'_> {
+  fn priority(priority: Priority) -> &'static str {
+    match priority {
+      Priority::Lowest => "1",
+      Priority::Low => "2",
+      Priority::Normal => "3",
+      Priority::High => "4",
+      Priority::Emergency => "5",
+    }
+  }
+}
+
+impl Notifier for Ntfy<'_> {
+  async fn notify(&self, notification: &Notification) -> Result {
+    let mut request = reqwest::Client::new()
+      .post(format!(
+        "{}/{}",
+        self.0.url.trim_end_matches('/'),
+        self.0.topic
+      ))
+      .header("Priority", Self::priority(notification.priority))
+      .body(notification.message.clone());
+
+    if let Some(title) = &notification.title {
+      request = request.header("Title", title);
+    }
+
+    if let Some(url) = &notification.url {
+      request = request.header("Click", url);
+    }
+
+    if let Some(token_file) = &self.0.token_file {
+      request = request.header(
+        AUTHORIZATION,
+        format!("Bearer {}", read_secret(token_file)?),
+      );
+    }
+
+    request
+      .send()
+      .await
+      .context(error::NotificationSend {
+        route: config::Route::Ntfy,
+      })?
+      .error_for_status()
+      .context(error::NotificationSend {
+        route: config::Route::Ntfy,
+      })?;
+
+    Ok(())
+  }
+}
+
+pub(super) struct Email<'a>(pub(super) &'a config::Email);
+
+impl Email<'_> {
+  fn build(&self, notification: &Notification) -> Vec<u8> {
+    let subject = notification.title.clone().unwrap_or_else(|| {
+      notification
+        .message
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("notification")
+        .into()
+    });
+
+    let subject = match notification.priority {
+      Priority::High => format!("[high] {subject}"),
+      Priority::Emergency => format!("[emergency] {subject}"),
+      Priority::Lowest | Priority::Low | Priority::Normal => subject,
+    };
+
+    let mut body = notification.message.clone();
+
+    if let Some(url) = &notification.url {
+      body.push_str("\n\n");
+      body.push_str(url);
+    }
+
+    mail_builder::MessageBuilder::new()
+      .from(("Root", mail::LOCAL_ADDRESS))
+      .to(self.0.to.as_str())
+      .subject(subject)
+      .message_id(format!("{}@tulip.farm", uuid::Uuid::now_v7()))
+      .text_body(body)
+      .write_to_vec()
+      .expect("writing to Vec failed")
+  }
+}
+
+impl Notifier for Email<'_> {
+  async fn notify(&self, notification: &Notification) -> Result {
+    mail::Mail::deliver(&self.0.sendmail, &self.0.to, &self.build(notification))
+  }
+}
+
+pub(super) struct Journal;
+
+impl Notifier for Journal {
+  async fn notify(&self, notification: &Notification) -> Result {
+    let level = match notification.priority {
+      Priority::Lowest | Priority::Low => ::log::Level::Info,
+      Priority::Normal | Priority::High => ::log::Level::Warn,
+      Priority::Emergency => ::log::Level::Error,
+    };
+
+    let message = match &notification.title {
+      Some(title) => format!("{title}: {}", notification.message),
+      None => notification.message.clone(),
+    };
+
+    #[cfg(target_os = "linux")]
+    {
+      let logger = systemd_journal_logger::JournalLog::empty()
+        .context(error::JournalSend)?
+        .with_syslog_identifier("notify".into());
+
+      logger
+        .journal_send(
+          &::log::Record::builder()
+            .level(level)
+            .args(format_args!("{message}"))
+            .build(),
+        )
+        .context(error::JournalSend)?;
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+      ::log::log!(level, "{message}");
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    axum::{
+      Router,
+      body::Bytes,
+      extract::State,
+      http::{HeaderMap, StatusCode},
+      routing::post,
+    },
+  };
+
+  type Requests = Arc<Mutex<Vec<(String, HeaderMap, String)>>>;
+
+  async fn server() -> (String, Requests) {
+    let requests = Requests::default();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+    let address = listener.local_addr().unwrap();
+
+    async fn record(
+      State(requests): State<Requests>,
+      uri: axum::http::Uri,
+      headers: HeaderMap,
+      body: Bytes,
+    ) -> (StatusCode, &'static str) {
+      let path = uri.path().to_string();
+
+      let status = if path == "/fail" {
+        StatusCode::INTERNAL_SERVER_ERROR
+      } else {
+        StatusCode::OK
+      };
+
+      requests
+        .lock()
+        .unwrap()
+        .push((path, headers, String::from_utf8(body.to_vec()).unwrap()));
+
+      (status, r#"{"status":1,"request":"foo","receipt":"bar"}"#)
+    }
+
+    async fn receipt(
+      State(requests): State<Requests>,
+      uri: axum::http::Uri,
+      headers: HeaderMap,
+    ) -> &'static str {
+      requests
+        .lock()
+        .unwrap()
+        .push((uri.to_string(), headers, String::new()));
+
+      r#"{"status":1,"acknowledged":1,"acknowledged_by":"baz","expired":0}"#
+    }
+
+    let router = Router::new()
+      .route("/{*path}", post(record).get(receipt))
+      .with_state(requests.clone());
+
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    (format!("http://{address}"), requests)
+  }
+
+  fn notification() -> Notification {
+    Notification {
+      title: Some("bar".into()),
+      priority: Priority::High,
+      url: Some("https://foo.com".into()),
+      ..Notification::new("foo")
+    }
+  }
+
+  #[tokio::test]
+  async fn pushover() {
+    let (server, requests) = server().await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    fs::write(dir.path().join("token"), "a\n").unwrap();
+    fs::write(dir.path().join("user"), "b\n").unwrap();
+
+    Pushover(&config::Pushover {
+      api: format!("{server}/1/"),
+      token_file: dir.path().join("token"),
+      user_file: dir.path().join("user"),
+    })
+    .notify(&notification())
+    .await
+    .unwrap();
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].0, "/1/messages.json");
+    assert_eq!(
+      requests[0].2,
+      "token=a&user=b&message=foo&title=bar&priority=1&url=https%3A%2F%2Ffoo.com"
+    );
+  }
+
+  #[tokio::test]
+  async fn pushover_emergency() {
+    let (server, requests) = server().await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    fs::write(dir.path().join("token"), "a\n").unwrap();
+    fs::write(dir.path().join("user"), "b\n").unwrap();
+
+    tokio::time::timeout(
+      Duration::from_secs(5),
+      Pushover(&config::Pushover {
+        api: format!("{server}/1/"),
+        token_file: dir.path().join("token"),
+        user_file: dir.path().join("user"),
+      })
+      .notify(&Notification {
+        priority: Priority::Emergency,
+        ..Notification::new("foo")
+      }),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].0, "/1/messages.json");
+  }
+
+  #[tokio::test]
+  async fn pushover_receipt() {
+    let (server, requests) = server().await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    fs::write(dir.path().join("token"), "a\n").unwrap();
+    fs::write(dir.path().join("user"), "b\n").unwrap();
+
+    let pushover = config::Pushover {
+      api: format!("{server}/1/"),
+      token_file: dir.path().join("token"),
+      user_file: dir.path().join("user"),
+    };
+
+    assert_eq!(
+      Pushover(&pushover)
+        .send(&Notification {
+          priority: Priority::Emergency,
+          ..Notification::new("foo")
+        })
+        .await
+        .unwrap()
+        .as_deref(),
+      Some("bar"),
+    );
+
+    let receipt = Pushover(&pushover).receipt("bar").await.unwrap();
+    assert_eq!(receipt.acknowledged, 1);
+    assert_eq!(receipt.acknowledged_by, "baz");
+    assert_eq!(receipt.expired, 0);
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].0, "/1/receipts/bar.json?token=a");
+  }
+
+  #[tokio::test]
+  async fn webhook() {
+    let (server, requests) = server().await;
+
+    Webhook(&config::Webhook {
+      url: format!("{server}/hook"),
+    })
+    .notify(&notification())
+    .await
+    .unwrap();
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].0, "/hook");
+    assert_eq!(requests[0].1[CONTENT_TYPE], "application/json");
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(&requests[0].2).unwrap(),
+      serde_json::json!({
+        "message": "foo",
+        "title": "bar",
+        "priority": "high",
+        "url": "https://foo.com",
+        "sound": null,
+      }),
+    );
+  }
+
+  #[tokio::test]
+  async fn webhook_failure() {
+    let (server, _requests) = server().await;
+
+    let err = Webhook(&config::Webhook {
+      url: format!("{server}/fail"),
+    })
+    .notify(&notification())
+    .await
+    .unwrap_err();
+
+    assert_eq!(err.to_string(), "failed to send webhook notification");
+  }
+
+  #[tokio::test]
+  async fn ntfy() {
+    let (server, requests) = server().await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    fs::write(dir.path().join("token"), "secret\n").unwrap();
+
+    Ntfy(&config::Ntfy {
+      url: format!("{server}/"),
+      topic: "alerts".into(),
+      token_file: Some(dir.path().join("token")),
+    })
+    .notify(&notification())
+    .await
+    .unwrap();
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    let (path, headers, body) = &requests[0];
+    assert_eq!(path, "/alerts");
+    assert_eq!(headers["Title"], "bar");
+    assert_eq!(headers["Priority"], "4");
+    assert_eq!(headers["Click"], "https://foo.com");
+    assert_eq!(headers[AUTHORIZATION], "Bearer secret");
+    assert_eq!(body, "foo");
+  }
+
+  #[tokio::test]
+  async fn email() {
+    let dir = tempfile::TempDir::new().unwrap();
+
+    let output = dir.path().join("output");
+
+    let sendmail = dir.path().join("sendmail");
+    fs::write(
+      &sendmail,
+      format!("#!/bin/sh\ncat > {}\n", output.display()),
+    )
+    .unwrap();
+    fs::set_permissions(
+      &sendmail,
+      std::os::unix::fs::PermissionsExt::from_mode(0o755),
+    )
+    .unwrap();
+
+    Email(&config::Email {
+      to: "foo@bar.com".into(),
+      sendmail,
+    })
+    .notify(&notification())
+    .await
+    .unwrap();
+
+    let email = fs::read_to_string(output).unwrap();
+    assert!(email.contains("To: <foo@bar.com>"), "{email}");
+    assert!(email.contains("Subject: [high] bar"), "{email}");
+    assert!(email.contains("foo\r\n\r\nhttps://foo.com"), "{email}");
+  }
+}
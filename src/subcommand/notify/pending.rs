@@ -0,0 +1,604 @@
+use {super::*, redb::ReadableTable};
+
+const PENDING: redb::TableDefinition<&str, &str> = redb::TableDefinition::new("notify_pending");
+
+const CLAIM_TIMEOUT: i64 = 600;
+const MAX_ATTEMPTS: u32 = 10;
+const RETRY_BASE: u64 = 60;
+const RETRY_MAX: u64 = 3600;
+
+#[derive(Deserialize, Serialize)]
+pub(super) struct Entry {
+  pub(super) notification: Notification,
+  pub(super) attempts: u32,
+  pub(super) next_attempt: i64,
+  pub(super) last_error: String,
+  #[serde(default)]
+  pub(super) claimed_until: Option<i64>,
+  #[serde(default)]
+  pub(super) collapsed: u32,
+  #[serde(default)]
+  pub(super) messages: Vec<String>,
+}
+
+impl Entry {
+  fn notification(&self) -> Notification {
+    if self.collapsed == 0 {
+      return self.notification.clone();
+    }
+
+    let messages = if self.messages.is_empty() {
+      std::slice::from_ref(&self.notification.message)
+    } else {
+      self.messages.as_slice()
+    };
+
+    Notification {
+      message: format!(
+        "{}\n({} held from {})",
+        messages.join("\n"),
+        self.collapsed + 1,
+        self.notification.source(),
+      ),
+      ..self.notification.clone()
+    }
+  }
+
+  fn delay(attempts: u32) -> i64 {
+    RETRY_BASE
+      .saturating_mul(2u64.saturating_pow(attempts.saturating_sub(1)))
+      .min(RETRY_MAX)
+      .try_into()
+      .unwrap_or(i64::MAX)
+  }
+}
+
+pub(super) fn push(db: &Database, notification: &Notification, error: &Error) -> Result {
+  let id = uuid::Uuid::now_v7().to_string();
+
+  put(
+    db,
+    &id,
+    &Entry {
+      notification: notification.clone(),
+      attempts: 1,
+      next_attempt: jiff::Timestamp::now().as_second() + Entry::delay(1),
+      last_error: error.to_string(),
+      claimed_until: None,
+      collapsed: 0,
+      messages: Vec::new(),
+    },
+  )
+}
+
+pub(super) fn defer(
+  db: &Database,
+  notification: &Notification,
+  until: i64,
+  reason: &str,
+) -> Result {
+  let id = uuid::Uuid::now_v7().to_string();
+
+  put(
+    db,
+    &id,
+    &Entry {
+      notification: notification.clone(),
+      attempts: 0,
+      next_attempt: until,
+      last_error: reason.into(),
+      claimed_until: None,
+      collapsed: 0,
+      messages: Vec::new(),
+    },
+  )
+}
+
+pub(super) fn hold(db: &Database, notification: &Notification, until: i64, reason: &str) -> Result {
+  let db = db.open()?;
+
+  let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
+  {
+    let mut table = write_txn
+      .open_table(PENDING)
+      .context(error::DatabaseTable)?;
+
+    let mut held = None;
+
+    for result in table.iter().context(error::DatabaseStorage)? {
+      let (id, entry) = result.context(error::DatabaseStorage)?;
+
+      let entry = serde_json::from_str::<Entry>(entry.value()).context(error::JsonParse)?;
+
+      if entry.attempts == 0
+        && entry.claimed_until.is_none()
+        && entry.last_error == reason
+        && entry.notification.source() == notification.source()
+      {
+        held = Some((id.value().to_string(), entry));
+        break;
+      }
+    }
+
+    let (id, entry) = match held {
+      Some((id, entry)) => {
+        let mut messages = if entry.messages.is_empty() {
+          vec![entry.notification.message.clone()]
+        } else {
+          entry.messages
+        };
+
+        if !messages.contains(&notification.message) {
+          messages.push(notification.message.clone());
+        }
+
+        let notification = if notification.priority >= entry.notification.priority {
+          notification.clone()
+        } else {
+          entry.notification
+        };
+
+        (
+          id,
+          Entry {
+            notification,
+            next_attempt: entry.next_attempt.max(until),
+            collapsed: entry.collapsed + 1,
+            messages,
+            ..entry
+          },
+        )
+      }
+      None => (
+        uuid::Uuid::now_v7().to_string(),
+        Entry {
+          notification: notification.clone(),
+          attempts: 0,
+          next_attempt: until,
+          last_error: reason.into(),
+          claimed_until: None,
+          collapsed: 0,
+          messages: Vec::new(),
+        },
+      ),
+    };
+
+    let json = serde_json::to_string(&entry).context(error::JsonParse)?;
+
+    table
+      .insert(id.as_str(), json.as_str())
+      .context(error::DatabaseStorage)?;
+  }
+  write_txn.commit().context(error::DatabaseCommit)?;
+
+  Ok(())
+}
+
+pub(super) fn list(db: &Database) -> Result<Vec<(String, Entry)>> {
+  let db = db.open()?;
+
+  let read_txn = db.begin_read().context(error::DatabaseTransaction)?;
+
+  let table = match read_txn.open_table(PENDING) {
+    Ok(table) => table,
+    Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+    Err(e) => return Err(e).context(error::DatabaseTable),
+  };
+
+  let mut entries = Vec::new();
+
+  for result in table.iter().context(error::DatabaseStorage)? {
+    let (id, entry) = result.context(error::DatabaseStorage)?;
+
+    entries.push((
+      id.value().to_string(),
+      serde_json::from_str(entry.value()).context(error::JsonParse)?,
+    ));
+  }
+
+  Ok(entries)
+}
+
+pub(super) async fn flush(db: &Database, config: &config::Config, all: bool) -> Result<usize> {
+  let mut delivered = 0;
+
+  for (id, _) in list(db)? {
+    let now = jiff::Timestamp::now().as_second();
+
+    let Some(mut entry) = claim(db, &id, now, all)? else {
+      continue;
+    };
+
+    if !all
+      && entry.attempts == 0
+      && let Some((until, reason)) = throttle::gate(
+        db,
+        &config.notify.throttle,
+        &entry.notification,
+        jiff::Timestamp::now(),
+      )?
+    {
+      entry.next_attempt = until;
+      entry.claimed_until = None;
+      put(db, &id, &entry)?;
+      ::log::info!("pending notification {id} deferred again: {reason}");
+      continue;
+    }
+
+    match deliver(config, db, &entry.notification()).await {
+      Ok(()) => {
+        remove(db, &id)?;
+        delivered += 1;
+      }
+      Err(err) if !retryable(&err) => {
+        ::log::error!("dropping pending notification {id}: {err}");
+        remove(db, &id)?;
+      }
+      Err(err) if entry.attempts + 1 >= MAX_ATTEMPTS => {
+        ::log::error!("dropping pending notification {id} after {MAX_ATTEMPTS} attempts: {err}");
+        remove(db, &id)?;
+      }
+      Err(err) => {
+        entry.attempts += 1;
+        entry.next_attempt = now + Entry::delay(entry.attempts);
+        entry.last_error = err.to_string();
+        entry.claimed_until = None;
+        put(db, &id, &entry)?;
+      }
+    }
+  }
+
+  Ok(delivered)
+}
+
+fn claim(db: &Database, id: &str, now: i64, all: bool) -> Result<Option<Entry>> {
+  let db = db.open()?;
+
+  let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
+
+  let entry = {
+    let mut table = write_txn
+      .open_table(PENDING)
+      .context(error::DatabaseTable)?;
+
+    let Some(mut entry) = table
+      .get(id)
+      .context(error::DatabaseStorage)?
+      .map(|entry| serde_json::from_str::<Entry>(entry.value()))
+      .transpose()
+      .context(error::JsonParse)?
+    else {
+      return Ok(None);
+    };
+
+    if entry.claimed_until.is_some_and(|until| until > now) || (!all && entry.next_attempt > now) {
+      return Ok(None);
+    }
+
+    entry.claimed_until = Some(now + CLAIM_TIMEOUT);
+
+    let json = serde_json::to_string(&entry).context(error::JsonParse)?;
+
+    table
+      .insert(id, json.as_str())
+      .context(error::DatabaseStorage)?;
+
+    entry
+  };
+
+  write_txn.commit().context(error::DatabaseCommit)?;
+
+  Ok(Some(entry))
+}
+
+fn put(db: &Database, id: &str, entry: &Entry) -> Result {
+  let json = serde_json::to_string(entry).context(error::JsonParse)?;
+
+  let db = db.open()?;
+
+  let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
+  {
+    let mut table = write_txn
+      .open_table(PENDING)
+      .context(error::DatabaseTable)?;
+    table
+      .insert(id, json.as_str())
+      .context(error::DatabaseStorage)?;
+  }
+  write_txn.commit().context(error::DatabaseCommit)?;
+
+  Ok(())
+}
+
+fn remove(db: &Database, id: &str) -> Result {
+  let db = db.open()?;
+
+  let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
+  {
+    let mut table = write_txn
+      .open_table(PENDING)
+      .context(error::DatabaseTable)?;
+    table.remove(id).context(error::DatabaseStorage)?;
+  }
+  write_txn.commit().context(error::DatabaseCommit)?;
+
+  Ok(())
+}
+
+#[derive(clap::Args)]
+pub(super) struct Pending {
+  #[arg(long)]
+  db: Option<PathBuf>,
+}
+
+impl Pending {
+  pub(super) fn run(self) -> Result {
+    let entries = list(&Database::new(self.db.unwrap_or_else(db_path)))?
+      .into_iter()
+      .map(|(id, entry)| {
+        serde_json::json!({
+          "id": id,
+          "message": entry.notification.message,
+          "title": entry.notification.title,
+          "priority": entry.notification.priority,
+          "source": entry.notification.source,
+          "attempts": entry.attempts,
+          "next_attempt": jiff::Timestamp::from_second(entry.next_attempt)
+            .map(|timestamp| timestamp.to_string())
+            .unwrap_or_default(),
+          "last_error": entry.last_error,
+        })
+      })
+      .collect::<Vec<serde_json::Value>>();
+
+    println!(
+      "{}",
+      serde_json::to_string_pretty(&entries).context(error::JsonParse)?
+    );
+
+    Ok(())
+  }
+}
+
+#[derive(clap::Args)]
+pub(super) struct Flush {
+  #[arg(long)]
+  db: Option<PathBuf>,
+}
+
+impl Flush {
+  pub(super) fn run(self) -> Result {
+    let db = Database::new(self.db.unwrap_or_else(db_path));
+
+    let config = config::Config::load_or_default(&config::Config::path());
+
+    let rt = tokio::runtime::Runtime::new().context(error::TokioRuntime)?;
+
+    let delivered = rt.block_on(async {
+      summarize(&db, &config)?;
+      let delivered = flush(&db, &config, true).await?;
+      receipt::check(&db, &config).await?;
+      Ok(delivered)
+    })?;
+
+    println!("{delivered} delivered, {} pending", list(&db)?.len());
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn delay() {
+    assert_eq!(Entry::delay(1), 60);
+    assert_eq!(Entry::delay(2), 120);
+    assert_eq!(Entry::delay(3), 240);
+    assert_eq!(Entry::delay(7), 3600);
+    assert_eq!(Entry::delay(100), 3600);
+  }
+
+  #[test]
+  fn push_and_list() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    assert!(list(&db).unwrap().is_empty());
+
+    push(
+      &db,
+      &Notification {
+        title: Some("bar".into()),
+        priority: Priority::High,
+        ..Notification::new("foo")
+      },
+      &Error::IrcClosed,
+    )
+    .unwrap();
+
+    let entries = list(&db).unwrap();
+    assert_eq!(entries.len(), 1);
+
+    let (id, entry) = &entries[0];
+    assert_eq!(entry.notification.message, "foo");
+    assert_eq!(entry.notification.title.as_deref(), Some("bar"));
+    assert_eq!(entry.notification.priority, Priority::High);
+    assert_eq!(entry.attempts, 1);
+    assert_eq!(entry.last_error, "IRC connection closed");
+    assert!(entry.next_attempt > jiff::Timestamp::now().as_second());
+
+    remove(&db, id).unwrap();
+    assert!(list(&db).unwrap().is_empty());
+  }
+
+  #[test]
+  fn claim() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    push(&db, &Notification::new("foo"), &Error::IrcClosed).unwrap();
+
+    let (id, entry) = list(&db).unwrap().remove(0);
+
+    let now = jiff::Timestamp::now().as_second();
+
+    assert!(super::claim(&db, &id, now, false).unwrap().is_none());
+    assert!(
+      super::claim(&db, &id, entry.next_attempt, false)
+        .unwrap()
+        .is_some()
+    );
+    assert!(
+      super::claim(&db, &id, entry.next_attempt, true)
+        .unwrap()
+        .is_none()
+    );
+    assert!(
+      super::claim(&db, &id, entry.next_attempt + CLAIM_TIMEOUT, false)
+        .unwrap()
+        .is_some()
+    );
+    assert!(super::claim(&db, "bar", now, true).unwrap().is_none());
+  }
+
+  fn sendmail(dir: &tempfile::TempDir, status: u8) {
+    fs::write(
+      dir.path().join("sendmail"),
+      format!(
+        "#!/bin/sh\ncat >> {}\nexit {status}\n",
+        dir.path().join("mail").display()
+      ),
+    )
+    .unwrap();
+  }
+
+  #[tokio::test]
+  async fn flush() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let mut config = notify::tests::offline_config(&dir);
+    config.notify.routing.unknown = vec![config::Route::Email];
+
+    sendmail(&dir, 1);
+
+    push(&db, &Notification::new("foo"), &Error::IrcClosed).unwrap();
+
+    assert_eq!(super::flush(&db, &config, false).await.unwrap(), 0);
+    assert_eq!(list(&db).unwrap()[0].1.attempts, 1);
+
+    let before = jiff::Timestamp::now().as_second();
+
+    assert_eq!(super::flush(&db, &config, true).await.unwrap(), 0);
+
+    let (id, entry) = list(&db).unwrap().remove(0);
+    assert_eq!(entry.attempts, 2);
+    assert_eq!(entry.claimed_until, None);
+    assert!(entry.next_attempt >= before + Entry::delay(2));
+    assert!(
+      entry.last_error.contains("failed to send"),
+      "{}",
+      entry.last_error
+    );
+
+    sendmail(&dir, 0);
+
+    assert_eq!(super::flush(&db, &config, true).await.unwrap(), 1);
+    assert!(list(&db).unwrap().is_empty());
+
+    sendmail(&dir, 1);
+
+    put(
+      &db,
+      &id,
+      &Entry {
+        attempts: MAX_ATTEMPTS - 1,
+        ..entry
+      },
+    )
+    .unwrap();
+
+    assert_eq!(super::flush(&db, &config, true).await.unwrap(), 0);
+    assert!(list(&db).unwrap().is_empty());
+  }
+
+  #[test]
+  fn hold() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let source = |source: &str, message: &str, priority: Priority| Notification {
+      source: Some(source.into()),
+      priority,
+      ..Notification::new(message)
+    };
+
+    super::hold(&db, &source("a", "foo", Priority::High), 100, "rate limit").unwrap();
+    super::hold(&db, &source("a", "bar", Priority::Low), 200, "rate limit").unwrap();
+    super::hold(&db, &source("a", "foo", Priority::Low), 100, "rate limit").unwrap();
+    super::hold(&db, &source("a", "baz", Priority::Low), 100, "quiet hours").unwrap();
+    super::hold(&db, &source("b", "qux", Priority::Low), 100, "rate limit").unwrap();
+
+    let entries = list(&db).unwrap();
+    assert_eq!(entries.len(), 3);
+
+    let (_, entry) = &entries[0];
+    assert_eq!(entry.collapsed, 2);
+    assert_eq!(entry.next_attempt, 200);
+
+    let notification = entry.notification();
+    assert_eq!(notification.message, "foo\nbar\n(3 held from a)");
+    assert_eq!(notification.priority, Priority::High);
+
+    assert_eq!(entries[1].1.notification().message, "baz");
+    assert_eq!(entries[2].1.notification().message, "qux");
+  }
+
+  #[tokio::test]
+  async fn flush_applies_rate_limit() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let mut config = notify::tests::offline_config(&dir);
+    config.notify.routing.unknown = vec![config::Route::Email];
+    config.notify.throttle.rate_limit = Some(1);
+    config.notify.throttle.rate_window = 3600;
+
+    sendmail(&dir, 0);
+
+    let now = jiff::Timestamp::now().as_second();
+
+    defer(&db, &Notification::new("foo"), now, "quiet hours").unwrap();
+    defer(&db, &Notification::new("bar"), now, "quiet hours").unwrap();
+
+    assert_eq!(super::flush(&db, &config, false).await.unwrap(), 1);
+
+    let entries = list(&db).unwrap();
+    assert_eq!(entries.len(), 1);
+
+    let (_, entry) = &entries[0];
+    assert_eq!(entry.notification.message, "bar");
+    assert_eq!(entry.attempts, 0);
+    assert_eq!(entry.claimed_until, None);
+    assert!(entry.next_attempt >= now + 3600);
+
+    assert_eq!(super::flush(&db, &config, false).await.unwrap(), 0);
+    assert_eq!(list(&db).unwrap().len(), 1);
+  }
+
+  #[tokio::test]
+  async fn flush_drops_unconfigured() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let mut config = notify::tests::offline_config(&dir);
+    config.notify.routing.unknown = vec![config::Route::Webhook];
+
+    push(&db, &Notification::new("foo"), &Error::IrcClosed).unwrap();
+
+    assert_eq!(super::flush(&db, &config, true).await.unwrap(), 0);
+    assert!(list(&db).unwrap().is_empty());
+  }
+}
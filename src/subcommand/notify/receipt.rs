@@ -0,0 +1,153 @@
+use {super::*, redb::ReadableTable};
+
+const RECEIPTS: redb::TableDefinition<&str, &str> = redb::TableDefinition::new("notify_receipts");
+
+const RECEIPT_GRACE: i64 = 3600;
+
+#[derive(Deserialize, Serialize)]
+struct Entry {
+  notification: Notification,
+  expires: i64,
+}
+
+pub(super) fn track(db: &Database, receipt: &str, notification: &Notification) -> Result {
+  put(
+    db,
+    receipt,
+    &Entry {
+      notification: notification.clone(),
+      expires: jiff::Timestamp::now()
+        .as_second()
+        .saturating_add(notification.expire.try_into().unwrap_or(i64::MAX))
+        .saturating_add(RECEIPT_GRACE),
+    },
+  )
+}
+
+fn put(db: &Database, receipt: &str, entry: &Entry) -> Result {
+  let json = serde_json::to_string(entry).context(error::JsonParse)?;
+
+  let db = db.open()?;
+
+  let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
+  {
+    let mut table = write_txn
+      .open_table(RECEIPTS)
+      .context(error::DatabaseTable)?;
+    table
+      .insert(receipt, json.as_str())
+      .context(error::DatabaseStorage)?;
+  }
+  write_txn.commit().context(error::DatabaseCommit)?;
+
+  Ok(())
+}
+
+fn list(db: &Database) -> Result<Vec<(String, Entry)>> {
+  let db = db.open()?;
+
+  let read_txn = db.begin_read().context(error::DatabaseTransaction)?;
+
+  let table = match read_txn.open_table(RECEIPTS) {
+    Ok(table) => table,
+    Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+    Err(e) => return Err(e).context(error::DatabaseTable),
+  };
+
+  let mut entries = Vec::new();
+
+  for result in table.iter().context(error::DatabaseStorage)? {
+    let (receipt, entry) = result.context(error::DatabaseStorage)?;
+
+    entries.push((
+      receipt.value().to_string(),
+      serde_json::from_str(entry.value()).context(error::JsonParse)?,
+    ));
+  }
+
+  Ok(entries)
+}
+
+pub(super) async fn check(db: &Database, config: &config::Config) -> Result {
+  for (receipt, entry) in list(db)? {
+    match notifier::Pushover(&config.notify.pushover)
+      .receipt(&receipt)
+      .await
+    {
+      Ok(status) if status.acknowledged == 1 => {
+        ::log::info!(
+          "emergency notification acknowledged by {}",
+          status.acknowledged_by,
+        );
+        remove(db, &receipt)?;
+      }
+      Ok(status) if status.expired == 1 => {
+        ::log::error!(
+          "emergency notification expired without acknowledgement: {}",
+          entry.notification.message,
+        );
+        remove(db, &receipt)?;
+      }
+      Ok(_) => {}
+      Err(err) if entry.expires <= jiff::Timestamp::now().as_second() => {
+        ::log::error!("giving up on emergency notification receipt {receipt}: {err}");
+        remove(db, &receipt)?;
+      }
+      Err(err) => ::log::warn!("failed to check emergency notification receipt {receipt}: {err}"),
+    }
+  }
+
+  Ok(())
+}
+
+fn remove(db: &Database, receipt: &str) -> Result {
+  let db = db.open()?;
+
+  let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
+  {
+    let mut table = write_txn
+      .open_table(RECEIPTS)
+      .context(error::DatabaseTable)?;
+    table.remove(receipt).context(error::DatabaseStorage)?;
+  }
+  write_txn.commit().context(error::DatabaseCommit)?;
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn check_keeps_unreachable_receipts_until_expired() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let mut config = notify::tests::offline_config(&dir);
+    config.notify.pushover.api = "http://127.0.0.1:1".into();
+
+    track(&db, "foo", &Notification::new("bar")).unwrap();
+
+    put(
+      &db,
+      "baz",
+      &Entry {
+        notification: Notification::new("qux"),
+        expires: 0,
+      },
+    )
+    .unwrap();
+
+    check(&db, &config).await.unwrap();
+
+    assert_eq!(
+      list(&db)
+        .unwrap()
+        .into_iter()
+        .map(|(receipt, _)| receipt)
+        .collect::<Vec<String>>(),
+      ["foo"],
+    );
+  }
+}
@@ -0,0 +1,565 @@
+use {super::*, jiff::ToSpan, redb::ReadableTable};
+
+const DEDUP: redb::TableDefinition<&str, &str> = redb::TableDefinition::new("notify_dedup");
+const RATE: redb::TableDefinition<&str, &str> = redb::TableDefinition::new("notify_rate");
+
+#[derive(Deserialize, Serialize)]
+struct Window {
+  start: i64,
+  count: u32,
+  notification: Notification,
+}
+
+fn key(notification: &Notification) -> String {
+  format!(
+    "{}\0{}\0{}",
+    notification.source(),
+    notification.title.as_deref().unwrap_or_default(),
+    notification.message,
+  )
+}
+
+fn summary(notification: &Notification, count: u32) -> Notification {
+  if count <= 1 {
+    return notification.clone();
+  }
+
+  Notification {
+    message: format!("{} (x{count})", notification.message),
+    ..notification.clone()
+  }
+}
+
+pub(super) fn dedup(
+  db: &Database,
+  config: &config::Throttle,
+  notification: &Notification,
+  now: jiff::Timestamp,
+) -> Result<Option<Notification>> {
+  let now = now.as_second();
+
+  let key = key(notification);
+
+  let db = db.open()?;
+
+  let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
+
+  let result = {
+    let mut table = write_txn.open_table(DEDUP).context(error::DatabaseTable)?;
+
+    let window = table
+      .get(key.as_str())
+      .context(error::DatabaseStorage)?
+      .map(|value| serde_json::from_str::<Window>(value.value()))
+      .transpose()
+      .context(error::JsonParse)?;
+
+    let (window, result) = match window {
+      Some(mut window) if now - window.start < config.dedup_window as i64 => {
+        window.count += 1;
+        (window, None)
+      }
+      window => {
+        let result = match window {
+          Some(window) => summary(notification, window.count + 1),
+          None => notification.clone(),
+        };
+
+        (
+          Window {
+            start: now,
+            count: 0,
+            notification: notification.clone(),
+          },
+          Some(result),
+        )
+      }
+    };
+
+    table
+      .insert(
+        key.as_str(),
+        serde_json::to_string(&window)
+          .context(error::JsonParse)?
+          .as_str(),
+      )
+      .context(error::DatabaseStorage)?;
+
+    result
+  };
+
+  write_txn.commit().context(error::DatabaseCommit)?;
+
+  Ok(result)
+}
+
+pub(super) fn summaries(
+  db: &Database,
+  config: &config::Throttle,
+  now: jiff::Timestamp,
+) -> Result<Vec<Notification>> {
+  let now = now.as_second();
+
+  let db = db.open()?;
+
+  let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
+
+  let mut summaries = Vec::new();
+
+  {
+    let mut table = write_txn.open_table(DEDUP).context(error::DatabaseTable)?;
+
+    let mut expired = Vec::new();
+
+    for result in table.iter().context(error::DatabaseStorage)? {
+      let (key, value) = result.context(error::DatabaseStorage)?;
+
+      let window = serde_json::from_str::<Window>(value.value()).context(error::JsonParse)?;
+
+      if now - window.start >= config.dedup_window as i64 {
+        expired.push((key.value().to_string(), window));
+      }
+    }
+
+    for (key, mut window) in expired {
+      if window.count == 0 {
+        table.remove(key.as_str()).context(error::DatabaseStorage)?;
+        continue;
+      }
+
+      summaries.push(summary(&window.notification, window.count));
+
+      window.start = now;
+      window.count = 0;
+
+      table
+        .insert(
+          key.as_str(),
+          serde_json::to_string(&window)
+            .context(error::JsonParse)?
+            .as_str(),
+        )
+        .context(error::DatabaseStorage)?;
+    }
+  }
+
+  write_txn.commit().context(error::DatabaseCommit)?;
+
+  Ok(summaries)
+}
+
+pub(super) fn gate(
+  db: &Database,
+  config: &config::Throttle,
+  notification: &Notification,
+  now: jiff::Timestamp,
+) -> Result<Option<(i64, String)>> {
+  if let Some(quiet_hours) = &config.quiet_hours
+    && !(quiet_hours.bypass && notification.priority >= Priority::High)
+  {
+    match quiet(quiet_hours, now) {
+      Ok(Some(end)) => return Ok(Some((end, "quiet hours".into()))),
+      Ok(None) => {}
+      Err(err) => ::log::error!("ignoring quiet hours: {err}"),
+    }
+  }
+
+  let Some(limit) = config.rate_limit else {
+    return Ok(None);
+  };
+
+  let now = now.as_second();
+
+  let source = notification.source();
+
+  let db = db.open()?;
+
+  let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
+
+  let result = {
+    let mut table = write_txn.open_table(RATE).context(error::DatabaseTable)?;
+
+    let mut sent = table
+      .get(source)
+      .context(error::DatabaseStorage)?
+      .map(|value| serde_json::from_str::<Vec<i64>>(value.value()))
+      .transpose()
+      .context(error::JsonParse)?
+      .unwrap_or_default();
+
+    sent.retain(|time| now - time < config.rate_window as i64);
+
+    let result = if sent.len() >= limit as usize {
+      Some((
+        sent[0] + config.rate_window as i64,
+        format!("rate limit for {source}"),
+      ))
+    } else {
+      sent.push(now);
+      None
+    };
+
+    table
+      .insert(
+        source,
+        serde_json::to_string(&sent)
+          .context(error::JsonParse)?
+          .as_str(),
+      )
+      .context(error::DatabaseStorage)?;
+
+    result
+  };
+
+  write_txn.commit().context(error::DatabaseCommit)?;
+
+  Ok(result)
+}
+
+fn quiet(config: &config::QuietHours, now: jiff::Timestamp) -> Result<Option<i64>> {
+  let invalid = |message: String| Error::QuietHours { message };
+
+  let (start, end, time_zone) = config.window()?;
+
+  let now = now.to_zoned(time_zone);
+
+  let time = now.time();
+
+  let quiet = if start <= end {
+    start <= time && time < end
+  } else {
+    time >= start || time < end
+  };
+
+  if !quiet {
+    return Ok(None);
+  }
+
+  let mut until = now
+    .with()
+    .time(end)
+    .build()
+    .map_err(|err| invalid(err.to_string()))?;
+
+  if until <= now {
+    until = until
+      .checked_add(1.day())
+      .map_err(|err| invalid(err.to_string()))?;
+  }
+
+  Ok(Some(until.timestamp().as_second()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn throttle() -> config::Throttle {
+    config::Throttle {
+      dedup_window: 600,
+      rate_limit: Some(2),
+      rate_window: 3600,
+      quiet_hours: None,
+    }
+  }
+
+  fn timestamp(s: &str) -> jiff::Timestamp {
+    s.parse().unwrap()
+  }
+
+  #[test]
+  fn dedup_and_summaries() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let config = throttle();
+
+    let foo = Notification::new("foo");
+
+    let t0 = timestamp("2026-01-01T00:00:00Z");
+
+    assert_eq!(
+      dedup(&db, &config, &foo, t0).unwrap().unwrap().message,
+      "foo"
+    );
+    assert!(
+      dedup(&db, &config, &foo, t0 + 60.seconds())
+        .unwrap()
+        .is_none()
+    );
+    assert!(
+      dedup(&db, &config, &foo, t0 + 120.seconds())
+        .unwrap()
+        .is_none()
+    );
+
+    assert_eq!(
+      dedup(&db, &config, &Notification::new("bar"), t0)
+        .unwrap()
+        .unwrap()
+        .message,
+      "bar"
+    );
+
+    assert!(
+      summaries(&db, &config, t0 + 300.seconds())
+        .unwrap()
+        .is_empty()
+    );
+
+    let summaries = super::summaries(&db, &config, t0 + 600.seconds()).unwrap();
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(summaries[0].message, "foo (x2)");
+
+    assert!(
+      dedup(&db, &config, &foo, t0 + 700.seconds())
+        .unwrap()
+        .is_none()
+    );
+
+    assert_eq!(
+      dedup(&db, &config, &foo, t0 + 1300.seconds())
+        .unwrap()
+        .unwrap()
+        .message,
+      "foo (x2)"
+    );
+
+    assert_eq!(
+      dedup(&db, &config, &foo, t0 + 2000.seconds())
+        .unwrap()
+        .unwrap()
+        .message,
+      "foo"
+    );
+  }
+
+  #[test]
+  fn summary_counts() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let config = throttle();
+
+    let foo = Notification::new("foo");
+
+    let t0 = timestamp("2026-01-01T00:00:00Z");
+
+    dedup(&db, &config, &foo, t0).unwrap().unwrap();
+
+    assert!(
+      dedup(&db, &config, &foo, t0 + 60.seconds())
+        .unwrap()
+        .is_none()
+    );
+
+    let summaries = super::summaries(&db, &config, t0 + 600.seconds()).unwrap();
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(summaries[0].message, "foo");
+
+    assert_eq!(
+      dedup(&db, &config, &foo, t0 + 1300.seconds())
+        .unwrap()
+        .unwrap()
+        .message,
+      "foo"
+    );
+  }
+
+  #[test]
+  fn dedup_by_source() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let config = throttle();
+
+    let t0 = timestamp("2026-01-01T00:00:00Z");
+
+    assert!(
+      dedup(&db, &config, &Notification::new("foo"), t0)
+        .unwrap()
+        .is_some()
+    );
+
+    assert!(
+      dedup(
+        &db,
+        &config,
+        &Notification {
+          source: Some("bar".into()),
+          ..Notification::new("foo")
+        },
+        t0,
+      )
+      .unwrap()
+      .is_some()
+    );
+  }
+
+  #[test]
+  fn rate_limit() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let config = throttle();
+
+    let t0 = timestamp("2026-01-01T00:00:00Z");
+
+    let foo = Notification::new("foo");
+
+    assert_eq!(gate(&db, &config, &foo, t0).unwrap(), None);
+    assert_eq!(gate(&db, &config, &foo, t0 + 10.seconds()).unwrap(), None);
+    assert_eq!(
+      gate(&db, &config, &foo, t0 + 20.seconds()).unwrap(),
+      Some((t0.as_second() + 3600, "rate limit for default".into())),
+    );
+
+    let bar = Notification {
+      source: Some("bar".into()),
+      ..Notification::new("foo")
+    };
+
+    assert_eq!(gate(&db, &config, &bar, t0 + 20.seconds()).unwrap(), None);
+
+    assert_eq!(gate(&db, &config, &foo, t0 + 3600.seconds()).unwrap(), None);
+
+    let unlimited = config::Throttle {
+      rate_limit: None,
+      ..throttle()
+    };
+
+    for _ in 0..10 {
+      assert_eq!(gate(&db, &unlimited, &foo, t0).unwrap(), None);
+    }
+  }
+
+  #[test]
+  fn quiet_hours() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    let config = config::Throttle {
+      rate_limit: None,
+      quiet_hours: Some(config::QuietHours {
+        start: "22:00".into(),
+        end: "08:00".into(),
+        time_zone: "America/New_York".into(),
+        bypass: true,
+      }),
+      ..throttle()
+    };
+
+    let foo = Notification::new("foo");
+
+    assert_eq!(
+      gate(&db, &config, &foo, timestamp("2026-01-01T20:00:00Z")).unwrap(),
+      None
+    );
+
+    assert_eq!(
+      gate(&db, &config, &foo, timestamp("2026-01-02T04:00:00Z")).unwrap(),
+      Some((
+        timestamp("2026-01-02T13:00:00Z").as_second(),
+        "quiet hours".into()
+      )),
+    );
+
+    assert_eq!(
+      gate(&db, &config, &foo, timestamp("2026-01-02T12:00:00Z")).unwrap(),
+      Some((
+        timestamp("2026-01-02T13:00:00Z").as_second(),
+        "quiet hours".into()
+      )),
+    );
+
+    assert_eq!(
+      gate(&db, &config, &foo, timestamp("2026-01-02T13:00:00Z")).unwrap(),
+      None
+    );
+
+    let high = Notification {
+      priority: Priority::High,
+      ..Notification::new("foo")
+    };
+
+    assert_eq!(
+      gate(&db, &config, &high, timestamp("2026-01-02T04:00:00Z")).unwrap(),
+      None
+    );
+
+    let strict = config::Throttle {
+      quiet_hours: Some(config::QuietHours {
+        bypass: false,
+        ..config.quiet_hours.clone().unwrap()
+      }),
+      ..throttle()
+    };
+
+    assert!(
+      gate(&db, &strict, &high, timestamp("2026-01-02T04:00:00Z"))
+        .unwrap()
+        .is_some()
+    );
+  }
+
+  #[test]
+  fn quiet_hours_same_day() {
+    let config = config::QuietHours {
+      start: "09:00".into(),
+      end: "17:00".into(),
+      time_zone: "UTC".into(),
+      bypass: true,
+    };
+
+    assert_eq!(
+      quiet(&config, timestamp("2026-01-01T08:59:00Z")).unwrap(),
+      None
+    );
+    assert_eq!(
+      quiet(&config, timestamp("2026-01-01T12:00:00Z")).unwrap(),
+      Some(timestamp("2026-01-01T17:00:00Z").as_second())
+    );
+    assert_eq!(
+      quiet(&config, timestamp("2026-01-01T17:00:00Z")).unwrap(),
+      None
+    );
+  }
+
+  #[test]
+  fn quiet_hours_invalid() {
+    let config = config::QuietHours {
+      start: "foo".into(),
+      end: "17:00".into(),
+      time_zone: "UTC".into(),
+      bypass: true,
+    };
+
+    assert!(quiet(&config, timestamp("2026-01-01T12:00:00Z")).is_err());
+
+    let config = config::QuietHours {
+      start: "09:00".into(),
+      time_zone: "Foo/Bar".into(),
+      ..config
+    };
+
+    assert!(quiet(&config, timestamp("2026-01-01T12:00:00Z")).is_err());
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let db = Database::new(dir.path().join("foo.redb"));
+
+    assert_eq!(
+      gate(
+        &db,
+        &config::Throttle {
+          rate_limit: None,
+          quiet_hours: Some(config),
+          ..throttle()
+        },
+        &Notification::new("foo"),
+        timestamp("2026-01-01T12:00:00Z"),
+      )
+      .unwrap(),
+      None,
+    );
+  }
+}
@@ -9,7 +9,7 @@ pub(crate) struct Reset {
 
 impl Reset {
   pub(crate) fn run(self) -> Result {
-    let db_path = self.db.unwrap_or_else(db_path);
-    reset_session(&db_path, &self.session)
+    let db = Database::new(self.db.unwrap_or_else(db_path));
+    reset_session(&db, &self.session)
   }
 }
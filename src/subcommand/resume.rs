@@ -33,8 +33,8 @@ impl Resume {
     let uuid = match self.session {
       Session::Uuid(uuid) => uuid.to_string(),
       Session::Name(name) => {
-        let db_path = self.db.unwrap_or_else(db_path);
-        let (uuid, resume) = lookup_session(&db_path, &name)?;
+        let db = Database::new(self.db.unwrap_or_else(db_path));
+        let (uuid, resume) = lookup_session(&db, &name)?;
         if !resume {
           return Err(Error::SessionNotFound { name });
         }
@@ -42,14 +42,18 @@ impl Resume {
       }
     };
 
-    let session_dir = Path::new(SESSION_DIR).join(&uuid);
+    Err(exec(&self.claude, &uuid))
+  }
+}
 
-    let err = Command::new(&self.claude)
-      .arg("--resume")
-      .arg(&uuid)
-      .current_dir(&session_dir)
-      .exec();
+pub(crate) fn exec(claude: &Path, uuid: &str) -> Error {
+  let session_dir = Path::new(SESSION_DIR).join(uuid);
 
-    Err(Error::AgentInvocation { source: err })
-  }
+  let err = Command::new(claude)
+    .arg("--resume")
+    .arg(uuid)
+    .current_dir(&session_dir)
+    .exec();
+
+  Error::AgentInvocation { source: err }
 }
@@ -20,13 +20,13 @@ pub(crate) struct Task {
 
 impl Task {
   pub(crate) fn run(self) -> Result {
-    let db_path = self.db.unwrap_or_else(db_path);
+    let db = Database::new(self.db.unwrap_or_else(db_path));
 
     let body =
       fs::read_to_string(&self.prompt).context(error::FilesystemIo { path: &self.prompt })?;
 
     let (session, resume) = if let Some(ref name) = self.session {
-      lookup_session(&db_path, name)?
+      lookup_session(&db, name)?
     } else {
       (uuid::Uuid::now_v7().to_string(), false)
     };
@@ -44,24 +44,14 @@ impl Task {
     if let Some(ref name) = self.session
       && !resume
     {
-      save_session(&db_path, name, &session)?;
+      save_session(&db, name, &session)?;
     }
 
     let html = mail::Mail::markdown_to_html(&response);
 
     let message_id = format!("{}@tulip.farm", uuid::Uuid::now_v7());
 
-    let db = redb::Database::create(&db_path).context(error::DatabaseOpen { path: &db_path })?;
-    let write_txn = db.begin_write().context(error::DatabaseTransaction)?;
-    {
-      let mut table = write_txn
-        .open_table(mail::THREADS)
-        .context(error::DatabaseTable)?;
-      table
-        .insert(message_id.as_str(), session.as_str())
-        .context(error::DatabaseStorage)?;
-    }
-    write_txn.commit().context(error::DatabaseCommit)?;
+    mail::Mail::register_thread(&db, &message_id, &session)?;
 
     let mut subject = self.name.clone();
     if let Some(first) = subject.get_mut(..1) {
@@ -80,16 +70,6 @@ impl Task {
 
     mail::Mail::save_to_maildir(&self.dir, &email)?;
 
-    let envelope = lettre::address::Envelope::new(
-      Some("root@tulip.farm".parse().unwrap()),
-      vec!["casey@rodarmor.com".parse().unwrap()],
-    )
-    .unwrap();
-
-    let transport = lettre::SendmailTransport::new_with_command(&self.sendmail);
-
-    lettre::Transport::send_raw(&transport, &envelope, &email).context(error::Send)?;
-
-    Ok(())
+    mail::Mail::deliver(&self.sendmail, "casey@rodarmor.com", &email)
   }
 }
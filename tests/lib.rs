@@ -12,4 +12,5 @@ mod chat;
 mod expected;
 mod log;
 mod mail;
+mod notify;
 mod test;
@@ -481,3 +481,599 @@ fn agent_failure() {
     .stderr_regex("error: agent exited with .*\n.*")
     .failure();
 }
+
+#[test]
+fn delivery_report_does_not_invoke_agent() {
+  use redb::ReadableDatabase;
+
+  let sendmail = find_in_path("true");
+  let test = Test::new();
+  let dir = test.path().to_str().unwrap().to_string();
+  let db = test.path().join("db.redb");
+
+  let threads = redb::TableDefinition::<&str, &str>::new("threads");
+  let delivery_failures = redb::TableDefinition::<&str, &str>::new("delivery_failures");
+
+  {
+    let database = redb::Database::create(&db).unwrap();
+    let write_txn = database.begin_write().unwrap();
+    write_txn
+      .open_table(threads)
+      .unwrap()
+      .insert("reply@tulip.farm", "foo-session")
+      .unwrap();
+    write_txn.commit().unwrap();
+  }
+
+  let notification = test.path().join("notification");
+  let notify_sendmail = write_script(
+    test.path(),
+    "notify-sendmail",
+    &format!("#!/bin/sh\ncat > {}\n", notification.display()),
+  );
+
+  let config = test.path().join("config.json");
+  std::fs::write(
+    &config,
+    format!(
+      r#"{{
+        "irc": {{"server": "127.0.0.1", "port": 1}},
+        "notify": {{
+          "relay": null,
+          "routing": {{
+            "active": ["email"],
+            "away": ["email"],
+            "offline": ["email"],
+            "unknown": ["email"]
+          }},
+          "email": {{"to": "foo@bar.com", "sendmail": "{notify_sendmail}"}}
+        }}
+      }}"#
+    ),
+  )
+  .unwrap();
+
+  let test = test
+    .args([
+      "mail",
+      "--dir",
+      &dir,
+      "--sendmail",
+      &sendmail,
+      "--db",
+      db.to_str().unwrap(),
+      "--claude",
+      "/nonexistent",
+      "--config",
+      config.to_str().unwrap(),
+    ])
+    .stdin(
+      b"From: MAILER-DAEMON@tulip.farm\r\nMessage-ID: <bounce@tulip.farm>\r\n\
+        Content-Type: multipart/report; report-type=delivery-status; boundary=bound\r\n\r\n\
+        --bound\r\n\
+        Content-Type: message/delivery-status\r\n\r\n\
+        Reporting-MTA: dns; tulip.farm\r\n\r\n\
+        Final-Recipient: rfc822; foo@bar.com\r\n\
+        Action: failed\r\n\
+        Status: 5.1.1\r\n\r\n\
+        --bound\r\n\
+        Content-Type: text/rfc822-headers\r\n\r\n\
+        Message-ID: <reply@tulip.farm>\r\n\
+        --bound--\r\n",
+    )
+    .success();
+
+  assert_eq!(
+    std::fs::read_dir(test.path().join("new")).unwrap().count(),
+    1
+  );
+
+  let database = redb::Database::create(&db).unwrap();
+  let read_txn = database.begin_read().unwrap();
+  assert_eq!(
+    read_txn
+      .open_table(delivery_failures)
+      .unwrap()
+      .get("reply@tulip.farm")
+      .unwrap()
+      .unwrap()
+      .value(),
+    "foo@bar.com 5.1.1",
+  );
+
+  let notification = std::fs::read_to_string(notification).unwrap();
+  assert!(
+    notification.contains("delivery failed: reply@tulip.farm (session foo-session)"),
+    "{notification}",
+  );
+  assert!(notification.contains("foo@bar.com 5.1.1"), "{notification}");
+}
+
+#[test]
+fn search() {
+  let test = Test::new();
+  let sendmail = write_sendmail(test.path(), "#!/bin/sh\ncat > /dev/null\n");
+  let claude = write_claude(test.path(), "#!/bin/sh\ncat > /dev/null\necho bar\n");
+  let dir = test.path().join("mail");
+  let dir = dir.to_str().unwrap().to_string();
+  let db = test.path().join("db.redb");
+  let db = db.to_str().unwrap().to_string();
+  let sessions = test.path().join("sessions");
+  let sessions = sessions.to_str().unwrap().to_string();
+
+  let test = test
+    .args([
+      "mail",
+      "--dir",
+      &dir,
+      "--sendmail",
+      &sendmail,
+      "--db",
+      &db,
+      "--claude",
+      &claude,
+      "--session-dir",
+      &sessions,
+    ])
+    .stdin(b"From: foo@bar.com\r\nMessage-ID: <foo@bar>\r\nSubject: qux\r\n\r\nbaz")
+    .success();
+
+  let test = test
+    .args(["mail", "search", "baz", "--dir", &dir, "--db", &db])
+    .stdout_regex("[0-9a-f-]{36} foo@bar\n   foo@bar.com qux\n")
+    .success();
+
+  test
+    .args([
+      "mail", "search", "bar", "--dir", &dir, "--db", &db, "--from", "root",
+    ])
+    .stdout_regex(
+      r"[0-9a-f-]{36} foo@bar\n  \d{4}-\d\d-\d\d \d\d:\d\d .*root@tulip.farm.* Re: qux\n",
+    )
+    .success();
+}
+
+#[test]
+fn archive() {
+  let test = Test::new();
+  let sendmail = write_sendmail(test.path(), "#!/bin/sh\ncat > /dev/null\n");
+  let claude = write_claude(
+    test.path(),
+    "#!/bin/sh\ncat > /dev/null\nprintf '# foo\\n'\n",
+  );
+  let dir = test.path().join("mail");
+  let dir = dir.to_str().unwrap().to_string();
+  let db = test.path().join("db.redb");
+  let db = db.to_str().unwrap().to_string();
+  let sessions = test.path().join("sessions");
+  let sessions = sessions.to_str().unwrap().to_string();
+  let out = test.path().join("archive");
+
+  let test = test
+    .args([
+      "mail",
+      "--dir",
+      &dir,
+      "--sendmail",
+      &sendmail,
+      "--db",
+      &db,
+      "--claude",
+      &claude,
+      "--session-dir",
+      &sessions,
+    ])
+    .stdin(b"From: foo@bar.com\r\nMessage-ID: <foo@bar>\r\nSubject: qux\r\n\r\n<baz>")
+    .success();
+
+  let test = test
+    .args([
+      "mail",
+      "archive",
+      "--out",
+      out.to_str().unwrap(),
+      "--dir",
+      &dir,
+      "--db",
+      &db,
+    ])
+    .success();
+
+  let index = std::fs::read_to_string(out.join("index.html")).unwrap();
+  assert!(index.contains("<a href=threads/foo@bar.html>qux</a>"));
+
+  let thread = out.join("threads/foo@bar.html");
+  let html = std::fs::read_to_string(&thread).unwrap();
+  assert!(html.contains("<pre>&lt;baz&gt;</pre>"));
+  assert!(html.contains("<h1>foo</h1>"));
+
+  let session = std::fs::read_dir(out.join("sessions"))
+    .unwrap()
+    .next()
+    .unwrap()
+    .unwrap()
+    .path();
+  let session = session.file_stem().unwrap().to_str().unwrap();
+  assert!(html.contains(&format!("<a href=../sessions/{session}.html>")));
+
+  std::fs::write(&thread, "stale").unwrap();
+  std::fs::write(out.join("threads/old.html"), "old").unwrap();
+  std::fs::write(out.join("sessions/old.html"), "old").unwrap();
+  std::fs::write(out.join("threads/notes.txt"), "notes").unwrap();
+
+  let _test = test
+    .args([
+      "mail",
+      "archive",
+      "--out",
+      out.to_str().unwrap(),
+      "--dir",
+      &dir,
+      "--db",
+      &db,
+    ])
+    .success();
+
+  assert_eq!(std::fs::read_to_string(&thread).unwrap(), "stale");
+  assert!(!out.join("threads/old.html").exists());
+  assert!(!out.join("sessions/old.html").exists());
+  assert!(out.join("threads/notes.txt").exists());
+  assert!(out.join(format!("sessions/{session}.html")).exists());
+}
+
+#[test]
+fn archive_deleted_message() {
+  let test = Test::new();
+  let sendmail = write_sendmail(test.path(), "#!/bin/sh\ncat > /dev/null\n");
+  let claude = write_claude(
+    test.path(),
+    "#!/bin/sh\ncat > /dev/null\nprintf '# foo\\n'\n",
+  );
+  let dir = test.path().join("mail");
+  let dir = dir.to_str().unwrap().to_string();
+  let db = test.path().join("db.redb");
+  let db = db.to_str().unwrap().to_string();
+  let sessions = test.path().join("sessions");
+  let sessions = sessions.to_str().unwrap().to_string();
+  let out = test.path().join("archive");
+  let out = out.to_str().unwrap().to_string();
+
+  let test = test
+    .args([
+      "mail",
+      "--dir",
+      &dir,
+      "--sendmail",
+      &sendmail,
+      "--db",
+      &db,
+      "--claude",
+      &claude,
+      "--session-dir",
+      &sessions,
+    ])
+    .stdin(b"From: foo@bar.com\r\nMessage-ID: <foo@bar>\r\nSubject: qux\r\n\r\nbaz")
+    .success();
+
+  let test = test
+    .args(["mail", "archive", "--out", &out, "--dir", &dir, "--db", &db])
+    .success();
+
+  let reply = std::fs::read_dir(test.path().join("mail/new"))
+    .unwrap()
+    .map(|entry| entry.unwrap().path())
+    .find(|path| {
+      std::fs::read_to_string(path)
+        .unwrap()
+        .contains("root@tulip.farm")
+    })
+    .unwrap();
+
+  std::fs::remove_file(reply).unwrap();
+
+  let test = test
+    .args([
+      "mail", "search", "qux", "--dir", &dir, "--db", &db, "--from", "root",
+    ])
+    .success();
+
+  let test = test
+    .args(["mail", "search", "qux", "--dir", &dir, "--db", &db])
+    .stdout_regex("[0-9a-f-]{36} foo@bar\n   foo@bar.com qux\n")
+    .success();
+
+  let test = test
+    .args(["mail", "archive", "--out", &out, "--dir", &dir, "--db", &db])
+    .success();
+
+  let html = std::fs::read_to_string(test.path().join("archive/threads/foo@bar.html")).unwrap();
+  assert!(html.contains("<pre>baz</pre>"));
+  assert!(!html.contains("<h1>foo</h1>"));
+}
+
+#[test]
+fn send() {
+  let test = Test::new();
+  let sent = test.path().join("sent");
+  let sendmail = write_sendmail(
+    test.path(),
+    &format!("#!/bin/sh\ncat > {}\n", sent.display()),
+  );
+  let claude = write_claude(test.path(), "#!/bin/sh\ncat > /dev/null\necho bar\n");
+  let dir = test.path().join("mail");
+  let db = test.path().join("db.redb");
+  let sessions = test.path().join("sessions");
+
+  let _test = test
+    .args([
+      "mail",
+      "send",
+      "--to",
+      "foo@bar.com",
+      "--subject",
+      "baz",
+      "--prompt",
+      "-",
+      "--dir",
+      dir.to_str().unwrap(),
+      "--sendmail",
+      &sendmail,
+      "--db",
+      db.to_str().unwrap(),
+      "--claude",
+      &claude,
+      "--session-dir",
+      sessions.to_str().unwrap(),
+    ])
+    .stdin("qux")
+    .success();
+
+  let email = std::fs::read_to_string(&sent).unwrap();
+  assert!(email.contains("To: <foo@bar.com>"));
+  assert!(email.contains("Subject: baz"));
+  assert!(email.contains("bar"));
+  assert!(!email.contains("In-Reply-To"));
+
+  assert_eq!(std::fs::read_dir(dir.join("new")).unwrap().count(), 1);
+}
+
+#[test]
+fn reply_to_sent_message_resumes_session() {
+  let test = Test::new();
+  let sent = test.path().join("sent");
+  let sendmail = write_sendmail(
+    test.path(),
+    &format!("#!/bin/sh\ncat > {}\n", sent.display()),
+  );
+  let log = test.path().join("log");
+  let claude = write_claude(
+    test.path(),
+    &format!(
+      "#!/bin/sh\ncat > /dev/null\necho \"$@\" >> {}\necho bar\n",
+      log.display()
+    ),
+  );
+  let dir = test.path().join("mail");
+  let db = test.path().join("db.redb");
+  let sessions = test.path().join("sessions");
+
+  let test = test
+    .args([
+      "mail",
+      "send",
+      "--to",
+      "foo@bar.com",
+      "--subject",
+      "baz",
+      "--prompt",
+      "-",
+      "--dir",
+      dir.to_str().unwrap(),
+      "--sendmail",
+      &sendmail,
+      "--db",
+      db.to_str().unwrap(),
+      "--claude",
+      &claude,
+      "--session-dir",
+      sessions.to_str().unwrap(),
+    ])
+    .stdin("qux")
+    .success();
+
+  let email = std::fs::read_to_string(&sent).unwrap();
+
+  let message_id = email
+    .lines()
+    .find_map(|line| line.strip_prefix("Message-ID: "))
+    .unwrap()
+    .to_string();
+
+  let _test = test
+    .args([
+      "mail",
+      "--dir",
+      dir.to_str().unwrap(),
+      "--sendmail",
+      &sendmail,
+      "--db",
+      db.to_str().unwrap(),
+      "--claude",
+      &claude,
+      "--session-dir",
+      sessions.to_str().unwrap(),
+    ])
+    .stdin(format!(
+      "From: foo@bar.com\r\nMessage-ID: <reply@bar.com>\r\nIn-Reply-To: {message_id}\r\n\
+       Content-Type: text/plain\r\n\r\nquux"
+    ))
+    .success();
+
+  let session_dirs = std::fs::read_dir(&sessions)
+    .unwrap()
+    .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+    .collect::<Vec<String>>();
+  assert_eq!(session_dirs.len(), 1);
+
+  let log = std::fs::read_to_string(&log).unwrap();
+  let invocations = log.lines().collect::<Vec<&str>>();
+  assert_eq!(invocations.len(), 2);
+  assert!(invocations[0].contains(&format!("--session-id {}", session_dirs[0])));
+  assert!(invocations[1].contains(&format!("--resume {}", session_dirs[0])));
+}
+
+#[test]
+fn send_draft() {
+  let test = Test::new();
+  let sendmail = write_sendmail(test.path(), "#!/bin/sh\ncat > /dev/null\n");
+  let log = test.path().join("log");
+  let claude = write_claude(
+    test.path(),
+    &format!(
+      "#!/bin/sh\ncat > /dev/null\necho \"$@\" >> {}\necho bar\n",
+      log.display()
+    ),
+  );
+  let dir = test.path().join("mail");
+  let db = test.path().join("db.redb");
+  let sessions = test.path().join("sessions");
+
+  let test = test
+    .args([
+      "mail",
+      "send",
+      "--to",
+      "foo@bar.com",
+      "--subject",
+      "baz",
+      "--prompt",
+      "-",
+      "--draft",
+      "--dir",
+      dir.to_str().unwrap(),
+      "--sendmail",
+      "/nonexistent",
+      "--db",
+      db.to_str().unwrap(),
+      "--claude",
+      &claude,
+      "--session-dir",
+      sessions.to_str().unwrap(),
+    ])
+    .stdin("qux")
+    .stdout_regex(".*/mail/drafts/new/[0-9]+.lab.tulip.farm\n")
+    .success();
+
+  assert!(!dir.join("new").exists());
+
+  let drafts = std::fs::read_dir(dir.join("drafts/new"))
+    .unwrap()
+    .map(|entry| entry.unwrap().path())
+    .collect::<Vec<std::path::PathBuf>>();
+  assert_eq!(drafts.len(), 1);
+
+  let message_id = std::fs::read_to_string(&drafts[0])
+    .unwrap()
+    .lines()
+    .find_map(|line| line.strip_prefix("Message-ID: ").map(str::to_string))
+    .unwrap();
+
+  let _test = test
+    .args([
+      "mail",
+      "--dir",
+      dir.to_str().unwrap(),
+      "--sendmail",
+      &sendmail,
+      "--db",
+      db.to_str().unwrap(),
+      "--claude",
+      &claude,
+      "--session-dir",
+      sessions.to_str().unwrap(),
+    ])
+    .stdin(format!(
+      "From: foo@bar.com\r\nMessage-ID: <reply@bar.com>\r\nIn-Reply-To: {message_id}\r\n\
+       Content-Type: text/plain\r\n\r\nquux"
+    ))
+    .success();
+
+  let session_dirs = std::fs::read_dir(&sessions)
+    .unwrap()
+    .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+    .collect::<Vec<String>>();
+  assert_eq!(session_dirs.len(), 1);
+
+  let log = std::fs::read_to_string(&log).unwrap();
+  let invocations = log.lines().collect::<Vec<&str>>();
+  assert_eq!(invocations.len(), 2);
+  assert!(invocations[1].contains(&format!("--resume {}", session_dirs[0])));
+}
+
+#[test]
+fn calendar_invite() {
+  let test = Test::new();
+  let sendmail = write_sendmail(test.path(), "#!/bin/sh\ncat > /dev/null\n");
+  let prompt = test.path().join("prompt");
+  let claude = write_claude(
+    test.path(),
+    &format!(
+      "#!/bin/sh\ncat > {}\nprintf 'See you there.\\nRSVP: ACCEPTED\\n'\n",
+      prompt.display()
+    ),
+  );
+  let dir = test.path().join("mail");
+  let db = test.path().join("db.redb");
+  let sessions = test.path().join("sessions");
+
+  let _test = test
+    .args([
+      "mail",
+      "--dir",
+      dir.to_str().unwrap(),
+      "--sendmail",
+      &sendmail,
+      "--db",
+      db.to_str().unwrap(),
+      "--claude",
+      &claude,
+      "--session-dir",
+      sessions.to_str().unwrap(),
+    ])
+    .stdin(
+      b"From: foo@bar.com\r\nMessage-ID: <foo@bar>\r\nSubject: Invitation: qux\r\n\
+        Content-Type: multipart/mixed; boundary=bound\r\n\r\n\
+        --bound\r\n\
+        Content-Type: text/plain\r\n\r\n\
+        baz\r\n\
+        --bound\r\n\
+        Content-Type: text/calendar; method=REQUEST\r\n\r\n\
+        BEGIN:VCALENDAR\r\n\
+        METHOD:REQUEST\r\n\
+        BEGIN:VEVENT\r\n\
+        UID:qux@bar.com\r\n\
+        SUMMARY:qux\r\n\
+        ORGANIZER:mailto:foo@bar.com\r\n\
+        DTSTART:20261020T150000Z\r\n\
+        END:VEVENT\r\n\
+        END:VCALENDAR\r\n\
+        --bound--\r\n",
+    )
+    .success();
+
+  let prompt = std::fs::read_to_string(prompt).unwrap();
+  assert!(prompt.contains("Title: qux"));
+  assert!(prompt.contains("Start: 2026-10-20 15:00 UTC"));
+  assert!(prompt.contains("RSVP: ACCEPTED"));
+
+  let reply = std::fs::read_dir(dir.join("new"))
+    .unwrap()
+    .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+    .find(|email| email.contains("From: \"Root\""))
+    .unwrap();
+
+  assert!(reply.contains("See you there."));
+  assert!(!reply.contains("RSVP:"));
+  assert!(reply.contains("Content-Type: text/calendar; method=\"REPLY\""));
+  assert!(reply.contains("ATTENDEE;PARTSTAT=ACCEPTED:mailto:root@tulip.farm"));
+}
@@ -0,0 +1,20 @@
+use super::*;
+
+#[test]
+fn pending_empty() {
+  let test = Test::new();
+  let db = test.path().join("db.redb");
+  let db = db.to_str().unwrap().to_string();
+  test
+    .args(["notify", "pending", "--db", &db])
+    .stdout_regex(r"\[\]\n")
+    .success();
+}
+
+#[test]
+fn notify_help() {
+  Test::new()
+    .args(["notify", "--help"])
+    .stdout_regex("(?s)Usage: lab notify.*flush.*pending.*--priority.*")
+    .success();
+}